@@ -0,0 +1,27 @@
+-- 已有的用户、站点表
+CREATE TABLE IF NOT EXISTS `user` (
+    `id`           BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `account`      VARCHAR(64)     NOT NULL,
+    `password`     VARCHAR(128)    NOT NULL,
+    `tokens`       BIGINT UNSIGNED NOT NULL DEFAULT 0,
+    `summary_key`  VARCHAR(64)     NOT NULL,
+    `openai_key`   VARCHAR(128)             DEFAULT NULL,
+    `active`       TINYINT UNSIGNED NOT NULL DEFAULT 1,
+    `created_time` DATETIME                 DEFAULT NULL,
+    `updated_time` DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_account` (`account`),
+    UNIQUE KEY `uk_summary_key` (`summary_key`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS `auth_site` (
+    `id`               BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `user_id`          BIGINT UNSIGNED NOT NULL,
+    `site_domain`      VARCHAR(255)    NOT NULL,
+    `site_summary_key` VARCHAR(64)              DEFAULT NULL,
+    `active`           TINYINT UNSIGNED NOT NULL DEFAULT 1,
+    `created_time`     DATETIME                 DEFAULT NULL,
+    `updated_time`     DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_user_id` (`user_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,12 @@
+-- tokens 变动流水
+CREATE TABLE IF NOT EXISTS `token_ledger` (
+    `id`            BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `user_id`       BIGINT UNSIGNED NOT NULL,
+    `amount`        BIGINT          NOT NULL COMMENT '正数为增加, 负数为扣减',
+    `balance_after` BIGINT UNSIGNED NOT NULL,
+    `kind`          VARCHAR(32)     NOT NULL COMMENT 'grant / debit',
+    `reason`        VARCHAR(255)             DEFAULT NULL,
+    `created_time`  DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_user_id` (`user_id`, `id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,12 @@
+-- 待发布到 kafka 的领域事件, 与业务数据在同一事务中写入
+CREATE TABLE IF NOT EXISTS `outbox_event` (
+    `id`             BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `event_type`     VARCHAR(64)     NOT NULL COMMENT 'user.created / key.rotated / ...',
+    `aggregate_id`   BIGINT UNSIGNED NOT NULL COMMENT '用户或站点 id',
+    `payload`        TEXT            NOT NULL,
+    `attempts`       INT UNSIGNED    NOT NULL DEFAULT 0,
+    `created_time`   DATETIME                 DEFAULT NULL,
+    `published_time` DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_published_time` (`published_time`, `id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,41 @@
+-- 站点 tokens 配额
+ALTER TABLE `auth_site`
+    ADD COLUMN `token_quota` BIGINT UNSIGNED DEFAULT NULL COMMENT '站点 tokens 配额, 为空不限制',
+    ADD COLUMN `tokens_used` BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT '站点已用 tokens';
+
+-- 告警规则, user_id 为空时对所有用户生效
+CREATE TABLE IF NOT EXISTS `alert_rule` (
+    `id`           BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `user_id`      BIGINT UNSIGNED          DEFAULT NULL,
+    `kind`         VARCHAR(32)     NOT NULL COMMENT 'balance_below / monthly_usage_percent / site_quota_exhausted',
+    `threshold`    BIGINT UNSIGNED NOT NULL,
+    `active`       TINYINT         NOT NULL DEFAULT 1,
+    `created_time` DATETIME                 DEFAULT NULL,
+    `updated_time` DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_user_id` (`user_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 告警状态, 用于去重: 条件持续满足时只告警一次
+CREATE TABLE IF NOT EXISTS `alert_state` (
+    `rule_id`      BIGINT UNSIGNED NOT NULL,
+    `subject`      VARCHAR(64)     NOT NULL COMMENT 'user:{id} 或 site:{id}',
+    `firing`       TINYINT         NOT NULL DEFAULT 0,
+    `updated_time` DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`rule_id`, `subject`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 告警历史
+CREATE TABLE IF NOT EXISTS `alert_history` (
+    `id`           BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `rule_id`      BIGINT UNSIGNED NOT NULL,
+    `user_id`      BIGINT UNSIGNED NOT NULL,
+    `site_id`      BIGINT UNSIGNED          DEFAULT NULL,
+    `kind`         VARCHAR(32)     NOT NULL,
+    `threshold`    BIGINT UNSIGNED NOT NULL,
+    `value`        BIGINT UNSIGNED NOT NULL,
+    `message`      VARCHAR(255)    NOT NULL,
+    `created_time` DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_user_id` (`user_id`, `id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
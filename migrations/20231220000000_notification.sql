@@ -0,0 +1,22 @@
+-- 用户接收通知的邮箱
+ALTER TABLE `user` ADD COLUMN `email` VARCHAR(128) DEFAULT NULL;
+
+-- 通知投递记录
+CREATE TABLE IF NOT EXISTS `notification` (
+    `id`           BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `user_id`      BIGINT UNSIGNED          DEFAULT NULL,
+    `channel`      VARCHAR(16)     NOT NULL COMMENT 'webhook / smtp',
+    `recipient`    VARCHAR(255)    NOT NULL,
+    `template`     VARCHAR(64)     NOT NULL,
+    `language`     VARCHAR(8)      NOT NULL,
+    `subject`      VARCHAR(255)    NOT NULL,
+    `body`         TEXT            NOT NULL,
+    `status`       VARCHAR(16)     NOT NULL COMMENT 'pending / sent / failed',
+    `attempts`     INT UNSIGNED    NOT NULL DEFAULT 0,
+    `last_error`   VARCHAR(512)             DEFAULT NULL,
+    `created_time` DATETIME                 DEFAULT NULL,
+    `sent_time`    DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `idx_user_id` (`user_id`, `id`),
+    KEY `idx_status` (`status`, `id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
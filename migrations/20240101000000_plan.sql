@@ -0,0 +1,19 @@
+-- 订阅套餐
+CREATE TABLE IF NOT EXISTS `plan` (
+    `id`                          BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `name`                        VARCHAR(64)     NOT NULL,
+    `monthly_tokens`              BIGINT UNSIGNED NOT NULL COMMENT '每月发放的 tokens',
+    `price_cents`                 BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT '月费, 单位分',
+    `rate_limit_capacity`         BIGINT UNSIGNED          DEFAULT NULL COMMENT '为空时使用默认限流',
+    `rate_limit_refill_per_second` DOUBLE                  DEFAULT NULL,
+    `active`                      TINYINT         NOT NULL DEFAULT 1,
+    `created_time`                DATETIME                 DEFAULT NULL,
+    `updated_time`                DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_name` (`name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 用户套餐及已发放额度的月份
+ALTER TABLE `user`
+    ADD COLUMN `plan_id`           BIGINT UNSIGNED DEFAULT NULL,
+    ADD COLUMN `plan_period_begin` DATETIME        DEFAULT NULL COMMENT '最近一次发放额度的月初';
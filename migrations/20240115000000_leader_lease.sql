@@ -0,0 +1,17 @@
+-- 多实例部署时的 leader 租约, 每个 name 同一时间只有一个实例持有
+CREATE TABLE IF NOT EXISTS `leader_lease` (
+    `name`         VARCHAR(64)  NOT NULL COMMENT 'scheduler / usage_consumer / outbox_publisher',
+    `holder`       VARCHAR(128) NOT NULL COMMENT '持有租约的实例 id',
+    `expires_at`   DATETIME(3)  NOT NULL,
+    `updated_time` DATETIME(3)  NOT NULL,
+    PRIMARY KEY (`name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 定时任务的暂停状态和手动触发请求, 由持有 scheduler 租约的实例读取执行
+CREATE TABLE IF NOT EXISTS `job_control` (
+    `name`              VARCHAR(64) NOT NULL,
+    `paused`            TINYINT     NOT NULL DEFAULT 0,
+    `trigger_requested` TINYINT     NOT NULL DEFAULT 0,
+    `updated_time`      DATETIME             DEFAULT NULL,
+    PRIMARY KEY (`name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,8 @@
+-- summary_key 及 site_summary_key 的过期时间, 为空表示永不过期
+ALTER TABLE `user`
+    ADD COLUMN `summary_key_expires_at` DATETIME DEFAULT NULL,
+    ADD KEY `idx_summary_key_expires_at` (`summary_key_expires_at`);
+
+ALTER TABLE `auth_site`
+    ADD COLUMN `site_summary_key_expires_at` DATETIME DEFAULT NULL,
+    ADD KEY `idx_site_summary_key_expires_at` (`site_summary_key_expires_at`);
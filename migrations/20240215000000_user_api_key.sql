@@ -0,0 +1,22 @@
+-- 用户的多个命名 key, 可单独过期和吊销
+CREATE TABLE IF NOT EXISTS `user_api_key` (
+    `id`             BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `user_id`        BIGINT UNSIGNED NOT NULL,
+    `name`           VARCHAR(64)     NOT NULL,
+    `api_key`        VARCHAR(64)     NOT NULL,
+    `expires_at`     DATETIME                 DEFAULT NULL COMMENT '为空表示永不过期',
+    `last_used_time` DATETIME                 DEFAULT NULL,
+    `revoked_time`   DATETIME                 DEFAULT NULL,
+    `created_time`   DATETIME                 DEFAULT NULL,
+    `updated_time`   DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_api_key` (`api_key`),
+    KEY `idx_user_id` (`user_id`),
+    KEY `idx_expires_at` (`expires_at`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 原 user.summary_key 迁移为每个用户的第一个 key
+INSERT IGNORE INTO `user_api_key` (`user_id`, `name`, `api_key`, `expires_at`, `created_time`, `updated_time`)
+SELECT `id`, 'default', `summary_key`, `summary_key_expires_at`, `created_time`, NOW()
+FROM `user`
+WHERE `summary_key` IS NOT NULL AND `summary_key` <> '';
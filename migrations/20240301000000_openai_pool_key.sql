@@ -0,0 +1,22 @@
+-- 共享 OpenAI key 池, 服务没有自己 openai_key 的用户
+CREATE TABLE IF NOT EXISTS `openai_pool_key` (
+    `id`                  BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `name`                VARCHAR(64)     NOT NULL,
+    `api_key`             VARCHAR(128)    NOT NULL,
+    `weight`              INT UNSIGNED    NOT NULL DEFAULT 1 COMMENT '加权轮询的权重',
+    `monthly_token_limit` BIGINT UNSIGNED          DEFAULT NULL COMMENT '每月最多服务的 tokens, 为空表示不限',
+    `tokens_used`         BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT 'period_begin 所在月已服务的 tokens',
+    `period_begin`        DATETIME                 DEFAULT NULL,
+    `healthy`             TINYINT         NOT NULL DEFAULT 1,
+    `health_reason`       VARCHAR(255)             DEFAULT NULL,
+    `active`              TINYINT         NOT NULL DEFAULT 1,
+    `created_time`        DATETIME                 DEFAULT NULL,
+    `updated_time`        DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_name` (`name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 用量扣减记录服务该次用量的共享 key
+ALTER TABLE `token_ledger`
+    ADD COLUMN `pool_key_id` BIGINT UNSIGNED DEFAULT NULL COMMENT '服务该次用量的共享 OpenAI key',
+    ADD KEY `idx_pool_key_id` (`pool_key_id`, `id`);
@@ -0,0 +1,11 @@
+-- 模型价格, 只追加不修改: 每行从 effective_from 起生效, 直到同一模型下一行生效
+CREATE TABLE IF NOT EXISTS `model_price` (
+    `id`                    BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `model`                 VARCHAR(64)     NOT NULL,
+    `prompt_multiplier`     DOUBLE          NOT NULL COMMENT '每个 prompt token 折算的计费 tokens',
+    `completion_multiplier` DOUBLE          NOT NULL COMMENT '每个 completion token 折算的计费 tokens',
+    `effective_from`        DATETIME        NOT NULL,
+    `created_time`          DATETIME                 DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_model_effective_from` (`model`, `effective_from`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
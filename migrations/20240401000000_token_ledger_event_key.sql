@@ -0,0 +1,4 @@
+-- 用量扣减记录消息标识, 重复消费同一条消息时不会重复扣减
+ALTER TABLE `token_ledger`
+    ADD COLUMN `event_key` VARCHAR(128) DEFAULT NULL COMMENT '用量消息的唯一标识 topic:partition:offset',
+    ADD UNIQUE KEY `uk_event_key` (`event_key`);
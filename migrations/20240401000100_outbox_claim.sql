@@ -0,0 +1,3 @@
+-- 发布前先在短事务中认领事件, 发送期间不持有行锁
+ALTER TABLE `outbox_event`
+    ADD COLUMN `claimed_until` DATETIME DEFAULT NULL COMMENT '认领到期时间, 到期未发布的事件可被重新认领';
@@ -0,0 +1,5 @@
+-- 站点已用 tokens 按月计, 跨月时从 0 重新计数
+ALTER TABLE `auth_site`
+    ADD COLUMN `period_begin` DATETIME DEFAULT NULL COMMENT 'tokens_used 所在月的月初';
+
+UPDATE `auth_site` SET `period_begin` = DATE_FORMAT(NOW(), '%Y-%m-01 00:00:00');
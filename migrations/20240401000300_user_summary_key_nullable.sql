@@ -0,0 +1,2 @@
+-- 吊销 default key 时清空 user.summary_key; 唯一索引允许多个 NULL
+ALTER TABLE `user` MODIFY COLUMN `summary_key` VARCHAR(64) DEFAULT NULL;
@@ -0,0 +1,6 @@
+-- 定时任务最近一次执行结果, 由执行任务的实例写入, 各实例的任务列表从这里读取
+ALTER TABLE `job_control`
+    ADD COLUMN `last_run_time`        DATETIME(3) DEFAULT NULL COMMENT 'UTC',
+    ADD COLUMN `last_duration_millis` BIGINT      DEFAULT NULL,
+    ADD COLUMN `last_success`         TINYINT     DEFAULT NULL,
+    ADD COLUMN `last_message`         TEXT        DEFAULT NULL COMMENT '成功时为执行摘要, 失败时为错误信息';
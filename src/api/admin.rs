@@ -1,17 +1,1007 @@
+use std::collections::HashMap;
+
 use actix_web::{
-    get,
+    get, post, put,
     web::{self},
-    HttpResponse, Scope,
+    HttpRequest, HttpResponse, Scope,
 };
+use log::warn;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use utoipa::IntoParams;
 
-use crate::api::success;
+use crate::{
+    api::{
+        success, success_page, JsonError, JsonSuccessAddAuthSite, JsonSuccessAlertHistoryPage,
+        JsonSuccessAlertRules, JsonSuccessApiKey, JsonSuccessApiKeyPage,
+        JsonSuccessAssignPlanResult, JsonSuccessAuthSitePage, JsonSuccessDailyUsage,
+        JsonSuccessExpiringKeys, JsonSuccessGrantResult, JsonSuccessImportReport, JsonSuccessJob,
+        JsonSuccessJobs, JsonSuccessKeyExpiryResult, JsonSuccessLeases, JsonSuccessModelPrices,
+        JsonSuccessNotificationPage, JsonSuccessNumber, JsonSuccessPlans, JsonSuccessPoolKeys,
+        JsonSuccessRestoreReport, JsonSuccessUser, JsonSuccessUserPage, upload_config,
+    },
+    client::{
+        entity::{
+            alert::{AlertHistory, AlertRule},
+            auth_site::AuthSite,
+            key_expiry,
+            model_price::ModelPrice,
+            notification::Notification,
+            openai_pool_key::OpenaiPoolKey,
+            plan::Plan,
+            token_ledger::TokenLedger,
+            user::User,
+            user_api_key::UserApiKey,
+        },
+        model::{
+            alert_model::AddAlertRule,
+            api_key_model::AddApiKey,
+            auth_site_model::{AddAuthSite, SiteQuota},
+            key_expiry_model::{KeyExpiry, KeyExpiryResult},
+            model_price_model::AddModelPrice,
+            plan_model::{AddPlan, AssignPlan, AssignPlanResult},
+            pool_key_model::{AddPoolKey, PoolKeyHealth, PoolKeyView, UpdatePoolKey},
+            user_model::{AddUser, GrantResult, GrantTokens, UserView},
+        },
+        service::{
+            alert_service, backup_service,
+            import_service::{self, ImportFormat, ImportOptions},
+            notify_service, plan_service, pool_service,
+        },
+    },
+    error::{Error, Result},
+    leader,
+    middleware::cors,
+    notify::template,
+    scheduler, setting,
+    utils::{page::PageQuery, uuid, validate},
+};
 
 ///请求路由
 pub fn routes() -> Scope {
-    web::scope("/admin").service(index)
+    web::scope("/admin").service(
+        web::scope("")
+            .wrap(cors::admin())
+            .service(index)
+            .service(list_users)
+            .service(add_user)
+            .service(rotate_summary_key)
+            .service(list_user_sites)
+            .service(deactivate_user)
+            .service(grant_tokens)
+            .service(user_usage)
+            .service(add_user_site)
+            .service(deactivate_site)
+            .service(set_site_quota)
+            .service(list_alert_rules)
+            .service(add_alert_rule)
+            .service(deactivate_alert_rule)
+            .service(list_user_alerts)
+            .service(list_user_notifications)
+            .service(list_plans)
+            .service(add_plan)
+            .service(assign_user_plan)
+            .service(list_pool_keys)
+            .service(add_pool_key)
+            .service(update_pool_key)
+            .service(set_pool_key_health)
+            .service(list_model_prices)
+            .service(add_model_price)
+            .service(model_price_history)
+            .service(list_jobs)
+            .service(trigger_job)
+            .service(pause_job)
+            .service(resume_job)
+            .service(list_leases)
+            .service(set_user_key_expiry)
+            .service(set_site_key_expiry)
+            .service(list_expiring_keys)
+            .service(list_user_api_keys)
+            .service(add_user_api_key)
+            .service(revoke_api_key)
+            .service(set_api_key_expiry)
+            .service(web::resource("/import").app_data(upload_config()).route(web::post().to(import_users)))
+            .service(backup)
+            .service(web::resource("/restore").app_data(upload_config()).route(web::post().to(restore))),
+    )
 }
 
+#[utoipa::path(
+    get,
+    path = "/admin",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessNumber))
+)]
 #[get("")]
 pub async fn index() -> HttpResponse {
     success(Some(1))
 }
+
+/// 用户列表
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    params(
+        ("page" = Option<u64>, Query, description = "页码, 从 1 开始"),
+        ("size" = Option<u64>, Query, description = "每页条数, 最大 100"),
+        ("cursor" = Option<u64>, Query, description = "游标, 传入时按 id 翻页"),
+        ("sort" = Option<String>, Query, description = "排序字段: id, account, tokens, created_time, updated_time"),
+        ("order" = Option<String>, Query, description = "asc 或 desc"),
+        ("account" = Option<String>, Query, description = "按账号过滤"),
+        ("active" = Option<u64>, Query, description = "按状态过滤"),
+        ("summary_key" = Option<String>, Query, description = "按 summary_key 过滤")
+    ),
+    responses(
+        (status = 200, body = JsonSuccessUserPage),
+        (status = 400, body = JsonError)
+    )
+)]
+#[get("/users")]
+pub async fn list_users(params: web::Query<HashMap<String, String>>) -> Result<HttpResponse> {
+    let query = PageQuery::from_query(&params)?;
+    let page = User::page(&query).await?;
+    Ok(success_page(page.map(UserView::from)))
+}
+
+/// 新增用户, 已配置通知渠道时在后台发送欢迎通知
+#[utoipa::path(
+    post,
+    path = "/admin/users",
+    tag = "admin",
+    request_body = AddUser,
+    responses(
+        (status = 200, body = JsonSuccessUser),
+        (status = 400, body = JsonError)
+    )
+)]
+#[post("/users")]
+pub async fn add_user(body: web::Json<AddUser>) -> Result<HttpResponse> {
+    let mut add = body.into_inner();
+    add.id = None;
+    let account = add.account.clone().unwrap_or_default();
+    User::add_user(add).await?;
+    let user = User::find_by_account(&account)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("user {}", account)))?;
+    notify_service::spawn_notify_account(user.clone(), template::WELCOME);
+    Ok(success(Some(UserView::from(user))))
+}
+
+/// 重新生成用户的 summary_key, 旧 key 立即失效, 已配置通知渠道时在后台通知用户
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/summary_key/rotate",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    responses(
+        (status = 200, body = JsonSuccessUser),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/users/{user_id}/summary_key/rotate")]
+pub async fn rotate_summary_key(user_id: web::Path<u64>) -> Result<HttpResponse> {
+    let user_id = user_id.into_inner();
+    User::rotate_summary_key(user_id).await?;
+    let user = User::find_by_id(user_id)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("user {}", user_id)))?;
+    notify_service::spawn_notify_account(user.clone(), template::KEY_ROTATED);
+    Ok(success(Some(UserView::from(user))))
+}
+
+/// 用户的站点列表
+#[utoipa::path(
+    get,
+    path = "/admin/users/{user_id}/sites",
+    tag = "admin",
+    params(
+        ("user_id" = u64, Path, description = "用户 id"),
+        ("page" = Option<u64>, Query, description = "页码, 从 1 开始"),
+        ("size" = Option<u64>, Query, description = "每页条数, 最大 100"),
+        ("cursor" = Option<u64>, Query, description = "游标, 传入时按 id 翻页"),
+        ("sort" = Option<String>, Query, description = "排序字段: id, site_domain, created_time, updated_time"),
+        ("order" = Option<String>, Query, description = "asc 或 desc"),
+        ("active" = Option<u64>, Query, description = "按状态过滤"),
+        ("site_domain" = Option<String>, Query, description = "按域名过滤")
+    ),
+    responses(
+        (status = 200, body = JsonSuccessAuthSitePage),
+        (status = 400, body = JsonError)
+    )
+)]
+#[get("/users/{user_id}/sites")]
+pub async fn list_user_sites(
+    user_id: web::Path<u64>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    let query = PageQuery::from_query(&params)?;
+    let page = AuthSite::page_by_user_id(user_id.into_inner(), &query).await?;
+    Ok(success_page(page))
+}
+
+/// 停用用户
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/deactivate",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/users/{user_id}/deactivate")]
+pub async fn deactivate_user(user_id: web::Path<u64>) -> Result<HttpResponse> {
+    User::deactivate(user_id.into_inner()).await?;
+    Ok(success(Some(1)))
+}
+
+/// 给用户发放 tokens
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/grants",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    request_body = GrantTokens,
+    responses(
+        (status = 200, body = JsonSuccessGrantResult),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/users/{user_id}/grants")]
+pub async fn grant_tokens(user_id: web::Path<u64>, body: web::Json<GrantTokens>) -> Result<HttpResponse> {
+    let user_id = user_id.into_inner();
+    validate::validate(&*body)?;
+    let balance = User::grant_tokens(user_id, body.tokens, body.reason.as_deref()).await?;
+    // 发放后重新检查, 解除已恢复的告警
+    if let Err(e) = alert_service::evaluate(user_id).await {
+        warn!("alert evaluate error, user: {}, error: {}", user_id, e);
+    }
+    Ok(success(Some(GrantResult { user_id, balance })))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageParams {
+    /// 统计最近多少天, 默认 30, 最大 366
+    pub days: Option<u32>,
+}
+
+/// 用户每日用量
+#[utoipa::path(
+    get,
+    path = "/admin/users/{user_id}/usage",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id"), UsageParams),
+    responses((status = 200, body = JsonSuccessDailyUsage))
+)]
+#[get("/users/{user_id}/usage")]
+pub async fn user_usage(user_id: web::Path<u64>, params: web::Query<UsageParams>) -> Result<HttpResponse> {
+    let days = params.days.unwrap_or(30).clamp(1, 366);
+    let usage = TokenLedger::daily_usage(user_id.into_inner(), days).await?;
+    Ok(success(Some(usage)))
+}
+
+/// 给用户新增站点, 未传 site_summary_key 时自动生成
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/sites",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    request_body = AddAuthSite,
+    responses(
+        (status = 200, body = JsonSuccessAddAuthSite),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/users/{user_id}/sites")]
+pub async fn add_user_site(user_id: web::Path<u64>, body: web::Json<AddAuthSite>) -> Result<HttpResponse> {
+    let user_id = user_id.into_inner();
+    if User::find_by_id(user_id).await?.is_none() {
+        return Err(Error::NotFound(format!("user {}", user_id)));
+    }
+    let mut add = body.into_inner();
+    add.id = None;
+    add.user_id = Some(user_id);
+    if add.site_summary_key.is_none() {
+        add.site_summary_key = Some(uuid::new_summary_key());
+    }
+    AuthSite::add_auth_site(add.clone()).await?;
+    Ok(success(Some(add)))
+}
+
+/// 停用站点
+#[utoipa::path(
+    post,
+    path = "/admin/sites/{site_id}/deactivate",
+    tag = "admin",
+    params(("site_id" = u64, Path, description = "站点 id")),
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/sites/{site_id}/deactivate")]
+pub async fn deactivate_site(site_id: web::Path<u64>) -> Result<HttpResponse> {
+    AuthSite::deactivate(site_id.into_inner()).await?;
+    Ok(success(Some(1)))
+}
+
+/// 设置站点配额
+#[utoipa::path(
+    put,
+    path = "/admin/sites/{site_id}/quota",
+    tag = "admin",
+    params(("site_id" = u64, Path, description = "站点 id")),
+    request_body = SiteQuota,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/sites/{site_id}/quota")]
+pub async fn set_site_quota(site_id: web::Path<u64>, body: web::Json<SiteQuota>) -> Result<HttpResponse> {
+    AuthSite::set_quota(site_id.into_inner(), body.token_quota).await?;
+    Ok(success(Some(1)))
+}
+
+/// 告警规则列表
+#[utoipa::path(
+    get,
+    path = "/admin/alert-rules",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessAlertRules))
+)]
+#[get("/alert-rules")]
+pub async fn list_alert_rules() -> Result<HttpResponse> {
+    let rules = AlertRule::all().await?;
+    Ok(success(Some(rules)))
+}
+
+/// 新增告警规则, 返回规则 id
+#[utoipa::path(
+    post,
+    path = "/admin/alert-rules",
+    tag = "admin",
+    request_body = AddAlertRule,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 400, body = JsonError)
+    )
+)]
+#[post("/alert-rules")]
+pub async fn add_alert_rule(body: web::Json<AddAlertRule>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    let rule: AlertRule = body.into_inner().into();
+    let id = AlertRule::add(&rule).await?;
+    Ok(success(Some(id)))
+}
+
+/// 停用告警规则
+#[utoipa::path(
+    post,
+    path = "/admin/alert-rules/{rule_id}/deactivate",
+    tag = "admin",
+    params(("rule_id" = u64, Path, description = "规则 id")),
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/alert-rules/{rule_id}/deactivate")]
+pub async fn deactivate_alert_rule(rule_id: web::Path<u64>) -> Result<HttpResponse> {
+    AlertRule::deactivate(rule_id.into_inner()).await?;
+    Ok(success(Some(1)))
+}
+
+/// 用户的告警历史
+#[utoipa::path(
+    get,
+    path = "/admin/users/{user_id}/alerts",
+    tag = "admin",
+    params(
+        ("user_id" = u64, Path, description = "用户 id"),
+        ("page" = Option<u64>, Query, description = "页码, 从 1 开始"),
+        ("size" = Option<u64>, Query, description = "每页条数, 最大 100"),
+        ("cursor" = Option<u64>, Query, description = "游标, 传入时按 id 翻页"),
+        ("kind" = Option<String>, Query, description = "按规则类型过滤")
+    ),
+    responses(
+        (status = 200, body = JsonSuccessAlertHistoryPage),
+        (status = 400, body = JsonError)
+    )
+)]
+#[get("/users/{user_id}/alerts")]
+pub async fn list_user_alerts(
+    user_id: web::Path<u64>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    let query = PageQuery::from_query(&params)?;
+    let page = AlertHistory::page_by_user_id(user_id.into_inner(), &query).await?;
+    Ok(success_page(page))
+}
+
+/// 用户的通知投递记录
+#[utoipa::path(
+    get,
+    path = "/admin/users/{user_id}/notifications",
+    tag = "admin",
+    params(
+        ("user_id" = u64, Path, description = "用户 id"),
+        ("page" = Option<u64>, Query, description = "页码, 从 1 开始"),
+        ("size" = Option<u64>, Query, description = "每页条数, 最大 100"),
+        ("cursor" = Option<u64>, Query, description = "游标, 传入时按 id 翻页"),
+        ("channel" = Option<String>, Query, description = "webhook 或 smtp"),
+        ("status" = Option<String>, Query, description = "pending, sent 或 failed")
+    ),
+    responses(
+        (status = 200, body = JsonSuccessNotificationPage),
+        (status = 400, body = JsonError)
+    )
+)]
+#[get("/users/{user_id}/notifications")]
+pub async fn list_user_notifications(
+    user_id: web::Path<u64>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    let query = PageQuery::from_query(&params)?;
+    let page = Notification::page_by_user_id(user_id.into_inner(), &query).await?;
+    Ok(success_page(page))
+}
+
+/// 套餐列表
+#[utoipa::path(
+    get,
+    path = "/admin/plans",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessPlans))
+)]
+#[get("/plans")]
+pub async fn list_plans() -> Result<HttpResponse> {
+    let plans = Plan::all().await?;
+    Ok(success(Some(plans)))
+}
+
+/// 新增套餐, 返回套餐 id
+#[utoipa::path(
+    post,
+    path = "/admin/plans",
+    tag = "admin",
+    request_body = AddPlan,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 400, body = JsonError),
+        (status = 409, body = JsonError)
+    )
+)]
+#[post("/plans")]
+pub async fn add_plan(body: web::Json<AddPlan>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    if body.rate_limit_capacity.is_some() != body.rate_limit_refill_per_second.is_some() {
+        return Err(Error::BizError(
+            "rate_limit_capacity 和 rate_limit_refill_per_second 需同时设置".to_string(),
+        ));
+    }
+    let plan: Plan = body.into_inner().into();
+    let id = Plan::add(&plan).await?;
+    Ok(success(Some(id)))
+}
+
+/// 更换用户套餐, 按本月剩余时间折算额度
+#[utoipa::path(
+    put,
+    path = "/admin/users/{user_id}/plan",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    request_body = AssignPlan,
+    responses(
+        (status = 200, body = JsonSuccessAssignPlanResult),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/users/{user_id}/plan")]
+pub async fn assign_user_plan(user_id: web::Path<u64>, body: web::Json<AssignPlan>) -> Result<HttpResponse> {
+    let user_id = user_id.into_inner();
+    let (adjustment, balance) = plan_service::change_plan(user_id, body.plan_id).await?;
+    if let Err(e) = alert_service::evaluate(user_id).await {
+        warn!("alert evaluate error, user: {}, error: {}", user_id, e);
+    }
+    Ok(success(Some(AssignPlanResult { user_id, adjustment, balance })))
+}
+
+/// 共享 OpenAI key 变更后立即刷新本实例的可选 key, 其他实例按刷新间隔生效
+async fn refresh_pool() {
+    if let Err(e) = pool_service::refresh().await {
+        warn!("openai pool keys refresh error: {}", e);
+    }
+}
+
+/// 共享 OpenAI key 列表, api_key 脱敏
+#[utoipa::path(
+    get,
+    path = "/admin/openai-keys",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessPoolKeys))
+)]
+#[get("/openai-keys")]
+pub async fn list_pool_keys() -> Result<HttpResponse> {
+    let keys: Vec<PoolKeyView> = OpenaiPoolKey::all().await?.into_iter().map(PoolKeyView::from).collect();
+    Ok(success(Some(keys)))
+}
+
+/// 新增共享 OpenAI key, 返回 id
+#[utoipa::path(
+    post,
+    path = "/admin/openai-keys",
+    tag = "admin",
+    request_body = AddPoolKey,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 400, body = JsonError),
+        (status = 409, body = JsonError)
+    )
+)]
+#[post("/openai-keys")]
+pub async fn add_pool_key(body: web::Json<AddPoolKey>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    let key: OpenaiPoolKey = body.into_inner().into();
+    let id = OpenaiPoolKey::add(&key).await?;
+    refresh_pool().await;
+    Ok(success(Some(id)))
+}
+
+/// 修改共享 OpenAI key 的权重、月度上限及状态
+#[utoipa::path(
+    put,
+    path = "/admin/openai-keys/{key_id}",
+    tag = "admin",
+    params(("key_id" = u64, Path, description = "共享 key id")),
+    request_body = UpdatePoolKey,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/openai-keys/{key_id}")]
+pub async fn update_pool_key(key_id: web::Path<u64>, body: web::Json<UpdatePoolKey>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    OpenaiPoolKey::update_settings(key_id.into_inner(), body.weight, body.monthly_token_limit, body.active).await?;
+    refresh_pool().await;
+    Ok(success(Some(1)))
+}
+
+/// 标记共享 OpenAI key 的健康状态, 不健康的 key 不再被选中
+#[utoipa::path(
+    put,
+    path = "/admin/openai-keys/{key_id}/health",
+    tag = "admin",
+    params(("key_id" = u64, Path, description = "共享 key id")),
+    request_body = PoolKeyHealth,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/openai-keys/{key_id}/health")]
+pub async fn set_pool_key_health(key_id: web::Path<u64>, body: web::Json<PoolKeyHealth>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    OpenaiPoolKey::set_health(key_id.into_inner(), body.healthy, body.reason.as_deref()).await?;
+    refresh_pool().await;
+    Ok(success(Some(1)))
+}
+
+/// 各模型当前生效的价格
+#[utoipa::path(
+    get,
+    path = "/admin/model-prices",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessModelPrices))
+)]
+#[get("/model-prices")]
+pub async fn list_model_prices() -> Result<HttpResponse> {
+    let prices = ModelPrice::current().await?;
+    Ok(success(Some(prices)))
+}
+
+/// 新增模型价格, 到 effective_from 时替换该模型原有价格, 返回 id
+#[utoipa::path(
+    post,
+    path = "/admin/model-prices",
+    tag = "admin",
+    request_body = AddModelPrice,
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 400, description = "参数错误, 包括 effective_from 早于当前时间", body = JsonError),
+        (status = 409, description = "该时间已有价格", body = JsonError)
+    )
+)]
+#[post("/model-prices")]
+pub async fn add_model_price(body: web::Json<AddModelPrice>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    let id = ModelPrice::add(
+        body.model.as_deref().unwrap_or_default(),
+        body.prompt_multiplier.unwrap_or_default(),
+        body.completion_multiplier.unwrap_or_default(),
+        body.effective_from.as_deref(),
+    )
+    .await?;
+    Ok(success(Some(id)))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PriceHistoryParams {
+    /// 模型名称, 为空时返回所有模型
+    pub model: Option<String>,
+}
+
+/// 模型价格历史, 包含尚未生效的价格
+#[utoipa::path(
+    get,
+    path = "/admin/model-prices/history",
+    tag = "admin",
+    params(PriceHistoryParams),
+    responses((status = 200, body = JsonSuccessModelPrices))
+)]
+#[get("/model-prices/history")]
+pub async fn model_price_history(params: web::Query<PriceHistoryParams>) -> Result<HttpResponse> {
+    let prices = ModelPrice::history(params.model.as_deref()).await?;
+    Ok(success(Some(prices)))
+}
+
+/// 定时任务列表, 包含上次执行结果和下次执行时间
+#[utoipa::path(
+    get,
+    path = "/admin/jobs",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessJobs))
+)]
+#[get("/jobs")]
+pub async fn list_jobs() -> Result<HttpResponse> {
+    Ok(success(Some(scheduler::list().await?)))
+}
+
+/// 立即执行一次定时任务
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{name}/trigger",
+    tag = "admin",
+    params(("name" = String, Path, description = "任务名")),
+    responses(
+        (status = 200, body = JsonSuccessJob),
+        (status = 404, body = JsonError),
+        (status = 409, body = JsonError)
+    )
+)]
+#[post("/jobs/{name}/trigger")]
+pub async fn trigger_job(name: web::Path<String>) -> Result<HttpResponse> {
+    let job = scheduler::trigger(&name).await?;
+    Ok(success(Some(job)))
+}
+
+/// 暂停定时任务
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{name}/pause",
+    tag = "admin",
+    params(("name" = String, Path, description = "任务名")),
+    responses(
+        (status = 200, body = JsonSuccessJob),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/jobs/{name}/pause")]
+pub async fn pause_job(name: web::Path<String>) -> Result<HttpResponse> {
+    let job = scheduler::set_paused(&name, true).await?;
+    Ok(success(Some(job)))
+}
+
+/// 恢复定时任务
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{name}/resume",
+    tag = "admin",
+    params(("name" = String, Path, description = "任务名")),
+    responses(
+        (status = 200, body = JsonSuccessJob),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/jobs/{name}/resume")]
+pub async fn resume_job(name: web::Path<String>) -> Result<HttpResponse> {
+    let job = scheduler::set_paused(&name, false).await?;
+    Ok(success(Some(job)))
+}
+
+/// leader 租约, 查看各后台任务由哪个实例执行
+#[utoipa::path(
+    get,
+    path = "/admin/leases",
+    tag = "admin",
+    responses((status = 200, body = JsonSuccessLeases))
+)]
+#[get("/leases")]
+pub async fn list_leases() -> Result<HttpResponse> {
+    let leases = leader::leases().await?;
+    Ok(success(Some(leases)))
+}
+
+/// 修改用户 summary_key 的过期时间
+#[utoipa::path(
+    put,
+    path = "/admin/users/{user_id}/key-expiry",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    request_body = KeyExpiry,
+    responses(
+        (status = 200, body = JsonSuccessKeyExpiryResult),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/users/{user_id}/key-expiry")]
+pub async fn set_user_key_expiry(user_id: web::Path<u64>, body: web::Json<KeyExpiry>) -> Result<HttpResponse> {
+    let user_id = user_id.into_inner();
+    validate::validate(&*body)?;
+    let expires_at = User::set_key_expiry(user_id, &body.change()?).await?;
+    Ok(success(Some(KeyExpiryResult { id: user_id, expires_at })))
+}
+
+/// 修改站点 site_summary_key 的过期时间
+#[utoipa::path(
+    put,
+    path = "/admin/sites/{site_id}/key-expiry",
+    tag = "admin",
+    params(("site_id" = u64, Path, description = "站点 id")),
+    request_body = KeyExpiry,
+    responses(
+        (status = 200, body = JsonSuccessKeyExpiryResult),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/sites/{site_id}/key-expiry")]
+pub async fn set_site_key_expiry(site_id: web::Path<u64>, body: web::Json<KeyExpiry>) -> Result<HttpResponse> {
+    let site_id = site_id.into_inner();
+    validate::validate(&*body)?;
+    let expires_at = AuthSite::set_key_expiry(site_id, &body.change()?).await?;
+    Ok(success(Some(KeyExpiryResult { id: site_id, expires_at })))
+}
+
+/// 用户的 api key 列表
+#[utoipa::path(
+    get,
+    path = "/admin/users/{user_id}/api-keys",
+    tag = "admin",
+    params(
+        ("user_id" = u64, Path, description = "用户 id"),
+        ("page" = Option<u64>, Query, description = "页码, 从 1 开始"),
+        ("size" = Option<u64>, Query, description = "每页条数, 最大 100"),
+        ("cursor" = Option<u64>, Query, description = "游标, 传入时按 id 翻页"),
+        ("sort" = Option<String>, Query, description = "排序字段: id, name, created_time, last_used_time"),
+        ("order" = Option<String>, Query, description = "asc 或 desc"),
+        ("name" = Option<String>, Query, description = "按名称过滤")
+    ),
+    responses(
+        (status = 200, body = JsonSuccessApiKeyPage),
+        (status = 400, body = JsonError)
+    )
+)]
+#[get("/users/{user_id}/api-keys")]
+pub async fn list_user_api_keys(
+    user_id: web::Path<u64>,
+    params: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    let query = PageQuery::from_query(&params)?;
+    let page = UserApiKey::page_by_user_id(user_id.into_inner(), &query).await?;
+    Ok(success_page(page))
+}
+
+/// 给用户新建 api key
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/api-keys",
+    tag = "admin",
+    params(("user_id" = u64, Path, description = "用户 id")),
+    request_body = AddApiKey,
+    responses(
+        (status = 200, body = JsonSuccessApiKey),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError),
+        (status = 409, description = "同名 key 已存在", body = JsonError)
+    )
+)]
+#[post("/users/{user_id}/api-keys")]
+pub async fn add_user_api_key(user_id: web::Path<u64>, body: web::Json<AddApiKey>) -> Result<HttpResponse> {
+    validate::validate(&*body)?;
+    let body = body.into_inner();
+    let name = body.name.unwrap_or_default();
+    let key = UserApiKey::add(user_id.into_inner(), &name, body.expires_at.as_deref()).await?;
+    Ok(success(Some(key)))
+}
+
+/// 吊销 api key, 立即失效
+#[utoipa::path(
+    post,
+    path = "/admin/api-keys/{key_id}/revoke",
+    tag = "admin",
+    params(("key_id" = u64, Path, description = "api key id")),
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 404, body = JsonError)
+    )
+)]
+#[post("/api-keys/{key_id}/revoke")]
+pub async fn revoke_api_key(key_id: web::Path<u64>) -> Result<HttpResponse> {
+    UserApiKey::revoke(key_id.into_inner()).await?;
+    Ok(success(Some(1)))
+}
+
+/// 修改 api key 的过期时间
+#[utoipa::path(
+    put,
+    path = "/admin/api-keys/{key_id}/key-expiry",
+    tag = "admin",
+    params(("key_id" = u64, Path, description = "api key id")),
+    request_body = KeyExpiry,
+    responses(
+        (status = 200, body = JsonSuccessKeyExpiryResult),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    )
+)]
+#[put("/api-keys/{key_id}/key-expiry")]
+pub async fn set_api_key_expiry(key_id: web::Path<u64>, body: web::Json<KeyExpiry>) -> Result<HttpResponse> {
+    let key_id = key_id.into_inner();
+    validate::validate(&*body)?;
+    let expires_at = UserApiKey::set_key_expiry(key_id, &body.change()?).await?;
+    Ok(success(Some(KeyExpiryResult { id: key_id, expires_at })))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExpiringParams {
+    /// 多少天内过期, 默认取配置 key_expiry.report_days, 最大 366
+    pub days: Option<u32>,
+}
+
+/// 即将过期的 summary_key 及 site_summary_key
+#[utoipa::path(
+    get,
+    path = "/admin/keys/expiring",
+    tag = "admin",
+    params(ExpiringParams),
+    responses((status = 200, body = JsonSuccessExpiringKeys))
+)]
+#[get("/keys/expiring")]
+pub async fn list_expiring_keys(params: web::Query<ExpiringParams>) -> Result<HttpResponse> {
+    let days = params
+        .days
+        .unwrap_or(setting::SETTING.key_expiry.report_days)
+        .clamp(1, 366);
+    let keys = key_expiry::expiring_within(days).await?;
+    Ok(success(Some(keys)))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+    /// csv 或 json, 默认 json
+    pub format: Option<String>,
+    /// 只校验不写入
+    #[serde(default)]
+    pub dry_run: bool,
+    /// 全部成功才写入
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+/// 批量导入用户及站点
+#[utoipa::path(
+    post,
+    path = "/admin/import",
+    tag = "admin",
+    params(ImportParams),
+    request_body(content = String, description = "CSV 或 JSON 数据", content_type = "text/plain"),
+    responses(
+        (status = 200, body = JsonSuccessImportReport),
+        (status = 400, body = JsonError),
+        (status = 413, description = "请求体超过 admin.max_upload_mb")
+    )
+)]
+pub async fn import_users(params: web::Query<ImportParams>, body: web::Bytes) -> Result<HttpResponse> {
+    let options = ImportOptions {
+        format: ImportFormat::parse(params.format.as_deref().unwrap_or("json"))?,
+        dry_run: params.dry_run,
+        atomic: params.atomic,
+    };
+    let report = import_service::import(&body, options).await?;
+    Ok(success(Some(report)))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BackupParams {
+    /// 是否 gzip 压缩
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+/// 备份/恢复接口的令牌请求头
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// 校验备份/恢复接口的令牌, 未配置令牌时接口禁用
+fn check_backup_token(req: &HttpRequest) -> Result<()> {
+    let expected = &setting::SETTING.admin.backup_token;
+    if expected.is_empty() {
+        return Err(Error::Forbidden("未配置 admin.backup_token, 备份接口已禁用".to_string()));
+    }
+    let token = req
+        .headers()
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    // 比较摘要而不是原文, 避免按字节提前返回泄露令牌前缀
+    if Sha256::digest(token.as_bytes()) != Sha256::digest(expected.as_bytes()) {
+        return Err(Error::Unauthorized("备份令牌无效".to_string()));
+    }
+    Ok(())
+}
+
+/// 导出全量备份, 不包含配置文件中的服务配置
+///
+/// 备份含明文密码及 openai key, 需携带 x-admin-token
+#[utoipa::path(
+    get,
+    path = "/admin/backup",
+    tag = "admin",
+    params(BackupParams),
+    responses(
+        (status = 200, description = "备份文件", body = String, content_type = "application/octet-stream"),
+        (status = 401, body = JsonError),
+        (status = 403, body = JsonError)
+    )
+)]
+#[get("/backup")]
+pub async fn backup(req: HttpRequest, params: web::Query<BackupParams>) -> Result<HttpResponse> {
+    check_backup_token(&req)?;
+    let bytes = backup_service::backup(params.gzip).await?;
+    let (content_type, file_name) = if params.gzip {
+        ("application/gzip", "backup.json.gz")
+    } else {
+        ("application/json", "backup.json")
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file_name),
+        ))
+        .body(bytes))
+}
+
+/// 从备份恢复到空库, 需携带 x-admin-token
+#[utoipa::path(
+    post,
+    path = "/admin/restore",
+    tag = "admin",
+    request_body(content = String, description = "备份文件, 支持 gzip", content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = JsonSuccessRestoreReport),
+        (status = 400, body = JsonError),
+        (status = 401, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 409, body = JsonError),
+        (status = 413, description = "请求体超过 admin.max_upload_mb")
+    )
+)]
+pub async fn restore(req: HttpRequest, body: web::Bytes) -> Result<HttpResponse> {
+    check_backup_token(&req)?;
+    let report = backup_service::restore(&body).await?;
+    Ok(success(Some(report)))
+}
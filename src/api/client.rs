@@ -1,17 +1,113 @@
 use actix_web::{
     get,
     web::{self},
-    HttpResponse, Scope,
+    HttpRequest, HttpResponse, Scope,
 };
+use serde::Deserialize;
+use serde_derive::Serialize;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::api::success;
+use crate::{
+    api::{success, JsonError, JsonSuccessKeyResolution, JsonSuccessNumber},
+    client::service::{key_service, pool_service},
+    error::{Error, Result},
+    middleware::{cors, rate_limit::RateLimit, SUMMARY_KEY_HEADER},
+};
 
-///请求路由
+///请求路由, 客户端接口统一跨域及限流
 pub fn routes() -> Scope {
-    web::scope("/client").service(index)
+    web::scope("/client").service(
+        web::scope("")
+            .wrap(RateLimit)
+            .wrap(cors::client())
+            .service(index)
+            .service(resolve),
+    )
 }
 
+#[utoipa::path(
+    get,
+    path = "/client",
+    tag = "client",
+    responses(
+        (status = 200, body = JsonSuccessNumber),
+        (status = 429, description = "请求过于频繁, 见 Retry-After 响应头", body = JsonError)
+    )
+)]
 #[get("")]
 pub async fn index() -> HttpResponse {
     success(Some(1))
 }
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResolveParams {
+    /// 调用方站点域名, 传入时校验站点是否有效
+    pub domain: Option<String>,
+}
+
+/// key 解析结果
+#[derive(Serialize, ToSchema, Debug)]
+pub struct KeyResolution {
+    pub user_id: u64,
+    pub key_id: u64,
+    /// 来自缓存, 用量扣减后最多滞后 `cache.ttl_seconds`
+    pub tokens: u64,
+    pub site_id: Option<u64>,
+    /// 用户没有自己的 openai_key 时选出的共享 key id, 用量上报时原样带回;
+    /// key 本身不经客户端接口返回, 服务端按 id 从 `openai_pool_key` 表读取, 见 `pool_service`。
+    /// 为空且用户没有 openai_key 时表示暂无可用的共享 key
+    pub pool_key_id: Option<u64>,
+}
+
+/// 解析请求头中的 summary_key, 返回用户余额、站点及服务本次请求的共享 key
+#[utoipa::path(
+    get,
+    path = "/client/resolve",
+    tag = "client",
+    params(
+        ("x-summary-key" = String, Header, description = "summary_key"),
+        ResolveParams
+    ),
+    responses(
+        (status = 200, body = JsonSuccessKeyResolution),
+        (status = 401, description = "summary_key 无效、已吊销或已过期", body = JsonError),
+        (status = 403, description = "站点未授权或 site_summary_key 已过期", body = JsonError),
+        (status = 429, description = "请求过于频繁, 见 Retry-After 响应头", body = JsonError)
+    )
+)]
+#[get("/resolve")]
+pub async fn resolve(req: HttpRequest, params: web::Query<ResolveParams>) -> Result<HttpResponse> {
+    let summary_key = req
+        .headers()
+        .get(SUMMARY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| Error::Unauthorized("缺少 summary_key".to_string()))?;
+    let resolved = key_service::find_key(summary_key)
+        .await?
+        .ok_or_else(|| Error::Unauthorized("summary_key 无效".to_string()))?;
+    let user = resolved.user;
+    let user_id = user.id.unwrap_or_default();
+
+    let site_id = match params.domain.as_deref() {
+        Some(domain) => {
+            let site = key_service::find_site(user_id, domain)
+                .await?
+                .ok_or_else(|| Error::Forbidden(format!("站点未授权: {}", domain)))?;
+            site.id
+        }
+        None => None,
+    };
+    let pool_key_id = match user.openai_key.as_deref() {
+        Some(openai_key) if !openai_key.is_empty() => None,
+        _ => pool_service::select(),
+    };
+    Ok(success(Some(KeyResolution {
+        user_id,
+        key_id: resolved.key_id,
+        tokens: user.tokens.unwrap_or(0),
+        site_id,
+        pool_key_id,
+    })))
+}
@@ -0,0 +1,30 @@
+use actix_web::{
+    get,
+    web::{self},
+    HttpResponse, Scope,
+};
+
+/// 管理后台页面, 编译时打包进二进制
+const INDEX_HTML: &str = include_str!("../../static/console/index.html");
+
+///请求路由
+pub fn routes() -> Scope {
+    web::scope("/console").service(index).service(index_slash)
+}
+
+/// 管理后台页面, 所有数据通过 /admin 接口获取
+#[get("")]
+pub async fn index() -> HttpResponse {
+    index_html()
+}
+
+#[get("/")]
+pub async fn index_slash() -> HttpResponse {
+    index_html()
+}
+
+fn index_html() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(INDEX_HTML)
+}
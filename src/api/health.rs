@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    get,
+    http::StatusCode,
+    web::{self},
+    HttpResponse, Scope,
+};
+use log::warn;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use serde_derive::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    api::{error, success, JsonError, JsonSuccessReadiness, JsonSuccessString},
+    db,
+    error::{code, Error, Result},
+    kafka,
+};
+
+/// 单个依赖的检查超时时间
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+///请求路由
+pub fn routes() -> Vec<Scope> {
+    vec![
+        web::scope("/healthz").service(healthz),
+        web::scope("/readyz").service(readyz),
+    ]
+}
+
+/// 依赖检查结果, 错误详情只记录到服务端日志
+#[derive(Serialize, ToSchema)]
+pub struct DependencyStatus {
+    #[schema(value_type = String)]
+    pub status: &'static str,
+    pub latency_ms: u128,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Readiness {
+    pub mysql: DependencyStatus,
+    pub kafka: DependencyStatus,
+}
+
+impl DependencyStatus {
+    fn from_result(name: &str, start: Instant, result: Result<()>) -> DependencyStatus {
+        let latency_ms = start.elapsed().as_millis();
+        match result {
+            Ok(_) => DependencyStatus {
+                status: "up",
+                latency_ms,
+            },
+            Err(e) => {
+                warn!("readiness check {} down: {}", name, e);
+                DependencyStatus {
+                    status: "down",
+                    latency_ms,
+                }
+            }
+        }
+    }
+
+    fn disabled() -> DependencyStatus {
+        DependencyStatus {
+            status: "disabled",
+            latency_ms: 0,
+        }
+    }
+
+    fn is_down(&self) -> bool {
+        self.status == "down"
+    }
+}
+
+/// 检查超时的错误, 日志中与依赖返回的错误区分开
+fn timeout_error(name: &str) -> Error {
+    Error::BizError(format!("{} check timed out after {}ms", name, CHECK_TIMEOUT.as_millis()))
+}
+
+async fn check_mysql() -> DependencyStatus {
+    let start = Instant::now();
+    let result = match tokio::time::timeout(
+        CHECK_TIMEOUT,
+        sqlx::query("select 1").execute(&db::get_pool()),
+    )
+    .await
+    {
+        Ok(r) => r.map(|_| ()).map_err(Error::from),
+        Err(_) => Err(timeout_error("mysql")),
+    };
+    DependencyStatus::from_result("mysql", start, result)
+}
+
+async fn check_kafka() -> DependencyStatus {
+    if !kafka::enabled() {
+        return DependencyStatus::disabled();
+    }
+    let start = Instant::now();
+    let result = match tokio::task::spawn_blocking(|| kafka::ping(CHECK_TIMEOUT)).await {
+        Ok(Err(Error::KafkaError(KafkaError::MetadataFetch(RDKafkaErrorCode::OperationTimedOut)))) => {
+            Err(timeout_error("kafka"))
+        }
+        Ok(r) => r,
+        Err(e) => Err(Error::BizError(format!("kafka check task failed: {}", e))),
+    };
+    DependencyStatus::from_result("kafka", start, result)
+}
+
+/// 存活检查
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses((status = 200, description = "服务存活", body = JsonSuccessString))
+)]
+#[get("")]
+pub async fn healthz() -> HttpResponse {
+    success(Some("ok"))
+}
+
+/// 就绪检查, 任一依赖不可用时返回 503, data 中为各依赖的状态
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "依赖均可用", body = JsonSuccessReadiness),
+        (status = 503, description = "存在不可用的依赖", body = JsonError)
+    )
+)]
+#[get("")]
+pub async fn readyz() -> HttpResponse {
+    let (mysql, kafka) = futures::join!(check_mysql(), check_kafka());
+    let readiness = Readiness { mysql, kafka };
+    if readiness.mysql.is_down() || readiness.kafka.is_down() {
+        return error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            code::UNAVAILABLE,
+            serde_json::to_value(&readiness).ok(),
+            Some("not ready".to_string()),
+        );
+    }
+    success(Some(readiness))
+}
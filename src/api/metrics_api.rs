@@ -0,0 +1,25 @@
+use actix_web::{
+    get,
+    web::{self},
+    HttpResponse, Scope,
+};
+
+use crate::{error::Result, metrics};
+
+///请求路由
+pub fn routes() -> Scope {
+    web::scope("/metrics").service(index)
+}
+
+/// Prometheus 指标
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses((status = 200, description = "Prometheus 文本格式指标", body = String, content_type = "text/plain"))
+)]
+#[get("")]
+pub async fn index() -> Result<HttpResponse> {
+    let (content_type, body) = metrics::render().await?;
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
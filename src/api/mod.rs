@@ -1,33 +1,119 @@
-use actix_web::{HttpResponse, Scope};
+use actix_web::{http::StatusCode, web, HttpResponse, Scope};
 use serde::ser;
 use serde_derive::Serialize;
+use utoipa::ToSchema;
 
+use crate::{
+    api::{client::KeyResolution, health::Readiness},
+    client::{
+        entity::{
+            alert::{AlertHistory, AlertRule},
+            auth_site::AuthSite,
+            key_expiry::ExpiringKey,
+            model_price::ModelPrice,
+            notification::Notification,
+            plan::Plan,
+            token_ledger::DailyUsage,
+            user_api_key::UserApiKey,
+        },
+        model::{
+            auth_site_model::AddAuthSite,
+            key_expiry_model::KeyExpiryResult,
+            plan_model::AssignPlanResult,
+            pool_key_model::PoolKeyView,
+            user_model::{GrantResult, UserView},
+        },
+        service::{backup_service::RestoreReport, import_service::ImportReport},
+    },
+    error::Error,
+    leader::Lease,
+    middleware::request_id::current_request_id,
+    scheduler::JobView,
+    setting,
+    utils::page::{Page, PageMeta},
+};
 
-pub mod test_api;
+
+pub mod health;
 pub mod admin;
 pub mod client;
+pub mod console;
+pub mod metrics_api;
+pub mod openapi;
 
 pub fn routes() -> Vec<Scope> {
    let mut scopes = vec![];
-   scopes.push(test_api::routes());
+   scopes.extend(health::routes());
    scopes.push(admin::routes());
    scopes.push(client::routes());
+   scopes.push(metrics_api::routes());
+   scopes.push(console::routes());
    scopes
 }
 
+/// 请求体 JSON 解析失败时按参数错误返回 400, 与其他错误使用相同的响应格式
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req| Error::invalid_param(err.to_string()).into())
+}
+
+/// 查询参数解析失败时按参数错误返回 400
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _req| Error::invalid_param(err.to_string()).into())
+}
+
+/// 上传接口 (导入、恢复) 的请求体上限, 默认的 256KiB 对备份文件来说太小
+pub fn upload_config() -> web::PayloadConfig {
+    web::PayloadConfig::new(setting::SETTING.admin.max_upload_bytes())
+}
+
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+#[aliases(
+    JsonSuccessString = JsonSuccess<String>,
+    JsonSuccessNumber = JsonSuccess<u32>,
+    JsonSuccessReadiness = JsonSuccess<Readiness>,
+    JsonSuccessUser = JsonSuccess<UserView>,
+    JsonSuccessUserPage = JsonSuccess<Vec<UserView>>,
+    JsonSuccessAuthSitePage = JsonSuccess<Vec<AuthSite>>,
+    JsonSuccessImportReport = JsonSuccess<ImportReport>,
+    JsonSuccessRestoreReport = JsonSuccess<RestoreReport>,
+    JsonSuccessGrantResult = JsonSuccess<GrantResult>,
+    JsonSuccessDailyUsage = JsonSuccess<Vec<DailyUsage>>,
+    JsonSuccessAddAuthSite = JsonSuccess<AddAuthSite>,
+    JsonSuccessKeyResolution = JsonSuccess<KeyResolution>,
+    JsonSuccessAlertRules = JsonSuccess<Vec<AlertRule>>,
+    JsonSuccessAlertHistoryPage = JsonSuccess<Vec<AlertHistory>>,
+    JsonSuccessNotificationPage = JsonSuccess<Vec<Notification>>,
+    JsonSuccessPlans = JsonSuccess<Vec<Plan>>,
+    JsonSuccessAssignPlanResult = JsonSuccess<AssignPlanResult>,
+    JsonSuccessJobs = JsonSuccess<Vec<JobView>>,
+    JsonSuccessJob = JsonSuccess<JobView>,
+    JsonSuccessLeases = JsonSuccess<Vec<Lease>>,
+    JsonSuccessKeyExpiryResult = JsonSuccess<KeyExpiryResult>,
+    JsonSuccessExpiringKeys = JsonSuccess<Vec<ExpiringKey>>,
+    JsonSuccessApiKey = JsonSuccess<UserApiKey>,
+    JsonSuccessApiKeyPage = JsonSuccess<Vec<UserApiKey>>,
+    JsonSuccessPoolKeys = JsonSuccess<Vec<PoolKeyView>>,
+    JsonSuccessModelPrices = JsonSuccess<Vec<ModelPrice>>
+)]
 pub struct JsonSuccess<T: ser::Serialize> {
     pub code: u32,
     pub data: Option<T>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<PageMeta>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct JsonError {
     pub code: u32,
-    pub data: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<serde_json::Value>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 pub fn success<T: ser::Serialize>(r: Option<T>) -> HttpResponse {
@@ -35,13 +121,32 @@ pub fn success<T: ser::Serialize>(r: Option<T>) -> HttpResponse {
         code: 0,
         data: r,
         error: None,
+        request_id: current_request_id(),
+        page: None,
+    })
+}
+
+/// 分页列表响应, 分页元数据放在信封的 page 字段
+pub fn success_page<T: ser::Serialize>(page: Page<T>) -> HttpResponse {
+    HttpResponse::Ok().json(JsonSuccess {
+        code: 0,
+        data: Some(page.records),
+        error: None,
+        request_id: current_request_id(),
+        page: Some(page.meta),
     })
 }
 
-pub fn error(err: Option<String>) -> HttpResponse {
-    HttpResponse::Ok().json(JsonError {
-        code: 1,
-        data: None,
+pub fn error(
+    status: StatusCode,
+    code: u32,
+    data: Option<serde_json::Value>,
+    err: Option<String>,
+) -> HttpResponse {
+    HttpResponse::build(status).json(JsonError {
+        code,
+        data,
         error: err,
+        request_id: current_request_id(),
     })
 }
\ No newline at end of file
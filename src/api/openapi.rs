@@ -0,0 +1,182 @@
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::{
+    api::{
+        self, health, JsonError, JsonSuccessAddAuthSite, JsonSuccessAuthSitePage,
+        JsonSuccessDailyUsage, JsonSuccessGrantResult, JsonSuccessImportReport, JsonSuccessNumber,
+        JsonSuccessReadiness, JsonSuccessRestoreReport, JsonSuccessString, JsonSuccessUser,
+        JsonSuccessUserPage,
+    },
+    client::{
+        entity::{
+            alert::{AlertHistory, AlertRule},
+            auth_site::AuthSite,
+            key_expiry::ExpiringKey,
+            model_price::ModelPrice,
+            notification::Notification,
+            plan::Plan,
+            token_ledger::DailyUsage,
+            user_api_key::UserApiKey,
+        },
+        model::{
+            alert_model::AddAlertRule,
+            api_key_model::AddApiKey,
+            auth_site_model::{AddAuthSite, SiteQuota},
+            key_expiry_model::{KeyExpiry, KeyExpiryResult},
+            model_price_model::AddModelPrice,
+            plan_model::{AddPlan, AssignPlan, AssignPlanResult},
+            pool_key_model::{AddPoolKey, PoolKeyHealth, PoolKeyView, UpdatePoolKey},
+            user_model::{AddUser, GrantResult, GrantTokens, UserView},
+        },
+        service::{
+            backup_service::RestoreReport,
+            import_service::{ImportReport, ImportRowError},
+        },
+    },
+    leader::Lease,
+    scheduler::JobView,
+    utils::page::PageMeta,
+};
+
+/// 管理后台 OpenAPI 文档
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        health::healthz,
+        health::readyz,
+        api::metrics_api::index,
+        api::admin::index,
+        api::admin::list_users,
+        api::admin::add_user,
+        api::admin::rotate_summary_key,
+        api::admin::list_user_sites,
+        api::admin::deactivate_user,
+        api::admin::grant_tokens,
+        api::admin::user_usage,
+        api::admin::add_user_site,
+        api::admin::deactivate_site,
+        api::admin::set_site_quota,
+        api::admin::list_alert_rules,
+        api::admin::add_alert_rule,
+        api::admin::deactivate_alert_rule,
+        api::admin::list_user_alerts,
+        api::admin::list_user_notifications,
+        api::admin::list_plans,
+        api::admin::add_plan,
+        api::admin::assign_user_plan,
+        api::admin::list_pool_keys,
+        api::admin::add_pool_key,
+        api::admin::update_pool_key,
+        api::admin::set_pool_key_health,
+        api::admin::list_model_prices,
+        api::admin::add_model_price,
+        api::admin::model_price_history,
+        api::admin::list_jobs,
+        api::admin::trigger_job,
+        api::admin::pause_job,
+        api::admin::resume_job,
+        api::admin::list_leases,
+        api::admin::set_user_key_expiry,
+        api::admin::set_site_key_expiry,
+        api::admin::list_expiring_keys,
+        api::admin::list_user_api_keys,
+        api::admin::add_user_api_key,
+        api::admin::revoke_api_key,
+        api::admin::set_api_key_expiry,
+        api::admin::import_users,
+        api::admin::backup,
+        api::admin::restore,
+        api::client::index,
+        api::client::resolve,
+    ),
+    components(schemas(
+        AddUser,
+        AddAuthSite,
+        UserView,
+        AuthSite,
+        PageMeta,
+        health::Readiness,
+        health::DependencyStatus,
+        JsonError,
+        JsonSuccessString,
+        JsonSuccessNumber,
+        JsonSuccessReadiness,
+        JsonSuccessUser,
+        JsonSuccessUserPage,
+        JsonSuccessAuthSitePage,
+        JsonSuccessImportReport,
+        ImportReport,
+        ImportRowError,
+        JsonSuccessRestoreReport,
+        RestoreReport,
+        GrantTokens,
+        GrantResult,
+        JsonSuccessGrantResult,
+        DailyUsage,
+        JsonSuccessDailyUsage,
+        JsonSuccessAddAuthSite,
+        SiteQuota,
+        AlertRule,
+        AlertHistory,
+        AddAlertRule,
+        api::JsonSuccessAlertRules,
+        api::JsonSuccessAlertHistoryPage,
+        Notification,
+        api::JsonSuccessNotificationPage,
+        Plan,
+        AddPlan,
+        AssignPlan,
+        AssignPlanResult,
+        api::JsonSuccessPlans,
+        api::JsonSuccessAssignPlanResult,
+        PoolKeyView,
+        AddPoolKey,
+        UpdatePoolKey,
+        PoolKeyHealth,
+        api::JsonSuccessPoolKeys,
+        ModelPrice,
+        AddModelPrice,
+        api::JsonSuccessModelPrices,
+        JobView,
+        api::JsonSuccessJobs,
+        api::JsonSuccessJob,
+        Lease,
+        api::JsonSuccessLeases,
+        KeyExpiry,
+        KeyExpiryResult,
+        ExpiringKey,
+        api::JsonSuccessKeyExpiryResult,
+        api::JsonSuccessExpiringKeys,
+        UserApiKey,
+        AddApiKey,
+        api::JsonSuccessApiKey,
+        api::JsonSuccessApiKeyPage,
+        api::client::KeyResolution,
+        api::JsonSuccessKeyResolution,
+    )),
+    tags(
+        (name = "health", description = "健康检查"),
+        (name = "metrics", description = "监控指标"),
+        (name = "admin", description = "管理接口"),
+        (name = "client", description = "客户端接口"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Swagger UI 页面 `/swagger-ui/`, 文档地址 `/openapi.json`
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/swagger-ui/{_:.*}").url("/openapi.json", ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod openapi_tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_json() {
+        let json = ApiDoc::openapi().to_json().unwrap();
+        assert!(json.contains("/admin/users/{user_id}/sites"));
+        assert!(json.contains("JsonSuccessUserPage"));
+    }
+}
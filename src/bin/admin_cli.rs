@@ -0,0 +1,348 @@
+//! 离线运维工具, HTTP 服务不可用时直接操作数据库
+//!
+//! ```text
+//! admin_cli user create --account foo --password 123456
+//! admin_cli --json user list --size 50
+//! admin_cli user grant --id 1 --tokens 10000 --reason "月度充值"
+//! admin_cli import --file users.csv --dry-run
+//! admin_cli backup --out backup.json.gz --gzip
+//! admin_cli migrate
+//! ```
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::HashMap;
+use summary_gpt_server_admin::client::entity::auth_site::AuthSite;
+use summary_gpt_server_admin::client::entity::user::User;
+use summary_gpt_server_admin::client::model::auth_site_model::AddAuthSite;
+use summary_gpt_server_admin::client::model::user_model::{AddUser, UserView};
+use summary_gpt_server_admin::client::service::backup_service;
+use summary_gpt_server_admin::client::service::import_service::{self, ImportFormat, ImportOptions};
+use summary_gpt_server_admin::client::service::notify_service;
+use summary_gpt_server_admin::db;
+use summary_gpt_server_admin::error::{Error, Result};
+use summary_gpt_server_admin::notify::template;
+use summary_gpt_server_admin::setting;
+use summary_gpt_server_admin::utils::page::PageQuery;
+
+#[derive(Parser)]
+#[command(name = "admin_cli", about = "summary-gpt 管理后台运维工具")]
+struct Cli {
+    /// 以 JSON 输出结果
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 用户管理
+    #[command(subcommand)]
+    User(UserCommand),
+    /// 站点管理
+    #[command(subcommand)]
+    Site(SiteCommand),
+    /// 从 CSV 或 JSON 文件批量导入用户及站点
+    Import {
+        #[arg(long)]
+        file: String,
+        /// csv 或 json, 默认按文件扩展名判断
+        #[arg(long)]
+        format: Option<String>,
+        /// 只校验不写入
+        #[arg(long)]
+        dry_run: bool,
+        /// 全部成功才写入
+        #[arg(long)]
+        atomic: bool,
+    },
+    /// 导出全量备份, 不包含配置文件中的服务配置
+    Backup {
+        #[arg(long)]
+        out: String,
+        /// gzip 压缩
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// 从备份恢复到空库
+    Restore {
+        #[arg(long)]
+        file: String,
+    },
+    /// 执行数据库迁移
+    Migrate,
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// 创建用户
+    Create {
+        #[arg(long)]
+        account: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u64).range(0..=1_000_000_000))]
+        tokens: u64,
+        #[arg(long)]
+        openai_key: Option<String>,
+        /// 接收通知的邮箱, 创建后发送欢迎邮件
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// 用户列表
+    List(ListArgs),
+    /// 发放 tokens
+    Grant {
+        #[arg(long)]
+        id: u64,
+        /// 与管理接口相同, 须在 1-1000000000 之间
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..=1_000_000_000))]
+        tokens: u64,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// 重新生成 summary_key
+    RotateKey {
+        #[arg(long)]
+        id: u64,
+    },
+}
+
+#[derive(Args)]
+struct ListArgs {
+    #[arg(long, default_value_t = 1)]
+    page: u64,
+    #[arg(long, default_value_t = 20)]
+    size: u64,
+    #[arg(long)]
+    account: Option<String>,
+    #[arg(long)]
+    active: Option<u64>,
+}
+
+#[derive(Subcommand)]
+enum SiteCommand {
+    /// 添加站点
+    Add {
+        #[arg(long)]
+        user_id: u64,
+        #[arg(long)]
+        domain: String,
+    },
+    /// 停用站点
+    Deactivate {
+        #[arg(long)]
+        id: u64,
+    },
+}
+
+/// 按 --json 输出结果, 否则输出可读文本
+fn print<T: Serialize>(json: bool, value: &T, text: impl FnOnce() -> String) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        println!("{}", text());
+    }
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let json = cli.json;
+    match cli.command {
+        Command::Migrate => {
+            db::run_migrations().await?;
+            print(json, &serde_json::json!({ "migrated": true }), || {
+                "migrations applied".to_string()
+            })
+        }
+        Command::Import {
+            file,
+            format,
+            dry_run,
+            atomic,
+        } => {
+            let format = match format {
+                Some(format) => ImportFormat::parse(&format)?,
+                None if file.ends_with(".csv") => ImportFormat::Csv,
+                None => ImportFormat::Json,
+            };
+            let data = std::fs::read(&file)?;
+            let report = import_service::import(
+                &data,
+                ImportOptions {
+                    format,
+                    dry_run,
+                    atomic,
+                },
+            )
+            .await?;
+            print(json, &report, || {
+                let mut lines = vec![format!(
+                    "total: {}, valid: {}, imported: {}{}",
+                    report.total,
+                    report.valid,
+                    report.imported,
+                    if report.dry_run { " (dry run)" } else { "" }
+                )];
+                for e in &report.errors {
+                    for f in &e.errors {
+                        lines.push(format!(
+                            "row {} {}: {} {}",
+                            e.row,
+                            e.account.clone().unwrap_or_default(),
+                            f.field,
+                            f.message
+                        ));
+                    }
+                }
+                lines.join("\n")
+            })
+        }
+        Command::Backup { out, gzip } => {
+            let bytes = backup_service::backup(gzip).await?;
+            std::fs::write(&out, &bytes)?;
+            let body = serde_json::json!({ "file": out, "bytes": bytes.len() });
+            print(json, &body, || format!("backup written to {} ({} bytes)", out, bytes.len()))
+        }
+        Command::Restore { file } => {
+            let report = backup_service::restore(&std::fs::read(&file)?).await?;
+            print(json, &report, || {
+                format!(
+                    "restored users: {}, auth_sites: {}, token_ledgers: {}, api_keys: {}, plans: {}, alert_rules: {}, pool_keys: {}, model_prices: {}",
+                    report.users,
+                    report.auth_sites,
+                    report.token_ledgers,
+                    report.api_keys,
+                    report.plans,
+                    report.alert_rules,
+                    report.pool_keys,
+                    report.model_prices
+                )
+            })
+        }
+        Command::User(UserCommand::Create {
+            account,
+            password,
+            tokens,
+            openai_key,
+            email,
+        }) => {
+            let mut add_user = AddUser::new();
+            add_user.account = Some(account.clone());
+            add_user.password = Some(password);
+            add_user.tokens = Some(tokens);
+            add_user.openai_key = openai_key;
+            add_user.email = email;
+            User::add_user(add_user).await?;
+            let user = User::find_by_account(&account)
+                .await?
+                .ok_or_else(|| Error::NotFound(format!("user {}", account)))?;
+            notify_service::notify_account(&user, template::WELCOME).await;
+            let user = UserView::from(user);
+            print(json, &user, || {
+                format!(
+                    "created user {} (id: {}, summary_key: {})",
+                    account,
+                    user.id.unwrap_or_default(),
+                    user.summary_key.clone().unwrap_or_default()
+                )
+            })
+        }
+        Command::User(UserCommand::List(args)) => {
+            let mut params = HashMap::new();
+            params.insert("page".to_string(), args.page.to_string());
+            params.insert("size".to_string(), args.size.to_string());
+            if let Some(account) = args.account {
+                params.insert("account".to_string(), account);
+            }
+            if let Some(active) = args.active {
+                params.insert("active".to_string(), active.to_string());
+            }
+            let page = User::page(&PageQuery::from_query(&params)?)
+                .await?
+                .map(UserView::from);
+            let body = serde_json::json!({ "records": page.records, "page": page.meta });
+            print(json, &body, || {
+                let mut lines = vec![format!(
+                    "{:<8} {:<24} {:>12} {:<12} {}",
+                    "ID", "ACCOUNT", "TOKENS", "SUMMARY_KEY", "ACTIVE"
+                )];
+                for u in &page.records {
+                    lines.push(format!(
+                        "{:<8} {:<24} {:>12} {:<12} {}",
+                        u.id.unwrap_or_default(),
+                        u.account.clone().unwrap_or_default(),
+                        u.tokens.unwrap_or_default(),
+                        u.summary_key.clone().unwrap_or_default(),
+                        u.active.unwrap_or_default()
+                    ));
+                }
+                lines.push(format!(
+                    "page {} / total {}",
+                    page.meta.page, page.meta.total
+                ));
+                lines.join("\n")
+            })
+        }
+        Command::User(UserCommand::Grant { id, tokens, reason }) => {
+            let balance = User::grant_tokens(id, tokens, reason.as_deref()).await?;
+            let body = serde_json::json!({ "user_id": id, "granted": tokens, "balance": balance });
+            print(json, &body, || {
+                format!("granted {} tokens to user {}, balance: {}", tokens, id, balance)
+            })
+        }
+        Command::User(UserCommand::RotateKey { id }) => {
+            let summary_key = User::rotate_summary_key(id).await?;
+            if let Some(user) = User::find_by_id(id).await? {
+                notify_service::notify_account(&user, template::KEY_ROTATED).await;
+            }
+            let body = serde_json::json!({ "user_id": id, "summary_key": summary_key });
+            print(json, &body, || {
+                format!("user {} summary_key rotated: {}", id, summary_key)
+            })
+        }
+        Command::Site(SiteCommand::Add { user_id, domain }) => {
+            let mut add = AddAuthSite::new();
+            add.user_id = Some(user_id);
+            add.site_domain = Some(domain.clone());
+            add.site_summary_key = Some(summary_gpt_server_admin::utils::uuid::new_summary_key());
+            let site_summary_key = add.site_summary_key.clone();
+            AuthSite::add_auth_site(add).await?;
+            let body = serde_json::json!({
+                "user_id": user_id,
+                "site_domain": domain,
+                "site_summary_key": site_summary_key,
+            });
+            print(json, &body, || {
+                format!("added site {} for user {}", domain, user_id)
+            })
+        }
+        Command::Site(SiteCommand::Deactivate { id }) => {
+            AuthSite::deactivate(id).await?;
+            print(json, &serde_json::json!({ "id": id, "active": 0 }), || {
+                format!("site {} deactivated", id)
+            })
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .target(env_logger::Target::Stderr)
+        .init();
+    let cli = Cli::parse();
+
+    let result: Result<()> = async {
+        db::init_connections(setting::get_conn_string().as_str()).await?;
+        run(cli).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,152 @@
+use rbatis::{impl_select, rbdc::datetime::DateTime};
+use rbs::Value;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use utoipa::ToSchema;
+
+use crate::{
+    db,
+    error::{Error, Result as MyResult},
+    utils::page::{Page, PageQuery, PageSpec},
+};
+
+/// 余额低于阈值
+pub const KIND_BALANCE_BELOW: &str = "balance_below";
+/// 本月已用 tokens 占本月发放 tokens 的百分比达到阈值
+pub const KIND_MONTHLY_USAGE_PERCENT: &str = "monthly_usage_percent";
+/// 站点已用 tokens 占站点配额的百分比达到阈值
+pub const KIND_SITE_QUOTA_EXHAUSTED: &str = "site_quota_exhausted";
+
+pub const KINDS: &[&str] = &[
+    KIND_BALANCE_BELOW,
+    KIND_MONTHLY_USAGE_PERCENT,
+    KIND_SITE_QUOTA_EXHAUSTED,
+];
+
+/// 告警规则, user_id 为空时对所有用户生效, 同类型的用户规则优先
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AlertRule {
+    pub id: Option<u64>,
+    pub user_id: Option<u64>,
+    pub kind: Option<String>,
+    pub threshold: Option<u64>,
+    pub active: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub updated_time: Option<DateTime>,
+}
+
+rbatis::crud!(AlertRule {});
+impl_select!(AlertRule{select_active_for_user(user_id:u64) => "`where active = 1 and (user_id is null or user_id = #{user_id})`"});
+
+/// 告警历史
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AlertHistory {
+    pub id: Option<u64>,
+    pub rule_id: Option<u64>,
+    pub user_id: Option<u64>,
+    pub site_id: Option<u64>,
+    pub kind: Option<String>,
+    pub threshold: Option<u64>,
+    pub value: Option<u64>,
+    pub message: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+}
+
+rbatis::crud!(AlertHistory {});
+
+const HISTORY_PAGE_SPEC: PageSpec = PageSpec {
+    table: "alert_history",
+    sorts: &["id", "created_time"],
+    filters: &["kind", "rule_id", "site_id"],
+};
+
+impl AlertRule {
+    /// 新增规则, 返回规则 id
+    pub async fn add(rule: &AlertRule) -> MyResult<u64> {
+        let result = AlertRule::insert(&mut db::get_rb(), rule).await?;
+        result
+            .last_insert_id
+            .as_u64()
+            .ok_or_else(|| Error::BizError("无法获取新规则 id".to_string()))
+    }
+
+    pub async fn all() -> MyResult<Vec<AlertRule>> {
+        let x = AlertRule::select_all(&mut db::get_rb()).await?;
+        Ok(x)
+    }
+
+    /// 对用户生效的规则, 包含全局规则
+    pub async fn find_active_for_user(user_id: u64) -> MyResult<Vec<AlertRule>> {
+        let x = AlertRule::select_active_for_user(&mut db::get_rb(), user_id).await?;
+        Ok(x)
+    }
+
+    /// 停用规则
+    pub async fn deactivate(id: u64) -> MyResult<()> {
+        let result = sqlx::query("update alert_rule set active = 0, updated_time = now() where id = ?")
+            .bind(id)
+            .execute(&db::get_pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!("alert rule {}", id)));
+        }
+        Ok(())
+    }
+}
+
+impl AlertHistory {
+    /// 更新规则在 subject 上的告警状态, 由未告警变为告警时写入历史
+    ///
+    /// 返回是否新触发; 条件持续满足时不会重复触发, 条件解除后才会再次触发。
+    pub async fn transition(subject: &str, firing: bool, history: &AlertHistory) -> MyResult<bool> {
+        let rule_id = history.rule_id.unwrap_or_default();
+        let mut tx = db::get_pool().begin().await?;
+        sqlx::query("insert ignore into alert_state (rule_id, subject, firing, updated_time) values (?, ?, 0, now())")
+            .bind(rule_id)
+            .bind(subject)
+            .execute(&mut *tx)
+            .await?;
+        let row = sqlx::query("select firing from alert_state where rule_id = ? and subject = ? for update")
+            .bind(rule_id)
+            .bind(subject)
+            .fetch_one(&mut *tx)
+            .await?;
+        let was_firing = row.try_get::<i8, _>("firing")? == 1;
+        if was_firing == firing {
+            tx.commit().await?;
+            return Ok(false);
+        }
+
+        sqlx::query("update alert_state set firing = ?, updated_time = now() where rule_id = ? and subject = ?")
+            .bind(firing as i8)
+            .bind(rule_id)
+            .bind(subject)
+            .execute(&mut *tx)
+            .await?;
+        if firing {
+            let sql = "insert into alert_history (rule_id, user_id, site_id, kind, threshold, value, message, created_time) \
+                       values (?, ?, ?, ?, ?, ?, ?, now())";
+            sqlx::query(sql)
+                .bind(rule_id)
+                .bind(history.user_id)
+                .bind(history.site_id)
+                .bind(&history.kind)
+                .bind(history.threshold)
+                .bind(history.value)
+                .bind(&history.message)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(firing)
+    }
+
+    pub async fn page_by_user_id(user_id: u64, query: &PageQuery) -> MyResult<Page<AlertHistory>> {
+        query
+            .fetch(&HISTORY_PAGE_SPEC, vec![("user_id", Value::U64(user_id))], |h: &AlertHistory| h.id)
+            .await
+    }
+}
@@ -1,23 +1,61 @@
-use log::info;
-use rbatis::{impl_select, rbdc::datetime::DateTime};
+use log::{info, warn};
+use rbatis::{executor::Executor, impl_select, rbdc::datetime::DateTime};
+use rbs::Value;
 use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use utoipa::ToSchema;
 
-use crate::{client::model::auth_site_model::AddAuthSite, db, error::Result as MyResult};
+use crate::{
+    client::{
+        entity::{
+            key_expiry,
+            outbox_event::{self, OutboxEvent},
+        },
+        model::{auth_site_model::AddAuthSite, key_expiry_model::ExpiryChange},
+        service::key_service,
+    },
+    db,
+    error::{Error, Result as MyResult},
+    middleware::mask_key,
+    utils::{
+        page::{Page, PageQuery, PageSpec},
+        validate,
+    },
+};
 
-#[derive(sqlx::FromRow, Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct AuthSite {
     pub id: Option<u64>,
     pub user_id: Option<u64>,
     pub site_domain: Option<String>,
     pub site_summary_key: Option<String>,
+    /// site_summary_key 过期时间, 为空表示永不过期
+    #[schema(value_type = Option<String>)]
+    pub site_summary_key_expires_at: Option<DateTime>,
+    /// 每月的 tokens 配额, 为空不限制
+    pub token_quota: Option<u64>,
+    /// period_begin 所在月已用的 tokens
+    pub tokens_used: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub period_begin: Option<DateTime>,
 
     pub active: Option<u64>,
+    #[schema(value_type = Option<String>)]
     pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
     pub updated_time: Option<DateTime>,
 }
 
 rbatis::crud!(AuthSite {});
 impl_select!(AuthSite{select_active_by_user_id(user_id:u64) => "`where user_id = #{user_id} and active = 1`"});
+impl_select!(AuthSite{select_all_active() => "`where active = 1`"});
+impl_select!(AuthSite{select_active_by_domain(user_id:u64, site_domain:&str) -> Option => "`where user_id = #{user_id} and site_domain = #{site_domain} and active = 1 and (site_summary_key_expires_at is null or site_summary_key_expires_at > now()) limit 1`"});
+
+const PAGE_SPEC: PageSpec = PageSpec {
+    table: "auth_site",
+    sorts: &["id", "site_domain", "created_time", "updated_time"],
+    filters: &["active", "site_domain"],
+};
 
 impl AuthSite {
     pub async fn new() -> AuthSite {
@@ -26,6 +64,10 @@ impl AuthSite {
             user_id: None,
             site_domain: None,
             site_summary_key: None,
+            site_summary_key_expires_at: None,
+            token_quota: None,
+            tokens_used: None,
+            period_begin: None,
             active: None,
             created_time: Some(DateTime::now()),
             updated_time: Some(DateTime::now()),
@@ -33,9 +75,35 @@ impl AuthSite {
     }
 
     pub async fn add_auth_site(add_model: AddAuthSite) -> MyResult<()> {
-        let auth_site = add_model.into();
+        let rb = db::get_rb();
+        let mut tx = rb.acquire_begin().await?;
+        if let Err(e) = AuthSite::add_auth_site_in(&mut tx, add_model).await {
+            if let Err(rollback_err) = tx.rollback().await {
+                warn!("add auth site rollback error: {}", rollback_err);
+            }
+            return Err(e);
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 在指定的连接或事务中新增站点
+    pub async fn add_auth_site_in(executor: &mut dyn Executor, add_model: AddAuthSite) -> MyResult<()> {
+        validate::validate(&add_model)?;
+        let auth_site: AuthSite = add_model.into();
         info!("add auth site: {:?}", auth_site);
-        AuthSite::insert(&mut db::get_rb(), &auth_site).await?;
+        let result = AuthSite::insert(executor, &auth_site).await?;
+        let site_id = result
+            .last_insert_id
+            .as_u64()
+            .ok_or_else(|| Error::BizError("无法获取新站点 id".to_string()))?;
+        let payload = serde_json::json!({
+            "site_id": site_id,
+            "user_id": auth_site.user_id,
+            "site_domain": auth_site.site_domain,
+            "site_summary_key_hint": auth_site.site_summary_key.as_deref().map(mask_key),
+        });
+        OutboxEvent::record_in(executor, outbox_event::SITE_ADDED, site_id, &payload).await?;
         Ok(())
     }
 
@@ -43,8 +111,84 @@ impl AuthSite {
         let x = AuthSite::select_active_by_user_id(&mut db::get_rb(), user_id).await?;
         Ok(x)
     }
+
+    /// 按用户和域名查询有效站点, 调用方一般通过 key_service 走缓存
+    pub async fn find_active_by_domain(user_id: u64, site_domain: &str) -> MyResult<Option<AuthSite>> {
+        let x = AuthSite::select_active_by_domain(&mut db::get_rb(), user_id, site_domain).await?;
+        Ok(x)
+    }
+
+    pub async fn find_all_active() -> MyResult<Vec<AuthSite>> {
+        let x = AuthSite::select_all_active(&mut db::get_rb()).await?;
+        Ok(x)
+    }
+
     pub async fn find_by_user_id(user_id: u64) -> MyResult<Vec<AuthSite>> {
         let x = AuthSite::select_by_column(&mut db::get_rb(), "user_id", user_id).await?;
         Ok(x)
     }
+
+    /// 停用站点
+    pub async fn deactivate(id: u64) -> MyResult<()> {
+        let mut tx = db::get_pool().begin().await?;
+        let row = sqlx::query("select user_id, site_domain, site_summary_key from auth_site where id = ? for update")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("auth site {}", id)))?;
+        sqlx::query("update auth_site set active = 0, updated_time = now() where id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let site_summary_key: Option<String> = row.try_get("site_summary_key")?;
+        let payload = serde_json::json!({
+            "site_id": id,
+            "user_id": row.try_get::<Option<u64>, _>("user_id")?,
+            "site_domain": row.try_get::<Option<String>, _>("site_domain")?,
+            "site_summary_key_hint": site_summary_key.as_deref().map(mask_key),
+        });
+        OutboxEvent::record(&mut tx, outbox_event::SITE_DEACTIVATED, id, &payload).await?;
+        tx.commit().await?;
+        key_service::invalidate_site(id);
+        Ok(())
+    }
+
+    /// 修改 site_summary_key 过期时间, 返回修改后的过期时间
+    pub async fn set_key_expiry(id: u64, change: &ExpiryChange) -> MyResult<Option<String>> {
+        let expires_at = key_expiry::update("auth_site", "site_summary_key_expires_at", id, change).await?;
+        key_service::invalidate_site(id);
+        Ok(expires_at)
+    }
+
+    /// 设置站点配额, 为空时不限制
+    pub async fn set_quota(id: u64, token_quota: Option<u64>) -> MyResult<()> {
+        let result = sqlx::query("update auth_site set token_quota = ?, updated_time = now() where id = ?")
+            .bind(token_quota)
+            .bind(id)
+            .execute(&db::get_pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!("auth site {}", id)));
+        }
+        key_service::invalidate_site(id);
+        Ok(())
+    }
+
+    /// 清零上月及更早的站点已用 tokens, 返回清零的站点数
+    pub async fn reset_usage(period_begin: &str) -> MyResult<u64> {
+        let sql = "update auth_site set tokens_used = 0, period_begin = ? \
+                   where period_begin is null or period_begin < ?";
+        let result = sqlx::query(sql)
+            .bind(period_begin)
+            .bind(period_begin)
+            .execute(&db::get_pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn page_by_user_id(user_id: u64, query: &PageQuery) -> MyResult<Page<AuthSite>> {
+        query
+            .fetch(&PAGE_SPEC, vec![("user_id", Value::U64(user_id))], |s: &AuthSite| s.id)
+            .await
+    }
 }
@@ -0,0 +1,104 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use sqlx::Row;
+
+use crate::{db, error::Result as MyResult};
+
+/// 定时任务的暂停状态和手动触发请求, 多实例部署时在实例间共享
+#[derive(Debug, Clone, Default)]
+pub struct JobControl {
+    pub name: String,
+    pub paused: bool,
+    pub trigger_requested: bool,
+    /// 最近一次执行, 由执行任务的实例写入
+    pub last_run: Option<JobRun>,
+}
+
+/// 任务的一次执行结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobRun {
+    pub started_at: DateTime<Utc>,
+    pub duration_millis: u64,
+    pub success: bool,
+    /// 成功时为执行摘要, 失败时为错误信息
+    pub message: String,
+}
+
+impl JobControl {
+    pub async fn all() -> MyResult<Vec<JobControl>> {
+        let rows = sqlx::query(
+            "select name, paused, trigger_requested, last_run_time, last_duration_millis, last_success, last_message \
+             from job_control",
+        )
+        .fetch_all(&db::get_pool())
+        .await?;
+        let mut controls = Vec::with_capacity(rows.len());
+        for row in rows {
+            let last_run_time: Option<NaiveDateTime> = row.try_get("last_run_time")?;
+            let last_run = match last_run_time {
+                Some(time) => Some(JobRun {
+                    started_at: Utc.from_utc_datetime(&time),
+                    duration_millis: row.try_get::<Option<i64>, _>("last_duration_millis")?.unwrap_or(0) as u64,
+                    success: row.try_get::<Option<i8>, _>("last_success")? == Some(1),
+                    message: row.try_get::<Option<String>, _>("last_message")?.unwrap_or_default(),
+                }),
+                None => None,
+            };
+            controls.push(JobControl {
+                name: row.try_get("name")?,
+                paused: row.try_get::<i8, _>("paused")? == 1,
+                trigger_requested: row.try_get::<i8, _>("trigger_requested")? == 1,
+                last_run,
+            });
+        }
+        Ok(controls)
+    }
+
+    /// 记录最近一次执行结果, 其他实例查看任务状态时读取
+    pub async fn record_run(name: &str, run: &JobRun) -> MyResult<()> {
+        let sql = "insert into job_control \
+                   (name, last_run_time, last_duration_millis, last_success, last_message, updated_time) \
+                   values (?, ?, ?, ?, ?, now()) \
+                   on duplicate key update last_run_time = values(last_run_time), \
+                   last_duration_millis = values(last_duration_millis), last_success = values(last_success), \
+                   last_message = values(last_message), updated_time = now()";
+        sqlx::query(sql)
+            .bind(name)
+            .bind(run.started_at.naive_utc())
+            .bind(run.duration_millis as i64)
+            .bind(run.success as i8)
+            .bind(&run.message)
+            .execute(&db::get_pool())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_paused(name: &str, paused: bool) -> MyResult<()> {
+        let sql = "insert into job_control (name, paused, updated_time) values (?, ?, now()) \
+                   on duplicate key update paused = values(paused), updated_time = now()";
+        sqlx::query(sql)
+            .bind(name)
+            .bind(paused as i8)
+            .execute(&db::get_pool())
+            .await?;
+        Ok(())
+    }
+
+    /// 请求 leader 执行一次任务
+    pub async fn request_trigger(name: &str) -> MyResult<()> {
+        let sql = "insert into job_control (name, trigger_requested, updated_time) values (?, 1, now()) \
+                   on duplicate key update trigger_requested = 1, updated_time = now()";
+        sqlx::query(sql).bind(name).execute(&db::get_pool()).await?;
+        Ok(())
+    }
+
+    /// 领取触发请求, 返回是否由本次调用领取
+    pub async fn take_trigger(name: &str) -> MyResult<bool> {
+        let result = sqlx::query(
+            "update job_control set trigger_requested = 0, updated_time = now() where name = ? and trigger_requested = 1",
+        )
+        .bind(name)
+        .execute(&db::get_pool())
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use utoipa::ToSchema;
+
+use crate::{
+    client::model::key_expiry_model::ExpiryChange,
+    db,
+    error::{Error, Result as MyResult},
+};
+
+pub const KIND_API_KEY: &str = "api_key";
+pub const KIND_SITE_SUMMARY_KEY: &str = "site_summary_key";
+
+/// 即将过期的 key
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExpiringKey {
+    /// api_key / site_summary_key
+    pub kind: String,
+    pub user_id: u64,
+    pub account: Option<String>,
+    pub key_id: Option<u64>,
+    pub key_name: Option<String>,
+    pub site_id: Option<u64>,
+    pub site_domain: Option<String>,
+    pub expires_at: String,
+}
+
+/// 修改 table 中 id 行的过期时间列, 返回修改后的值
+pub async fn update(table: &str, column: &str, id: u64, change: &ExpiryChange) -> MyResult<Option<String>> {
+    let mut tx = db::get_pool().begin().await?;
+    let select = format!("select cast({} as char) as expires_at from {} where id = ? for update", column, table);
+    sqlx::query(&select)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("{} {}", table, id)))?;
+
+    let value = match change {
+        ExpiryChange::Never => "null".to_string(),
+        ExpiryChange::At(_) => "?".to_string(),
+        ExpiryChange::ExtendDays(_) => format!("if({c} is null, null, greatest({c}, now()) + interval ? day)", c = column),
+    };
+    let update = format!("update {} set {} = {}, updated_time = now() where id = ?", table, column, value);
+    let query = match change {
+        ExpiryChange::Never => sqlx::query(&update),
+        ExpiryChange::At(expires_at) => sqlx::query(&update).bind(expires_at.as_str()),
+        ExpiryChange::ExtendDays(days) => sqlx::query(&update).bind(*days),
+    };
+    query.bind(id).execute(&mut *tx).await?;
+
+    let row = sqlx::query(&select).bind(id).fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(row.try_get("expires_at")?)
+}
+
+/// table 中 id 行的过期时间列距现在的秒数, 按数据库时间计算; 不过期或行不存在时返回 None
+pub async fn seconds_left(table: &str, column: &str, id: u64) -> MyResult<Option<i64>> {
+    let sql = format!("select timestampdiff(second, now(), {}) as seconds_left from {} where id = ?", column, table);
+    let row = sqlx::query(&sql).bind(id).fetch_optional(&db::get_pool()).await?;
+    match row {
+        Some(row) => Ok(row.try_get("seconds_left")?),
+        None => Ok(None),
+    }
+}
+
+/// 未来 days 天内过期的有效 key, 按过期时间排序
+pub async fn expiring_within(days: u32) -> MyResult<Vec<ExpiringKey>> {
+    let sql = "select ? as kind, k.user_id, u.account, k.id as key_id, k.name as key_name, \
+               null as site_id, null as site_domain, \
+               cast(k.expires_at as char) as expires_at, k.expires_at as sort_time \
+               from user_api_key k join user u on k.user_id = u.id \
+               where u.active = 1 and k.revoked_time is null \
+               and k.expires_at > now() and k.expires_at <= now() + interval ? day \
+               union all \
+               select ?, s.user_id, u.account, null, null, s.id, s.site_domain, \
+               cast(s.site_summary_key_expires_at as char), s.site_summary_key_expires_at \
+               from auth_site s left join user u on s.user_id = u.id where s.active = 1 \
+               and s.site_summary_key_expires_at > now() and s.site_summary_key_expires_at <= now() + interval ? day \
+               order by sort_time";
+    let rows = sqlx::query(sql)
+        .bind(KIND_API_KEY)
+        .bind(days)
+        .bind(KIND_SITE_SUMMARY_KEY)
+        .bind(days)
+        .fetch_all(&db::get_pool())
+        .await?;
+    let mut keys = Vec::with_capacity(rows.len());
+    for row in rows {
+        keys.push(ExpiringKey {
+            kind: row.try_get("kind")?,
+            user_id: row.try_get("user_id")?,
+            account: row.try_get("account")?,
+            key_id: row.try_get("key_id")?,
+            key_name: row.try_get("key_name")?,
+            site_id: row.try_get("site_id")?,
+            site_domain: row.try_get("site_domain")?,
+            expires_at: row.try_get("expires_at")?,
+        });
+    }
+    Ok(keys)
+}
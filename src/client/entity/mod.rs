@@ -1,2 +1,12 @@
 pub mod user;
-pub mod auth_site;
\ No newline at end of file
+pub mod auth_site;
+pub mod token_ledger;
+pub mod outbox_event;
+pub mod alert;
+pub mod notification;
+pub mod plan;
+pub mod job_control;
+pub mod key_expiry;
+pub mod user_api_key;
+pub mod openai_pool_key;
+pub mod model_price;
@@ -0,0 +1,92 @@
+use rbatis::{impl_select, rbdc::datetime::DateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    db,
+    error::{Error, Result as MyResult},
+    utils::validate,
+};
+
+/// 模型价格, 从 effective_from 起生效, 直到同一模型的下一条价格生效; effective_from 为 UTC 时间
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ModelPrice {
+    pub id: Option<u64>,
+    pub model: Option<String>,
+    /// 每个 prompt token 折算的计费 tokens
+    pub prompt_multiplier: Option<f64>,
+    /// 每个 completion token 折算的计费 tokens
+    pub completion_multiplier: Option<f64>,
+    #[schema(value_type = Option<String>)]
+    pub effective_from: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+}
+
+rbatis::crud!(ModelPrice {});
+
+impl_select!(ModelPrice{select_effective(model:&str, at:&str) -> Option => "`where model = #{model} and effective_from <= #{at} order by effective_from desc limit 1`"});
+impl_select!(ModelPrice{select_current() => "`where effective_from <= utc_timestamp() and not exists (select 1 from model_price p where p.model = model_price.model and p.effective_from <= utc_timestamp() and p.effective_from > model_price.effective_from) order by model`"});
+impl_select!(ModelPrice{select_history(model:&str) => "`where model = #{model} order by effective_from desc`"});
+impl_select!(ModelPrice{select_all_history() => "`order by model, effective_from desc`"});
+
+impl ModelPrice {
+    /// 新增价格, effective_from (UTC) 为空时立即生效, 返回 id
+    ///
+    /// 不能早于当前时间, 已计费的用量不会按新价格重算
+    pub async fn add(
+        model: &str,
+        prompt_multiplier: f64,
+        completion_multiplier: f64,
+        effective_from: Option<&str>,
+    ) -> MyResult<u64> {
+        let sql = "insert into model_price (model, prompt_multiplier, completion_multiplier, effective_from, created_time) \
+                   select ?, ?, ?, coalesce(?, utc_timestamp()), now() from dual \
+                   where coalesce(?, utc_timestamp()) >= utc_timestamp() - interval 1 minute \
+                   and not exists (select 1 from model_price where model = ? and effective_from = coalesce(?, utc_timestamp()))";
+        let result = sqlx::query(sql)
+            .bind(model)
+            .bind(prompt_multiplier)
+            .bind(completion_multiplier)
+            .bind(effective_from)
+            .bind(effective_from)
+            .bind(model)
+            .bind(effective_from)
+            .execute(&db::get_pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            // 区分参数错误和与已有价格冲突
+            let past: i64 =
+                sqlx::query_scalar("select coalesce(?, utc_timestamp()) < utc_timestamp() - interval 1 minute")
+                    .bind(effective_from)
+                    .fetch_one(&db::get_pool())
+                    .await?;
+            if past == 1 {
+                return Err(validate::field_error("effective_from", "past", "effective_from 不能早于当前时间"));
+            }
+            return Err(Error::Conflict(format!("该时间已有价格: {}", model)));
+        }
+        Ok(result.last_insert_id())
+    }
+
+    /// at 时刻生效的价格
+    pub async fn find_effective(model: &str, at: &str) -> MyResult<Option<ModelPrice>> {
+        let x = ModelPrice::select_effective(&mut db::get_rb(), model, at).await?;
+        Ok(x)
+    }
+
+    /// 各模型当前生效的价格
+    pub async fn current() -> MyResult<Vec<ModelPrice>> {
+        let x = ModelPrice::select_current(&mut db::get_rb()).await?;
+        Ok(x)
+    }
+
+    /// 价格历史, 包含尚未生效的价格, 按生效时间倒序; model 为空时返回所有模型
+    pub async fn history(model: Option<&str>) -> MyResult<Vec<ModelPrice>> {
+        let x = match model {
+            Some(model) => ModelPrice::select_history(&mut db::get_rb(), model).await?,
+            None => ModelPrice::select_all_history(&mut db::get_rb()).await?,
+        };
+        Ok(x)
+    }
+}
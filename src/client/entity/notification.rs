@@ -0,0 +1,97 @@
+use rbatis::rbdc::datetime::DateTime;
+use rbs::Value;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    db,
+    error::Result as MyResult,
+    notify::Message,
+    utils::page::{Page, PageQuery, PageSpec},
+};
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_FAILED: &str = "failed";
+
+/// 通知投递记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Notification {
+    pub id: Option<u64>,
+    pub user_id: Option<u64>,
+    pub channel: Option<String>,
+    pub recipient: Option<String>,
+    pub template: Option<String>,
+    pub language: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    pub status: Option<String>,
+    pub attempts: Option<u32>,
+    pub last_error: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub sent_time: Option<DateTime>,
+}
+
+rbatis::crud!(Notification {});
+
+const PAGE_SPEC: PageSpec = PageSpec {
+    table: "notification",
+    sorts: &["id", "created_time"],
+    filters: &["channel", "status", "template"],
+};
+
+impl Notification {
+    /// 投递前记录, 返回记录 id
+    pub async fn create(
+        user_id: Option<u64>,
+        channel: &str,
+        recipient: &str,
+        message: &Message,
+    ) -> MyResult<u64> {
+        let sql = "insert into notification (user_id, channel, recipient, template, language, subject, body, status, attempts, created_time) \
+                   values (?, ?, ?, ?, ?, ?, ?, ?, 0, now())";
+        let result = sqlx::query(sql)
+            .bind(user_id)
+            .bind(channel)
+            .bind(recipient)
+            .bind(&message.template)
+            .bind(message.language.as_str())
+            .bind(&message.subject)
+            .bind(&message.body)
+            .bind(STATUS_PENDING)
+            .execute(&db::get_pool())
+            .await?;
+        Ok(result.last_insert_id())
+    }
+
+    /// 记录投递结果, error 为空表示成功
+    pub async fn finish(id: u64, attempts: u32, error: Option<String>) -> MyResult<()> {
+        let (status, sql) = match error {
+            None => (
+                STATUS_SENT,
+                "update notification set status = ?, attempts = ?, last_error = ?, sent_time = now() where id = ?",
+            ),
+            Some(_) => (
+                STATUS_FAILED,
+                "update notification set status = ?, attempts = ?, last_error = ? where id = ?",
+            ),
+        };
+        let error = error.map(|e| e.chars().take(512).collect::<String>());
+        sqlx::query(sql)
+            .bind(status)
+            .bind(attempts)
+            .bind(error)
+            .bind(id)
+            .execute(&db::get_pool())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn page_by_user_id(user_id: u64, query: &PageQuery) -> MyResult<Page<Notification>> {
+        query
+            .fetch(&PAGE_SPEC, vec![("user_id", Value::U64(user_id))], |n: &Notification| n.id)
+            .await
+    }
+}
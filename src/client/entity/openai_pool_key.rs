@@ -0,0 +1,148 @@
+use rbatis::rbdc::datetime::DateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Row, Transaction};
+
+use crate::{
+    db,
+    error::{Error, Result as MyResult},
+};
+
+/// 共享 OpenAI key, 服务没有自己 openai_key 的用户
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpenaiPoolKey {
+    pub id: Option<u64>,
+    pub name: Option<String>,
+    pub api_key: Option<String>,
+    /// 加权轮询的权重, 0 表示不参与选择
+    pub weight: Option<u32>,
+    /// 每月最多服务的 tokens, 为空表示不限
+    pub monthly_token_limit: Option<u64>,
+    /// period_begin 所在月已服务的 tokens
+    pub tokens_used: Option<u64>,
+    pub period_begin: Option<DateTime>,
+    pub healthy: Option<u64>,
+    pub health_reason: Option<String>,
+    pub active: Option<u64>,
+    pub created_time: Option<DateTime>,
+    pub updated_time: Option<DateTime>,
+}
+
+rbatis::crud!(OpenaiPoolKey {});
+
+impl OpenaiPoolKey {
+    /// 新增共享 key, 返回 id
+    pub async fn add(key: &OpenaiPoolKey) -> MyResult<u64> {
+        if OpenaiPoolKey::select_by_column(&mut db::get_rb(), "name", key.name.clone())
+            .await?
+            .into_iter()
+            .next()
+            .is_some()
+        {
+            return Err(Error::Conflict(format!(
+                "共享 key 已存在: {}",
+                key.name.as_deref().unwrap_or_default()
+            )));
+        }
+        let result = OpenaiPoolKey::insert(&mut db::get_rb(), key).await?;
+        result
+            .last_insert_id
+            .as_u64()
+            .ok_or_else(|| Error::BizError("无法获取新共享 key id".to_string()))
+    }
+
+    pub async fn all() -> MyResult<Vec<OpenaiPoolKey>> {
+        let x = OpenaiPoolKey::select_all(&mut db::get_rb()).await?;
+        Ok(x)
+    }
+
+    pub async fn find_by_id(id: u64) -> MyResult<Option<OpenaiPoolKey>> {
+        let x = OpenaiPoolKey::select_by_column(&mut db::get_rb(), "id", id).await?;
+        Ok(x.into_iter().next())
+    }
+
+    /// 可参与选择的 key: 有效、健康、权重大于 0 且本月未超限, 返回 (id, 权重)
+    pub async fn available(period_begin: &str) -> MyResult<Vec<(u64, u32)>> {
+        let sql = "select id, weight from openai_pool_key \
+                   where active = 1 and healthy = 1 and weight > 0 \
+                   and (monthly_token_limit is null or period_begin is null or period_begin < ? \
+                   or tokens_used < monthly_token_limit) order by id";
+        let rows = sqlx::query(sql)
+            .bind(period_begin)
+            .fetch_all(&db::get_pool())
+            .await?;
+        let mut keys = Vec::with_capacity(rows.len());
+        for row in rows {
+            keys.push((row.try_get("id")?, row.try_get("weight")?));
+        }
+        Ok(keys)
+    }
+
+    /// 修改权重、月度上限及状态, 为空的字段不变; monthly_token_limit 为 0 表示不限
+    pub async fn update_settings(
+        id: u64,
+        weight: Option<u32>,
+        monthly_token_limit: Option<u64>,
+        active: Option<u64>,
+    ) -> MyResult<()> {
+        let sql = "update openai_pool_key set weight = coalesce(?, weight), \
+                   monthly_token_limit = if(? is null, monthly_token_limit, nullif(?, 0)), \
+                   active = coalesce(?, active), updated_time = now() where id = ?";
+        let result = sqlx::query(sql)
+            .bind(weight)
+            .bind(monthly_token_limit)
+            .bind(monthly_token_limit)
+            .bind(active)
+            .bind(id)
+            .execute(&db::get_pool())
+            .await?;
+        if result.rows_affected() == 0 && OpenaiPoolKey::find_by_id(id).await?.is_none() {
+            return Err(Error::NotFound(format!("openai pool key {}", id)));
+        }
+        Ok(())
+    }
+
+    /// 标记健康状态, 不健康的 key 不参与选择
+    pub async fn set_health(id: u64, healthy: bool, reason: Option<&str>) -> MyResult<()> {
+        let result = sqlx::query(
+            "update openai_pool_key set healthy = ?, health_reason = ?, updated_time = now() where id = ?",
+        )
+        .bind(healthy as u8)
+        .bind(reason)
+        .bind(id)
+        .execute(&db::get_pool())
+        .await?;
+        if result.rows_affected() == 0 && OpenaiPoolKey::find_by_id(id).await?.is_none() {
+            return Err(Error::NotFound(format!("openai pool key {}", id)));
+        }
+        Ok(())
+    }
+
+    /// 在调用方的事务中确认 key 存在, 用于校验用量事件带回的 id
+    pub async fn exists(tx: &mut Transaction<'_, MySql>, id: u64) -> MyResult<bool> {
+        let row = sqlx::query("select id from openai_pool_key where id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    /// 在调用方的事务中累加 key 本月服务的 tokens, 跨月时从 0 重新计数; id 须已由 `exists` 校验
+    pub async fn record_usage(
+        tx: &mut Transaction<'_, MySql>,
+        id: u64,
+        tokens: u64,
+        period_begin: &str,
+    ) -> MyResult<()> {
+        let sql = "update openai_pool_key set \
+                   tokens_used = if(period_begin = ?, tokens_used + ?, ?), period_begin = ? where id = ?";
+        sqlx::query(sql)
+            .bind(period_begin)
+            .bind(tokens)
+            .bind(tokens)
+            .bind(period_begin)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
+}
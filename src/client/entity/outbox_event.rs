@@ -0,0 +1,76 @@
+use rbatis::{executor::Executor, rbdc::datetime::DateTime};
+use rbs::Value;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Transaction};
+
+use crate::{db, error::Result as MyResult};
+
+// 事件中的 key 只带脱敏后的前缀 (*_hint), 明文不进入消息队列
+pub const USER_CREATED: &str = "user.created";
+pub const USER_DEACTIVATED: &str = "user.deactivated";
+pub const KEY_ROTATED: &str = "key.rotated";
+pub const SITE_ADDED: &str = "site.added";
+pub const SITE_DEACTIVATED: &str = "site.deactivated";
+pub const BALANCE_CHANGED: &str = "balance.changed";
+pub const API_KEY_CREATED: &str = "api_key.created";
+pub const API_KEY_REVOKED: &str = "api_key.revoked";
+
+const INSERT_SQL: &str =
+    "insert into outbox_event (event_type, aggregate_id, payload, attempts, created_time) values (?, ?, ?, 0, now())";
+
+/// 待发布的领域事件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    pub id: Option<u64>,
+    pub event_type: Option<String>,
+    pub aggregate_id: Option<u64>,
+    pub payload: Option<String>,
+    pub attempts: Option<u32>,
+    pub created_time: Option<DateTime>,
+    pub published_time: Option<DateTime>,
+}
+
+rbatis::crud!(OutboxEvent {});
+
+impl OutboxEvent {
+    /// 在调用方的 sqlx 事务中写入事件
+    pub async fn record<T: Serialize>(
+        tx: &mut Transaction<'_, MySql>,
+        event_type: &str,
+        aggregate_id: u64,
+        payload: &T,
+    ) -> MyResult<()> {
+        sqlx::query(INSERT_SQL)
+            .bind(event_type)
+            .bind(aggregate_id)
+            .bind(serde_json::to_string(payload)?)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
+
+    /// 在调用方的 rbatis 连接或事务中写入事件
+    pub async fn record_in<T: Serialize>(
+        executor: &mut dyn Executor,
+        event_type: &str,
+        aggregate_id: u64,
+        payload: &T,
+    ) -> MyResult<()> {
+        let args = vec![
+            Value::String(event_type.to_string()),
+            Value::U64(aggregate_id),
+            Value::String(serde_json::to_string(payload)?),
+        ];
+        executor.exec(INSERT_SQL, args).await?;
+        Ok(())
+    }
+
+    /// 删除 before 之前已发布的事件, 返回删除条数
+    pub async fn purge_published(before: &str) -> MyResult<u64> {
+        let result = sqlx::query("delete from outbox_event where published_time is not null and published_time < ?")
+            .bind(before)
+            .execute(&db::get_pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
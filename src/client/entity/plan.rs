@@ -0,0 +1,120 @@
+use rbatis::rbdc::datetime::DateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use utoipa::ToSchema;
+
+use crate::{
+    db,
+    error::{Error, Result as MyResult},
+    setting::Limit,
+};
+
+/// 订阅套餐
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Plan {
+    pub id: Option<u64>,
+    pub name: Option<String>,
+    /// 每月发放的 tokens
+    pub monthly_tokens: Option<u64>,
+    /// 月费, 单位分
+    pub price_cents: Option<u64>,
+    /// 为空时使用默认限流
+    pub rate_limit_capacity: Option<u64>,
+    pub rate_limit_refill_per_second: Option<f64>,
+    pub active: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub updated_time: Option<DateTime>,
+}
+
+rbatis::crud!(Plan {});
+
+impl Plan {
+    /// 新增套餐, 返回套餐 id
+    pub async fn add(plan: &Plan) -> MyResult<u64> {
+        if Plan::select_by_column(&mut db::get_rb(), "name", plan.name.clone())
+            .await?
+            .into_iter()
+            .next()
+            .is_some()
+        {
+            return Err(Error::Conflict(format!("套餐已存在: {}", plan.name.as_deref().unwrap_or_default())));
+        }
+        let result = Plan::insert(&mut db::get_rb(), plan).await?;
+        result
+            .last_insert_id
+            .as_u64()
+            .ok_or_else(|| Error::BizError("无法获取新套餐 id".to_string()))
+    }
+
+    pub async fn all() -> MyResult<Vec<Plan>> {
+        let x = Plan::select_all(&mut db::get_rb()).await?;
+        Ok(x)
+    }
+
+    pub async fn find_by_id(id: u64) -> MyResult<Option<Plan>> {
+        let x = Plan::select_by_column(&mut db::get_rb(), "id", id).await?;
+        Ok(x.into_iter().next())
+    }
+
+    /// 本月剩余比例为 remaining 时, 从 old_monthly 换到 new_monthly 需要补发 (正) 或扣回 (负) 的 tokens
+    pub fn prorate(old_monthly: u64, new_monthly: u64, remaining: f64) -> i64 {
+        let remaining = remaining.clamp(0.0, 1.0);
+        ((new_monthly as f64 - old_monthly as f64) * remaining).round() as i64
+    }
+
+    /// 套餐限流额度, 未配置时返回 None
+    pub fn limit(&self) -> Option<Limit> {
+        Some(Limit {
+            capacity: self.rate_limit_capacity?,
+            refill_per_second: self.rate_limit_refill_per_second?,
+        })
+    }
+
+    /// 有效用户的套餐限流额度, 按用户 id; 同一用户的所有 key 共用一个额度
+    pub async fn user_limits() -> MyResult<Vec<(u64, Limit)>> {
+        let sql = "select u.id, p.rate_limit_capacity, p.rate_limit_refill_per_second \
+                   from user u join plan p on u.plan_id = p.id \
+                   where u.active = 1 \
+                   and p.rate_limit_capacity is not null and p.rate_limit_refill_per_second is not null";
+        let rows = sqlx::query(sql).fetch_all(&db::get_pool()).await?;
+        let mut limits = Vec::with_capacity(rows.len());
+        for row in rows {
+            limits.push((
+                row.try_get("id")?,
+                Limit {
+                    capacity: row.try_get("rate_limit_capacity")?,
+                    refill_per_second: row.try_get("rate_limit_refill_per_second")?,
+                },
+            ));
+        }
+        Ok(limits)
+    }
+
+    /// 本月未发放额度的套餐用户 id
+    pub async fn users_due(period_begin: &str) -> MyResult<Vec<u64>> {
+        let sql = "select id from user where active = 1 and plan_id is not null \
+                   and (plan_period_begin is null or plan_period_begin < ?)";
+        let rows = sqlx::query(sql).bind(period_begin).fetch_all(&db::get_pool()).await?;
+        let mut ids = Vec::with_capacity(rows.len());
+        for row in rows {
+            ids.push(row.try_get("id")?);
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prorate() {
+        assert_eq!(Plan::prorate(1000, 3000, 0.5), 1000);
+        assert_eq!(Plan::prorate(3000, 1000, 0.5), -1000);
+        assert_eq!(Plan::prorate(0, 3000, 1.0), 3000);
+        assert_eq!(Plan::prorate(1000, 3000, 1.5), 2000);
+        assert_eq!(Plan::prorate(1000, 1000, 0.3), 0);
+    }
+}
@@ -0,0 +1,130 @@
+use rbatis::rbdc::datetime::DateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Row, Transaction};
+use utoipa::ToSchema;
+
+use crate::{db, error::Result as MyResult};
+
+/// 管理员发放
+pub const KIND_GRANT: &str = "grant";
+/// 用量扣减
+pub const KIND_DEBIT: &str = "debit";
+/// 套餐月度额度
+pub const KIND_PLAN_ALLOWANCE: &str = "plan_allowance";
+/// 更换套餐的折算
+pub const KIND_PLAN_PRORATION: &str = "plan_proration";
+
+/// tokens 变动流水
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenLedger {
+    pub id: Option<u64>,
+    pub user_id: Option<u64>,
+    pub amount: Option<i64>,
+    pub balance_after: Option<u64>,
+    pub kind: Option<String>,
+    pub reason: Option<String>,
+    /// 服务该次用量的共享 OpenAI key
+    pub pool_key_id: Option<u64>,
+    /// 用量消息的唯一标识, 用于扣减去重
+    pub event_key: Option<String>,
+    pub created_time: Option<DateTime>,
+}
+
+rbatis::crud!(TokenLedger {});
+
+/// 用量扣减流水的来源, 其他流水为空
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageSource<'a> {
+    /// 服务该次用量的共享 OpenAI key
+    pub pool_key_id: Option<u64>,
+    /// 用量消息的唯一标识 (topic:partition:offset), 同一标识只扣减一次
+    pub event_key: Option<&'a str>,
+}
+
+/// 按天汇总的用量
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DailyUsage {
+    /// 日期, 格式 yyyy-MM-dd
+    pub day: String,
+    pub tokens: u64,
+}
+
+impl TokenLedger {
+    /// 在调用方的事务中记录一条流水
+    pub async fn record(
+        tx: &mut Transaction<'_, MySql>,
+        user_id: u64,
+        amount: i64,
+        balance_after: u64,
+        kind: &str,
+        reason: Option<&str>,
+        source: UsageSource<'_>,
+    ) -> MyResult<()> {
+        let sql = "insert into token_ledger (user_id, amount, balance_after, kind, reason, pool_key_id, event_key, created_time) \
+                   values (?, ?, ?, ?, ?, ?, ?, now())";
+        sqlx::query(sql)
+            .bind(user_id)
+            .bind(amount)
+            .bind(balance_after)
+            .bind(kind)
+            .bind(reason)
+            .bind(source.pool_key_id)
+            .bind(source.event_key)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
+
+    /// 该用量消息是否已经扣减过
+    pub async fn has_event(event_key: &str) -> MyResult<bool> {
+        let row = sqlx::query("select 1 from token_ledger where event_key = ? limit 1")
+            .bind(event_key)
+            .fetch_optional(&db::get_pool())
+            .await?;
+        Ok(row.is_some())
+    }
+
+    /// 最近 days 天每天扣减的 tokens, 没有用量的日期不返回
+    pub async fn daily_usage(user_id: u64, days: u32) -> MyResult<Vec<DailyUsage>> {
+        let sql = "select date_format(created_time, '%Y-%m-%d') as day, cast(-sum(amount) as signed) as tokens \
+                   from token_ledger where user_id = ? and kind = ? and created_time >= date_sub(curdate(), interval ? day) \
+                   group by day order by day";
+        let rows = sqlx::query(sql)
+            .bind(user_id)
+            .bind(KIND_DEBIT)
+            .bind(days)
+            .fetch_all(&db::get_pool())
+            .await?;
+        let mut usage = Vec::with_capacity(rows.len());
+        for row in rows {
+            usage.push(DailyUsage {
+                day: row.try_get("day")?,
+                tokens: row.try_get::<i64, _>("tokens")?.max(0) as u64,
+            });
+        }
+        Ok(usage)
+    }
+
+    /// since 之后的 (发放 tokens, 扣减 tokens) 合计
+    pub async fn totals_since(user_id: u64, since: &str) -> MyResult<(u64, u64)> {
+        // 发放、套餐额度及折算都计入 granted
+        let sql = "select cast(coalesce(sum(case when kind <> ? then amount else 0 end), 0) as signed) as granted, \
+                   cast(coalesce(-sum(case when kind = ? then amount else 0 end), 0) as signed) as used \
+                   from token_ledger where user_id = ? and created_time >= ?";
+        let row = sqlx::query(sql)
+            .bind(KIND_DEBIT)
+            .bind(KIND_DEBIT)
+            .bind(user_id)
+            .bind(since)
+            .fetch_one(&db::get_pool())
+            .await?;
+        let granted = row.try_get::<i64, _>("granted")?.max(0) as u64;
+        let used = row.try_get::<i64, _>("used")?.max(0) as u64;
+        Ok((granted, used))
+    }
+
+    pub async fn find_by_user_id(user_id: u64) -> MyResult<Vec<TokenLedger>> {
+        let x = TokenLedger::select_by_column(&mut db::get_rb(), "user_id", user_id).await?;
+        Ok(x)
+    }
+}
@@ -1,7 +1,29 @@
-use crate::{client::model::user_model::AddUser, db};
-use log::info;
-use rbatis::{impl_select, rbdc::datetime::DateTime};
+use crate::{
+    client::{
+        entity::{
+            key_expiry,
+            outbox_event::{self, OutboxEvent},
+            openai_pool_key::OpenaiPoolKey,
+            plan::Plan,
+            token_ledger::{self, TokenLedger, UsageSource},
+            user_api_key::{self, UserApiKey},
+        },
+        model::{key_expiry_model::ExpiryChange, usage_model::UsageEvent, user_model::AddUser},
+        service::key_service,
+    },
+    db,
+    error::Error,
+    middleware::mask_key,
+    utils::{
+        date_utils,
+        page::{Page, PageQuery, PageSpec},
+        uuid, validate,
+    },
+};
+use log::{info, warn};
+use rbatis::{executor::Executor, impl_select, rbdc::datetime::DateTime};
 use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Row, Transaction};
 
 use crate::error::Result as MyResult;
 
@@ -13,7 +35,14 @@ pub struct User {
 
     pub tokens: Option<u64>,
     pub summary_key: Option<String>,
+    /// summary_key 过期时间, 为空表示永不过期
+    pub summary_key_expires_at: Option<DateTime>,
     pub openai_key: Option<String>,
+    /// 接收通知的邮箱
+    pub email: Option<String>,
+    pub plan_id: Option<u64>,
+    /// 最近一次发放套餐额度的月初
+    pub plan_period_begin: Option<DateTime>,
 
     pub active: Option<u64>,
     pub created_time: Option<DateTime>,
@@ -21,8 +50,19 @@ pub struct User {
 }
 
 rbatis::crud!(User {}, "user");
+
+const PAGE_SPEC: PageSpec = PageSpec {
+    table: "user",
+    sorts: &["id", "account", "tokens", "created_time", "updated_time"],
+    filters: &["account", "active", "summary_key"],
+};
 impl_select!(User{select_one_by_account(account:&str) -> Option => "`where account = #{account}`"});
 
+/// tokens 转为流水金额, 超出 i64 范围时报错而不是截断
+fn to_amount(tokens: u64) -> MyResult<i64> {
+    i64::try_from(tokens).map_err(|_| Error::BizError(format!("tokens 超出范围: {}", tokens)))
+}
+
 impl User {
     pub fn new() -> User {
         User {
@@ -31,7 +71,11 @@ impl User {
             password: None,
             tokens: None,
             summary_key: None,
+            summary_key_expires_at: None,
             openai_key: None,
+            email: None,
+            plan_id: None,
+            plan_period_begin: None,
             active: None,
             created_time: None,
             updated_time: None,
@@ -39,12 +83,43 @@ impl User {
     }
 
     pub async fn add_user(add_user: AddUser) -> MyResult<()> {
-        info!("add user: {:?}", add_user);
-        let user = add_user.into();
-        User::insert(&mut db::get_rb(), &user).await?;
+        let rb = db::get_rb();
+        let mut tx = rb.acquire_begin().await?;
+        if let Err(e) = User::add_user_in(&mut tx, add_user).await {
+            if let Err(rollback_err) = tx.rollback().await {
+                warn!("add user rollback error: {}", rollback_err);
+            }
+            return Err(e);
+        }
+        tx.commit().await?;
         Ok(())
     }
 
+    /// 在指定的连接或事务中新增用户, 返回新用户 id
+    pub async fn add_user_in(executor: &mut dyn Executor, add_user: AddUser) -> MyResult<u64> {
+        validate::validate(&add_user)?;
+        info!("add user: {:?}", add_user);
+        let user: User = add_user.into();
+        User::insert(executor, &user).await?;
+
+        let account = user.account.unwrap_or_default();
+        let user = User::select_one_by_account(executor, &account)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("user {}", account)))?;
+        let user_id = user.id.unwrap_or_default();
+        let payload = serde_json::json!({
+            "user_id": user_id,
+            "account": user.account,
+            "summary_key_hint": user.summary_key.as_deref().map(mask_key),
+            "tokens": user.tokens,
+        });
+        OutboxEvent::record_in(executor, outbox_event::USER_CREATED, user_id, &payload).await?;
+        if let Some(summary_key) = user.summary_key.as_deref().filter(|k| !k.is_empty()) {
+            UserApiKey::add_in(executor, user_id, user_api_key::DEFAULT_NAME, summary_key, None).await?;
+        }
+        Ok(user_id)
+    }
+
     pub async fn find_by_account(account: &str) -> MyResult<Option<User>> {
         let x = User::select_one_by_account(&mut db::get_rb(), account).await?;
         Ok(x)
@@ -54,4 +129,361 @@ impl User {
         let x = User::select_all(&mut db::get_rb()).await?;
         Ok(x)
     }
+
+    pub async fn page(query: &PageQuery) -> MyResult<Page<User>> {
+        query.fetch(&PAGE_SPEC, vec![], |u: &User| u.id).await
+    }
+
+    pub async fn find_by_id(id: u64) -> MyResult<Option<User>> {
+        let x = User::select_by_column(&mut db::get_rb(), "id", id).await?;
+        Ok(x.into_iter().next())
+    }
+
+    /// 锁定用户行, 返回 (用户 id, 当前 tokens)
+    async fn lock_balance(
+        tx: &mut Transaction<'_, MySql>,
+        column: &str,
+        value: &str,
+    ) -> MyResult<Option<(u64, u64)>> {
+        let sql = format!("select id, tokens from user where {} = ? for update", column);
+        let row = sqlx::query(&sql).bind(value).fetch_optional(&mut *tx).await?;
+        match row {
+            Some(row) => Ok(Some((
+                row.try_get("id")?,
+                row.try_get::<Option<u64>, _>("tokens")?.unwrap_or(0),
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// 在事务中修改 tokens 并记录流水, 返回变动后的余额
+    async fn change_tokens(
+        tx: &mut Transaction<'_, MySql>,
+        user_id: u64,
+        balance: u64,
+        amount: i64,
+        kind: &str,
+        reason: Option<&str>,
+        source: UsageSource<'_>,
+    ) -> MyResult<u64> {
+        let balance_after = to_amount(balance)?
+            .checked_add(amount)
+            .ok_or_else(|| Error::BizError(format!("用户 {} tokens 超出范围", user_id)))?
+            .max(0) as u64;
+        // 扣到 0 时实际变动小于请求的数量, 流水和事件记录实际变动
+        let applied = to_amount(balance_after)? - to_amount(balance)?;
+        sqlx::query("update user set tokens = ?, updated_time = now() where id = ?")
+            .bind(balance_after)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        TokenLedger::record(tx, user_id, applied, balance_after, kind, reason, source).await?;
+        let payload = serde_json::json!({
+            "user_id": user_id,
+            "amount": applied,
+            "balance": balance_after,
+            "kind": kind,
+        });
+        OutboxEvent::record(tx, outbox_event::BALANCE_CHANGED, user_id, &payload).await?;
+        Ok(balance_after)
+    }
+
+    /// 校验用量事件带回的共享 key id: 不存在时拒绝该事件; 用户有自己的 openai_key 时不应由共享 key 服务, 忽略该 id
+    async fn usage_pool_key(
+        tx: &mut Transaction<'_, MySql>,
+        user_id: u64,
+        pool_key_id: Option<u64>,
+    ) -> MyResult<Option<u64>> {
+        let pool_key_id = match pool_key_id {
+            Some(pool_key_id) => pool_key_id,
+            None => return Ok(None),
+        };
+        let row = sqlx::query("select openai_key from user where id = ?")
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let openai_key: Option<String> = row.try_get("openai_key")?;
+        if openai_key.map_or(false, |key| !key.is_empty()) {
+            warn!("pool key {} ignored, user {} has own openai_key", pool_key_id, user_id);
+            return Ok(None);
+        }
+        if !OpenaiPoolKey::exists(tx, pool_key_id).await? {
+            return Err(Error::invalid_param(format!("共享 key 不存在: {}", pool_key_id)));
+        }
+        Ok(Some(pool_key_id))
+    }
+
+    /// 按用量事件的 api key 扣减计费 tokens, 不足时扣到 0; 带 site_summary_key 时累加站点用量
+    ///
+    /// 用量已经发生, 所以 key 已吊销或过期也照常扣减; 由共享 OpenAI key 服务时记录到流水 (id 无效时拒绝整个事件),
+    /// 并按原始 tokens 累加 key 的月用量。event_key 记录到流水, 同一消息重复扣减时流水的唯一索引冲突而回滚。
+    /// 返回用户 id, 未找到 key 时返回 None
+    pub async fn debit_tokens(event: &UsageEvent, tokens: u64, event_key: &str) -> MyResult<Option<u64>> {
+        let mut tx = db::get_pool().begin().await?;
+        let (key_id, user_id) = match UserApiKey::find_owner(&mut tx, &event.summary_key).await? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let (user_id, balance) = match User::lock_balance(&mut tx, "id", &user_id.to_string()).await? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let pool_key_id = User::usage_pool_key(&mut tx, user_id, event.pool_key_id).await?;
+        let debit = to_amount(tokens.min(balance))?;
+        let reason = event
+            .model
+            .as_deref()
+            .map(|model| format!("{} {}+{}", model, event.prompt_tokens, event.completion_tokens));
+        User::change_tokens(
+            &mut tx,
+            user_id,
+            balance,
+            -debit,
+            token_ledger::KIND_DEBIT,
+            reason.as_deref(),
+            UsageSource {
+                pool_key_id,
+                event_key: Some(event_key),
+            },
+        )
+        .await?;
+        let month_begin = date_utils::get_current_month_begin();
+        if let Some(pool_key_id) = pool_key_id {
+            let raw_tokens = event
+                .total_tokens
+                .max(event.prompt_tokens.saturating_add(event.completion_tokens));
+            OpenaiPoolKey::record_usage(&mut tx, pool_key_id, raw_tokens, &month_begin).await?;
+        }
+        if let Some(site_summary_key) = event.site_summary_key.as_deref() {
+            // 站点用量按月计, 跨月后第一次扣减从 0 重新计数
+            let sql = "update auth_site set tokens_used = if(period_begin = ?, tokens_used + ?, ?), period_begin = ? \
+                       where user_id = ? and site_summary_key = ?";
+            sqlx::query(sql)
+                .bind(&month_begin)
+                .bind(tokens)
+                .bind(tokens)
+                .bind(&month_begin)
+                .bind(user_id)
+                .bind(site_summary_key)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        // 只改变余额, 不失效缓存; 每次用量都失效会让缓存形同虚设
+        key_service::touch(key_id);
+        Ok(Some(user_id))
+    }
+
+    /// 给用户发放 tokens, 返回发放后的余额
+    pub async fn grant_tokens(user_id: u64, tokens: u64, reason: Option<&str>) -> MyResult<u64> {
+        let mut tx = db::get_pool().begin().await?;
+        let (user_id, balance) = User::lock_balance(&mut tx, "id", &user_id.to_string())
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("user {}", user_id)))?;
+        let balance_after = User::change_tokens(
+            &mut tx,
+            user_id,
+            balance,
+            to_amount(tokens)?,
+            token_ledger::KIND_GRANT,
+            reason,
+            UsageSource::default(),
+        )
+        .await?;
+        tx.commit().await?;
+        key_service::invalidate_user(user_id);
+        Ok(balance_after)
+    }
+
+    /// 发放 period_begin 所在月的套餐额度, 返回发放后的余额; 已发放或没有套餐时返回 None
+    pub async fn grant_plan_allowance(user_id: u64, period_begin: &str) -> MyResult<Option<u64>> {
+        let mut tx = db::get_pool().begin().await?;
+        let sql = "select u.tokens, p.name, p.monthly_tokens from user u join plan p on u.plan_id = p.id \
+                   where u.id = ? and (u.plan_period_begin is null or u.plan_period_begin < ?) for update";
+        let row = match sqlx::query(sql)
+            .bind(user_id)
+            .bind(period_begin)
+            .fetch_optional(&mut *tx)
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let balance = row.try_get::<Option<u64>, _>("tokens")?.unwrap_or(0);
+        let name: String = row.try_get("name")?;
+        let monthly_tokens: u64 = row.try_get("monthly_tokens")?;
+        let reason = format!("{} {}", name, &period_begin[..7.min(period_begin.len())]);
+        let balance_after = User::change_tokens(
+            &mut tx,
+            user_id,
+            balance,
+            to_amount(monthly_tokens)?,
+            token_ledger::KIND_PLAN_ALLOWANCE,
+            Some(&reason),
+            UsageSource::default(),
+        )
+        .await?;
+        sqlx::query("update user set plan_period_begin = ? where id = ?")
+            .bind(period_begin)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        key_service::invalidate_user(user_id);
+        Ok(Some(balance_after))
+    }
+
+    /// 更换套餐, 按本月剩余比例折算新旧套餐的差额, 返回 (折算的 tokens, 调整后余额)
+    ///
+    /// 本月未发放过旧套餐额度时, 旧套餐按 0 计算; plan 为空表示取消套餐。
+    pub async fn change_plan(
+        user_id: u64,
+        plan: Option<&Plan>,
+        period_begin: &str,
+        remaining: f64,
+    ) -> MyResult<(i64, u64)> {
+        let mut tx = db::get_pool().begin().await?;
+        let sql = "select u.tokens, p.monthly_tokens, u.plan_period_begin >= ? as granted \
+                   from user u left join plan p on u.plan_id = p.id where u.id = ? for update";
+        let row = sqlx::query(sql)
+            .bind(period_begin)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("user {}", user_id)))?;
+        let balance = row.try_get::<Option<u64>, _>("tokens")?.unwrap_or(0);
+        let old_monthly = match row.try_get::<Option<i64>, _>("granted")? {
+            Some(1) => row.try_get::<Option<u64>, _>("monthly_tokens")?.unwrap_or(0),
+            _ => 0,
+        };
+        let new_monthly = plan.and_then(|p| p.monthly_tokens).unwrap_or(0);
+        let adjustment = Plan::prorate(old_monthly, new_monthly, remaining);
+
+        let balance_after = if adjustment == 0 {
+            balance
+        } else {
+            let reason = plan.and_then(|p| p.name.clone()).unwrap_or_else(|| "取消套餐".to_string());
+            User::change_tokens(
+                &mut tx,
+                user_id,
+                balance,
+                adjustment,
+                token_ledger::KIND_PLAN_PRORATION,
+                Some(&reason),
+                UsageSource::default(),
+            )
+            .await?
+        };
+        sqlx::query("update user set plan_id = ?, plan_period_begin = ?, updated_time = now() where id = ?")
+            .bind(plan.and_then(|p| p.id))
+            .bind(plan.map(|_| period_begin))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        key_service::invalidate_user(user_id);
+        Ok((adjustment, balance_after))
+    }
+
+    /// 锁定用户行, 返回当前 summary_key
+    async fn lock_summary_key(tx: &mut Transaction<'_, MySql>, user_id: u64) -> MyResult<Option<String>> {
+        let row = sqlx::query("select summary_key from user where id = ? for update")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("user {}", user_id)))?;
+        Ok(row.try_get("summary_key")?)
+    }
+
+    /// 重新生成 summary_key, 返回新的 key
+    ///
+    /// 对应的 api key 吊销后以同名新建一行, 过期时间不变, 没有时补一个 default key;
+    /// 旧 key 行保留, 旧 key 尚未处理的用量仍能按 key 找到用户并扣减
+    pub async fn rotate_summary_key(user_id: u64) -> MyResult<String> {
+        let mut tx = db::get_pool().begin().await?;
+        let old_summary_key = User::lock_summary_key(&mut tx, user_id).await?;
+        let summary_key = uuid::new_summary_key();
+        sqlx::query("update user set summary_key = ?, updated_time = now() where id = ?")
+            .bind(&summary_key)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let old_key_id: Option<u64> = sqlx::query(
+            "select id from user_api_key where user_id = ? and api_key = ? and revoked_time is null for update",
+        )
+        .bind(user_id)
+        .bind(&old_summary_key)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|row| row.try_get("id"))
+        .transpose()?;
+        match old_key_id {
+            Some(old_key_id) => {
+                sqlx::query("update user_api_key set revoked_time = now(), updated_time = now() where id = ?")
+                    .bind(old_key_id)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query(
+                    "insert into user_api_key (user_id, name, api_key, expires_at, created_time, updated_time) \
+                     select user_id, name, ?, expires_at, now(), now() from user_api_key where id = ?",
+                )
+                .bind(&summary_key)
+                .bind(old_key_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query(
+                    "insert into user_api_key (user_id, name, api_key, created_time, updated_time) \
+                     values (?, ?, ?, now(), now())",
+                )
+                .bind(user_id)
+                .bind(user_api_key::DEFAULT_NAME)
+                .bind(&summary_key)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        let payload = serde_json::json!({
+            "user_id": user_id,
+            "old_summary_key_hint": old_summary_key.as_deref().map(mask_key),
+            "summary_key_hint": mask_key(&summary_key),
+        });
+        OutboxEvent::record(&mut tx, outbox_event::KEY_ROTATED, user_id, &payload).await?;
+        tx.commit().await?;
+        key_service::invalidate_user(user_id);
+        Ok(summary_key)
+    }
+
+    /// 修改 summary_key 过期时间并同步到对应的 api key, 返回修改后的过期时间
+    pub async fn set_key_expiry(user_id: u64, change: &ExpiryChange) -> MyResult<Option<String>> {
+        let expires_at = key_expiry::update("user", "summary_key_expires_at", user_id, change).await?;
+        sqlx::query(
+            "update user_api_key k join user u on u.id = k.user_id and k.api_key = u.summary_key \
+             set k.expires_at = u.summary_key_expires_at, k.updated_time = now() where u.id = ?",
+        )
+        .bind(user_id)
+        .execute(&db::get_pool())
+        .await?;
+        key_service::invalidate_user(user_id);
+        Ok(expires_at)
+    }
+
+    /// 停用用户
+    pub async fn deactivate(user_id: u64) -> MyResult<()> {
+        let mut tx = db::get_pool().begin().await?;
+        let summary_key = User::lock_summary_key(&mut tx, user_id).await?;
+        sqlx::query("update user set active = 0, updated_time = now() where id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let payload = serde_json::json!({
+            "user_id": user_id,
+            "summary_key_hint": summary_key.as_deref().map(mask_key),
+        });
+        OutboxEvent::record(&mut tx, outbox_event::USER_DEACTIVATED, user_id, &payload).await?;
+        tx.commit().await?;
+        key_service::invalidate_user(user_id);
+        Ok(())
+    }
 }
@@ -0,0 +1,254 @@
+use log::{info, warn};
+use rbatis::{executor::Executor, rbdc::datetime::DateTime};
+use rbs::Value;
+use serde::{Deserialize, Serialize};
+use sqlx::{MySql, Row, Transaction};
+use utoipa::ToSchema;
+
+use crate::{
+    client::{
+        entity::{
+            key_expiry,
+            outbox_event::{self, OutboxEvent},
+            user::User,
+        },
+        model::key_expiry_model::ExpiryChange,
+        service::key_service,
+    },
+    db,
+    error::{Error, Result as MyResult},
+    middleware::mask_key,
+    utils::{
+        page::{Page, PageQuery, PageSpec},
+        uuid,
+    },
+};
+
+/// 迁移或新建用户时生成的第一个 key 的名称
+pub const DEFAULT_NAME: &str = "default";
+
+/// 用户的命名 key, 可单独过期和吊销
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UserApiKey {
+    pub id: Option<u64>,
+    pub user_id: Option<u64>,
+    pub name: Option<String>,
+    pub api_key: Option<String>,
+    /// 为空表示永不过期
+    #[schema(value_type = Option<String>)]
+    pub expires_at: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub last_used_time: Option<DateTime>,
+    /// 不为空表示已吊销
+    #[schema(value_type = Option<String>)]
+    pub revoked_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub updated_time: Option<DateTime>,
+}
+
+rbatis::crud!(UserApiKey {});
+
+const PAGE_SPEC: PageSpec = PageSpec {
+    table: "user_api_key",
+    sorts: &["id", "name", "created_time", "last_used_time"],
+    filters: &["name"],
+};
+
+/// 没有对应 key 的 user.summary_key 补为 default key, 与迁移脚本一致
+const BACKFILL_SQL: &str = "insert ignore into user_api_key (user_id, name, api_key, expires_at, created_time, updated_time) \
+    select id, 'default', summary_key, summary_key_expires_at, created_time, now() from user \
+    where summary_key is not null and summary_key <> ''";
+
+impl UserApiKey {
+    /// 在指定的连接或事务中新增 key, 返回新 key 的 id
+    pub async fn add_in(
+        executor: &mut dyn Executor,
+        user_id: u64,
+        name: &str,
+        api_key: &str,
+        expires_at: Option<&str>,
+    ) -> MyResult<u64> {
+        let key = UserApiKey {
+            id: None,
+            user_id: Some(user_id),
+            name: Some(name.to_string()),
+            api_key: Some(api_key.to_string()),
+            expires_at: None,
+            last_used_time: None,
+            revoked_time: None,
+            created_time: Some(DateTime::now()),
+            updated_time: Some(DateTime::now()),
+        };
+        let result = UserApiKey::insert(executor, &key).await?;
+        let key_id = result
+            .last_insert_id
+            .as_u64()
+            .ok_or_else(|| Error::BizError("无法获取新 key id".to_string()))?;
+        if let Some(expires_at) = expires_at {
+            executor
+                .exec(
+                    "update user_api_key set expires_at = ? where id = ?",
+                    vec![Value::String(expires_at.to_string()), Value::U64(key_id)],
+                )
+                .await?;
+        }
+        let payload = serde_json::json!({
+            "key_id": key_id,
+            "user_id": user_id,
+            "name": name,
+            "api_key_hint": mask_key(api_key),
+            "expires_at": expires_at,
+        });
+        OutboxEvent::record_in(executor, outbox_event::API_KEY_CREATED, key_id, &payload).await?;
+        Ok(key_id)
+    }
+
+    /// 给用户新建 key, 同一用户未吊销的 key 不能重名
+    pub async fn add(user_id: u64, name: &str, expires_at: Option<&str>) -> MyResult<UserApiKey> {
+        User::find_by_id(user_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("user {}", user_id)))?;
+        let duplicated = UserApiKey::select_by_column(&mut db::get_rb(), "user_id", user_id)
+            .await?
+            .into_iter()
+            .any(|k| k.revoked_time.is_none() && k.name.as_deref() == Some(name));
+        if duplicated {
+            return Err(Error::Conflict(format!("key 名称已存在: {}", name)));
+        }
+
+        let rb = db::get_rb();
+        let mut tx = rb.acquire_begin().await?;
+        let api_key = uuid::new_summary_key();
+        let key_id = match UserApiKey::add_in(&mut tx, user_id, name, &api_key, expires_at).await {
+            Ok(key_id) => key_id,
+            Err(e) => {
+                if let Err(rollback_err) = tx.rollback().await {
+                    warn!("add api key rollback error: {}", rollback_err);
+                }
+                return Err(e);
+            }
+        };
+        tx.commit().await?;
+        info!("api key {} added for user {}", key_id, user_id);
+        UserApiKey::find_by_id(key_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("api key {}", key_id)))
+    }
+
+    pub async fn find_by_id(id: u64) -> MyResult<Option<UserApiKey>> {
+        let x = UserApiKey::select_by_column(&mut db::get_rb(), "id", id).await?;
+        Ok(x.into_iter().next())
+    }
+
+    pub async fn page_by_user_id(user_id: u64, query: &PageQuery) -> MyResult<Page<UserApiKey>> {
+        query
+            .fetch(
+                &PAGE_SPEC,
+                vec![("user_id", Value::U64(user_id))],
+                |k: &UserApiKey| k.id,
+            )
+            .await
+    }
+
+    /// 按 key 查询未吊销、未过期且用户有效的 key, 返回 (key id, 距过期的秒数, 用户)
+    ///
+    /// 调用方一般通过 key_service 走缓存
+    pub async fn find_active(api_key: &str) -> MyResult<Option<(u64, Option<i64>, User)>> {
+        let sql = "select k.id, k.user_id, timestampdiff(second, now(), k.expires_at) as expires_in \
+                   from user_api_key k join user u on u.id = k.user_id \
+                   where k.api_key = ? and k.revoked_time is null \
+                   and (k.expires_at is null or k.expires_at > now()) and u.active = 1";
+        let row = match sqlx::query(sql)
+            .bind(api_key)
+            .fetch_optional(&db::get_pool())
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let key_id: u64 = row.try_get("id")?;
+        let expires_in: Option<i64> = row.try_get("expires_in")?;
+        let user = User::find_by_id(row.try_get("user_id")?).await?;
+        Ok(user.map(|user| (key_id, expires_in, user)))
+    }
+
+    /// 按 key 查询 (key id, 用户 id), 不检查吊销和过期, 用于记录已发生的用量
+    pub async fn find_owner(
+        tx: &mut Transaction<'_, MySql>,
+        api_key: &str,
+    ) -> MyResult<Option<(u64, u64)>> {
+        let row = sqlx::query("select id, user_id from user_api_key where api_key = ?")
+            .bind(api_key)
+            .fetch_optional(&mut *tx)
+            .await?;
+        match row {
+            Some(row) => Ok(Some((row.try_get("id")?, row.try_get("user_id")?))),
+            None => Ok(None),
+        }
+    }
+
+    /// 吊销 key; 吊销的是 user.summary_key 时一并清空 (列可为空, 见 20240401000300 迁移)
+    pub async fn revoke(id: u64) -> MyResult<()> {
+        let mut tx = db::get_pool().begin().await?;
+        let row = sqlx::query("select user_id, name, api_key, revoked_time is not null as revoked from user_api_key where id = ? for update")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("api key {}", id)))?;
+        if row.try_get::<i64, _>("revoked")? == 1 {
+            return Ok(());
+        }
+        let user_id: u64 = row.try_get("user_id")?;
+        let api_key: String = row.try_get("api_key")?;
+        sqlx::query(
+            "update user_api_key set revoked_time = now(), updated_time = now() where id = ?",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("update user set summary_key = null, updated_time = now() where id = ? and summary_key = ?")
+            .bind(user_id)
+            .bind(&api_key)
+            .execute(&mut *tx)
+            .await?;
+        let payload = serde_json::json!({
+            "key_id": id,
+            "user_id": user_id,
+            "name": row.try_get::<String, _>("name")?,
+            "api_key_hint": mask_key(&api_key),
+        });
+        OutboxEvent::record(&mut tx, outbox_event::API_KEY_REVOKED, id, &payload).await?;
+        tx.commit().await?;
+        key_service::invalidate_key(id);
+        key_service::invalidate_user(user_id);
+        Ok(())
+    }
+
+    /// 修改 key 过期时间, 返回修改后的过期时间
+    pub async fn set_key_expiry(id: u64, change: &ExpiryChange) -> MyResult<Option<String>> {
+        let expires_at = key_expiry::update("user_api_key", "expires_at", id, change).await?;
+        key_service::invalidate_key(id);
+        Ok(expires_at)
+    }
+
+    /// 记录最近使用时间, 只会往后更新
+    pub async fn update_last_used(id: u64, used_time: &str) -> MyResult<()> {
+        sqlx::query(
+            "update user_api_key set last_used_time = greatest(coalesce(last_used_time, ?), ?) where id = ?",
+        )
+        .bind(used_time)
+        .bind(used_time)
+        .bind(id)
+        .execute(&db::get_pool())
+        .await?;
+        Ok(())
+    }
+
+    /// 把没有对应 key 的 user.summary_key 补为 default key, 返回补充的条数
+    pub async fn backfill_legacy(executor: &mut dyn Executor) -> MyResult<u64> {
+        let result = executor.exec(BACKFILL_SQL, vec![]).await?;
+        Ok(result.rows_affected)
+    }
+}
@@ -0,0 +1,45 @@
+use rbatis::rbdc::datetime::DateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+
+use crate::client::entity::alert::{self, AlertRule};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct AddAlertRule {
+    /// 为空时对所有用户生效
+    pub user_id: Option<u64>,
+    /// balance_below / monthly_usage_percent / site_quota_exhausted
+    #[validate(required(message = "kind 不能为空"), custom = "validate_kind")]
+    pub kind: Option<String>,
+    /// balance_below 为 tokens 数, 其余为百分比
+    #[validate(
+        required(message = "threshold 不能为空"),
+        range(min = 1, message = "threshold 必须大于 0")
+    )]
+    pub threshold: Option<u64>,
+}
+
+fn validate_kind(kind: &str) -> Result<(), ValidationError> {
+    if alert::KINDS.contains(&kind) {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("kind");
+        err.message = Some(format!("kind 只能为 {}", alert::KINDS.join(", ")).into());
+        Err(err)
+    }
+}
+
+impl Into<AlertRule> for AddAlertRule {
+    fn into(self) -> AlertRule {
+        AlertRule {
+            id: None,
+            user_id: self.user_id,
+            kind: self.kind,
+            threshold: self.threshold,
+            active: Some(1),
+            created_time: Some(DateTime::now()),
+            updated_time: Some(DateTime::now()),
+        }
+    }
+}
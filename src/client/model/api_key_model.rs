@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct AddApiKey {
+    #[validate(
+        required(message = "name 不能为空"),
+        length(min = 1, max = 64, message = "name 长度必须在 1 到 64 之间")
+    )]
+    pub name: Option<String>,
+    /// 过期时间, yyyy-MM-dd HH:mm:ss; 为空表示永不过期
+    #[validate(custom = "crate::utils::validate::validate_datetime")]
+    pub expires_at: Option<String>,
+}
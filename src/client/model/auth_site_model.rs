@@ -1,14 +1,37 @@
 use rbatis::rbdc::datetime::DateTime;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
 
 use crate::client::entity::auth_site::AuthSite;
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
 pub struct AddAuthSite {
     pub id: Option<u64>,
+    #[validate(
+        required(message = "user_id 不能为空"),
+        range(min = 1, message = "user_id 无效")
+    )]
     pub user_id: Option<u64>,
+    #[validate(
+        required(message = "域名不能为空"),
+        custom = "crate::utils::validate::validate_domain"
+    )]
     pub site_domain: Option<String>,
+    #[validate(
+        length(min = 8, max = 64, message = "site_summary_key 长度须在 8-64 之间"),
+        custom = "crate::utils::validate::validate_key"
+    )]
     pub site_summary_key: Option<String>,
+    /// 每月的 tokens 配额, 为空不限制
+    pub token_quota: Option<u64>,
+}
+
+/// 站点配额
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SiteQuota {
+    /// 每月的 tokens 配额, 为空时不限制
+    pub token_quota: Option<u64>,
 }
 
 impl AddAuthSite {
@@ -18,6 +41,7 @@ impl AddAuthSite {
             user_id: None,
             site_domain: None,
             site_summary_key: None,
+            token_quota: None,
         }
     }
 }
@@ -29,6 +53,10 @@ impl Into<AuthSite> for AddAuthSite {
             user_id: self.user_id,
             site_domain: self.site_domain,
             site_summary_key: self.site_summary_key,
+            site_summary_key_expires_at: None,
+            token_quota: self.token_quota,
+            tokens_used: Some(0),
+            period_begin: None,
             active: Some(1),
             created_time: Some(DateTime::now()),
             updated_time: Some(DateTime::now()),
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::error::{Error, Result};
+
+/// 修改 key 过期时间, expires_at、extend_days 与 never 须且只能设置一个
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct KeyExpiry {
+    /// 新的过期时间, yyyy-MM-dd HH:mm:ss
+    #[validate(custom = "crate::utils::validate::validate_datetime")]
+    pub expires_at: Option<String>,
+    /// 在当前过期时间上延长的天数, 已过期的 key 从现在起算; 永不过期的 key 不变
+    #[validate(range(min = 1, max = 3650, message = "extend_days 须在 1-3650 之间"))]
+    pub extend_days: Option<u32>,
+    /// 为 true 时改为永不过期
+    pub never: Option<bool>,
+}
+
+/// 过期时间的修改方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpiryChange {
+    Never,
+    At(String),
+    ExtendDays(u32),
+}
+
+impl KeyExpiry {
+    pub fn change(&self) -> Result<ExpiryChange> {
+        // 空请求体不能当作永不过期, 以免误清除过期时间
+        match (&self.expires_at, self.extend_days, self.never.unwrap_or(false)) {
+            (Some(expires_at), None, false) => Ok(ExpiryChange::At(expires_at.clone())),
+            (None, Some(days), false) => Ok(ExpiryChange::ExtendDays(days)),
+            (None, None, true) => Ok(ExpiryChange::Never),
+            (None, None, false) => Err(Error::BizError(
+                "须设置 expires_at、extend_days 或 never 之一".to_string(),
+            )),
+            _ => Err(Error::BizError(
+                "expires_at、extend_days 和 never 不能同时设置".to_string(),
+            )),
+        }
+    }
+}
+
+/// 修改过期时间的结果
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeyExpiryResult {
+    pub id: u64,
+    /// 为空表示永不过期
+    pub expires_at: Option<String>,
+}
+
+#[cfg(test)]
+mod key_expiry_model_tests {
+    use super::*;
+
+    #[test]
+    fn test_change() {
+        assert!(KeyExpiry::default().change().is_err());
+        let never = KeyExpiry {
+            never: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(never.change().unwrap(), ExpiryChange::Never);
+        let extend = KeyExpiry {
+            extend_days: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(extend.change().unwrap(), ExpiryChange::ExtendDays(30));
+        let both = KeyExpiry {
+            extend_days: Some(30),
+            never: Some(true),
+            ..Default::default()
+        };
+        assert!(both.change().is_err());
+    }
+}
@@ -1,2 +1,9 @@
 pub mod user_model;
-pub mod auth_site_model;
\ No newline at end of file
+pub mod auth_site_model;
+pub mod usage_model;
+pub mod alert_model;
+pub mod plan_model;
+pub mod key_expiry_model;
+pub mod api_key_model;
+pub mod pool_key_model;
+pub mod model_price_model;
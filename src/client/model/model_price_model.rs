@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct AddModelPrice {
+    #[validate(
+        required(message = "model 不能为空"),
+        length(min = 1, max = 64, message = "model 长度必须在 1 到 64 之间")
+    )]
+    pub model: Option<String>,
+    #[validate(
+        required(message = "prompt_multiplier 不能为空"),
+        range(
+            min = 0.0,
+            max = 10000.0,
+            message = "prompt_multiplier 须在 0-10000 之间"
+        )
+    )]
+    pub prompt_multiplier: Option<f64>,
+    #[validate(
+        required(message = "completion_multiplier 不能为空"),
+        range(
+            min = 0.0,
+            max = 10000.0,
+            message = "completion_multiplier 须在 0-10000 之间"
+        )
+    )]
+    pub completion_multiplier: Option<f64>,
+    /// 生效时间, UTC, yyyy-MM-dd HH:mm:ss; 为空时立即生效, 不能早于当前时间
+    #[validate(custom = "crate::utils::validate::validate_datetime")]
+    pub effective_from: Option<String>,
+}
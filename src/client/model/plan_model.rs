@@ -0,0 +1,54 @@
+use rbatis::rbdc::datetime::DateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::client::entity::plan::Plan;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct AddPlan {
+    #[validate(
+        required(message = "name 不能为空"),
+        length(min = 1, max = 64, message = "name 长度必须在 1 到 64 之间")
+    )]
+    pub name: Option<String>,
+    #[validate(required(message = "monthly_tokens 不能为空"))]
+    pub monthly_tokens: Option<u64>,
+    /// 月费, 单位分
+    pub price_cents: Option<u64>,
+    /// 与 rate_limit_refill_per_second 同时为空时使用默认限流
+    #[validate(range(min = 1, message = "rate_limit_capacity 必须大于 0"))]
+    pub rate_limit_capacity: Option<u64>,
+    #[validate(range(min = 0.001, message = "rate_limit_refill_per_second 必须大于 0"))]
+    pub rate_limit_refill_per_second: Option<f64>,
+}
+
+impl Into<Plan> for AddPlan {
+    fn into(self) -> Plan {
+        Plan {
+            id: None,
+            name: self.name,
+            monthly_tokens: self.monthly_tokens,
+            price_cents: Some(self.price_cents.unwrap_or(0)),
+            rate_limit_capacity: self.rate_limit_capacity,
+            rate_limit_refill_per_second: self.rate_limit_refill_per_second,
+            active: Some(1),
+            created_time: Some(DateTime::now()),
+            updated_time: Some(DateTime::now()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AssignPlan {
+    /// 为空表示取消套餐
+    pub plan_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AssignPlanResult {
+    pub user_id: u64,
+    /// 按本月剩余时间折算补发 (正) 或扣回 (负) 的 tokens
+    pub adjustment: i64,
+    pub balance: u64,
+}
@@ -0,0 +1,104 @@
+use rbatis::rbdc::datetime::DateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{client::entity::openai_pool_key::OpenaiPoolKey, middleware::mask_key};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct AddPoolKey {
+    #[validate(
+        required(message = "name 不能为空"),
+        length(min = 1, max = 64, message = "name 长度必须在 1 到 64 之间")
+    )]
+    pub name: Option<String>,
+    #[validate(
+        required(message = "api_key 不能为空"),
+        length(min = 1, max = 128, message = "api_key 长度须在 1-128 之间")
+    )]
+    pub api_key: Option<String>,
+    /// 加权轮询的权重, 默认 1
+    #[validate(range(max = 1000, message = "weight 须在 0-1000 之间"))]
+    pub weight: Option<u32>,
+    /// 每月最多服务的 tokens, 为空表示不限
+    #[validate(range(min = 1, message = "monthly_token_limit 必须大于 0"))]
+    pub monthly_token_limit: Option<u64>,
+}
+
+impl Into<OpenaiPoolKey> for AddPoolKey {
+    fn into(self) -> OpenaiPoolKey {
+        OpenaiPoolKey {
+            id: None,
+            name: self.name,
+            api_key: self.api_key,
+            weight: Some(self.weight.unwrap_or(1)),
+            monthly_token_limit: self.monthly_token_limit,
+            tokens_used: Some(0),
+            period_begin: None,
+            healthy: Some(1),
+            health_reason: None,
+            active: Some(1),
+            created_time: Some(DateTime::now()),
+            updated_time: Some(DateTime::now()),
+        }
+    }
+}
+
+/// 修改共享 key, 为空的字段不变
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct UpdatePoolKey {
+    #[validate(range(max = 1000, message = "weight 须在 0-1000 之间"))]
+    pub weight: Option<u32>,
+    /// 0 表示取消上限
+    pub monthly_token_limit: Option<u64>,
+    #[validate(range(max = 1, message = "active 只能为 0 或 1"))]
+    pub active: Option<u64>,
+}
+
+/// 标记共享 key 的健康状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct PoolKeyHealth {
+    pub healthy: bool,
+    #[validate(length(max = 255, message = "reason 长度不能超过 255"))]
+    pub reason: Option<String>,
+}
+
+/// 对外展示的共享 key, api_key 脱敏
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct PoolKeyView {
+    pub id: Option<u64>,
+    pub name: Option<String>,
+    pub api_key: Option<String>,
+    pub weight: Option<u32>,
+    pub monthly_token_limit: Option<u64>,
+    /// period_begin 所在月已服务的 tokens
+    pub tokens_used: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub period_begin: Option<DateTime>,
+    pub healthy: Option<u64>,
+    pub health_reason: Option<String>,
+    pub active: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub updated_time: Option<DateTime>,
+}
+
+impl From<OpenaiPoolKey> for PoolKeyView {
+    fn from(key: OpenaiPoolKey) -> Self {
+        PoolKeyView {
+            id: key.id,
+            name: key.name,
+            api_key: key.api_key.as_deref().map(mask_key),
+            weight: key.weight,
+            monthly_token_limit: key.monthly_token_limit,
+            tokens_used: key.tokens_used,
+            period_begin: key.period_begin,
+            healthy: key.healthy,
+            health_reason: key.health_reason,
+            active: key.active,
+            created_time: key.created_time,
+            updated_time: key.updated_time,
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// summary 服务上报的用量消息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageEvent {
+    pub summary_key: String,
+    pub site_summary_key: Option<String>,
+    pub model: Option<String>,
+    /// 服务该次用量的共享 OpenAI key, 来自 key 解析结果
+    #[serde(default)]
+    pub pool_key_id: Option<u64>,
+
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+
+    /// 事件时间, 毫秒时间戳
+    pub event_time: Option<u64>,
+}
@@ -1,17 +1,36 @@
 use rbatis::rbdc::datetime::DateTime;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
 
-use crate::{client::entity::user::User, utils::uuid};
+use crate::{client::entity::user::User, middleware::mask_key, utils::uuid};
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
 pub struct AddUser {
     pub id: Option<u64>,
+    #[validate(
+        required(message = "账号不能为空"),
+        length(min = 3, max = 32, message = "账号长度须在 3-32 之间"),
+        custom = "crate::utils::validate::validate_account"
+    )]
     pub account: Option<String>,
+    #[validate(
+        required(message = "密码不能为空"),
+        length(min = 6, max = 64, message = "密码长度须在 6-64 之间")
+    )]
     pub password: Option<String>,
 
+    #[validate(range(max = 1000000000, message = "tokens 不能超过 1000000000"))]
     pub tokens: Option<u64>,
+    #[validate(
+        length(min = 8, max = 64, message = "summary_key 长度须在 8-64 之间"),
+        custom = "crate::utils::validate::validate_key"
+    )]
     pub summary_key: Option<String>,
+    #[validate(length(min = 1, max = 128, message = "openai_key 长度须在 1-128 之间"))]
     pub openai_key: Option<String>,
+    #[validate(email(message = "邮箱格式不正确"))]
+    pub email: Option<String>,
 }
 impl AddUser {
     pub fn new() -> AddUser {
@@ -22,6 +41,7 @@ impl AddUser {
             tokens: Some(0),
             summary_key: None,
             openai_key: None,
+            email: None,
         }
     }
 }
@@ -34,10 +54,67 @@ impl Into<User> for AddUser {
             password: self.password,
             tokens: self.tokens,
             summary_key: Some(uuid::new_summary_key()),
+            summary_key_expires_at: None,
             openai_key: self.openai_key,
+            email: self.email,
+            plan_id: None,
+            plan_period_begin: None,
             active: Some(1),
             created_time: Some(DateTime::now()),
             updated_time: Some(DateTime::now()),
         }
     }
 }
+
+/// 对外展示的用户信息, 不包含密码, openai_key 脱敏
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UserView {
+    pub id: Option<u64>,
+    pub account: Option<String>,
+    pub tokens: Option<u64>,
+    pub summary_key: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub summary_key_expires_at: Option<DateTime>,
+    pub openai_key: Option<String>,
+    pub email: Option<String>,
+    pub plan_id: Option<u64>,
+    pub active: Option<u64>,
+    #[schema(value_type = Option<String>)]
+    pub created_time: Option<DateTime>,
+    #[schema(value_type = Option<String>)]
+    pub updated_time: Option<DateTime>,
+}
+
+impl From<User> for UserView {
+    fn from(user: User) -> Self {
+        UserView {
+            id: user.id,
+            account: user.account,
+            tokens: user.tokens,
+            summary_key: user.summary_key,
+            summary_key_expires_at: user.summary_key_expires_at,
+            openai_key: user.openai_key.as_deref().map(mask_key),
+            email: user.email,
+            plan_id: user.plan_id,
+            active: user.active,
+            created_time: user.created_time,
+            updated_time: user.updated_time,
+        }
+    }
+}
+
+/// 管理员发放 tokens
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+pub struct GrantTokens {
+    #[validate(range(min = 1, max = 1000000000, message = "tokens 须在 1-1000000000 之间"))]
+    pub tokens: u64,
+    #[validate(length(max = 255, message = "reason 不能超过 255 个字符"))]
+    pub reason: Option<String>,
+}
+
+/// 发放后的余额
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct GrantResult {
+    pub user_id: u64,
+    pub balance: u64,
+}
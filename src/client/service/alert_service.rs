@@ -0,0 +1,249 @@
+//! 告警规则检查, 每次扣减或发放 tokens 后调用, 新触发的告警通过通知渠道发送
+use std::collections::HashSet;
+
+use log::info;
+
+use crate::{
+    client::{
+        entity::{
+            alert::{self, AlertHistory, AlertRule},
+            auth_site::AuthSite,
+            token_ledger::TokenLedger,
+            user::User,
+        },
+        service::notify_service,
+    },
+    error::Result,
+    utils::date_utils,
+};
+
+/// 站点用量
+#[derive(Debug, Clone, Default)]
+pub struct SiteUsage {
+    pub site_id: u64,
+    pub site_domain: String,
+    pub token_quota: Option<u64>,
+    pub tokens_used: u64,
+}
+
+/// 检查告警所需的用户用量
+#[derive(Debug, Clone, Default)]
+pub struct UsageSnapshot {
+    pub user_id: u64,
+    pub tokens: u64,
+    /// 本月发放的 tokens
+    pub month_granted: u64,
+    /// 本月扣减的 tokens
+    pub month_used: u64,
+    pub sites: Vec<SiteUsage>,
+}
+
+/// 规则在单个对象上的检查结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// user:{id} 或 site:{id}
+    pub subject: String,
+    pub site_id: Option<u64>,
+    pub value: u64,
+    pub firing: bool,
+    pub message: String,
+}
+
+fn percent(used: u64, total: u64) -> u64 {
+    used.saturating_mul(100) / total.max(1)
+}
+
+/// 用户规则覆盖同类型的全局规则
+fn effective_rules(rules: Vec<AlertRule>) -> Vec<AlertRule> {
+    let user_kinds: HashSet<String> = rules
+        .iter()
+        .filter(|r| r.user_id.is_some())
+        .filter_map(|r| r.kind.clone())
+        .collect();
+    rules
+        .into_iter()
+        .filter(|r| r.user_id.is_some() || !r.kind.as_ref().map_or(false, |k| user_kinds.contains(k)))
+        .collect()
+}
+
+/// 按规则检查用量, 返回每个对象的检查结果
+pub fn check(rule: &AlertRule, snapshot: &UsageSnapshot) -> Vec<Check> {
+    let threshold = rule.threshold.unwrap_or_default();
+    let user_subject = format!("user:{}", snapshot.user_id);
+    match rule.kind.as_deref() {
+        Some(alert::KIND_BALANCE_BELOW) => vec![Check {
+            subject: user_subject,
+            site_id: None,
+            value: snapshot.tokens,
+            firing: snapshot.tokens < threshold,
+            message: format!("余额 {} 低于 {}", snapshot.tokens, threshold),
+        }],
+        Some(alert::KIND_MONTHLY_USAGE_PERCENT) if snapshot.month_granted > 0 => {
+            let used = percent(snapshot.month_used, snapshot.month_granted);
+            vec![Check {
+                subject: user_subject,
+                site_id: None,
+                value: used,
+                firing: used >= threshold,
+                message: format!("本月已使用 {}% 的额度, 阈值 {}%", used, threshold),
+            }]
+        }
+        Some(alert::KIND_SITE_QUOTA_EXHAUSTED) => snapshot
+            .sites
+            .iter()
+            .filter_map(|site| {
+                let quota = site.token_quota?;
+                let used = percent(site.tokens_used, quota);
+                Some(Check {
+                    subject: format!("site:{}", site.site_id),
+                    site_id: Some(site.site_id),
+                    value: used,
+                    firing: used >= threshold,
+                    message: format!("站点 {} 已使用 {}% 的配额, 阈值 {}%", site.site_domain, used, threshold),
+                })
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+async fn snapshot(user_id: u64) -> Result<Option<UsageSnapshot>> {
+    let user = match User::find_by_id(user_id).await? {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+    let month_begin = date_utils::get_current_month_begin();
+    let (month_granted, month_used) = TokenLedger::totals_since(user_id, &month_begin).await?;
+    let sites = AuthSite::find_active_by_user_id(user_id)
+        .await?
+        .into_iter()
+        .map(|s| SiteUsage {
+            site_id: s.id.unwrap_or_default(),
+            site_domain: s.site_domain.unwrap_or_default(),
+            token_quota: s.token_quota,
+            tokens_used: s.tokens_used.unwrap_or(0),
+        })
+        .collect();
+    Ok(Some(UsageSnapshot {
+        user_id,
+        tokens: user.tokens.unwrap_or(0),
+        month_granted,
+        month_used,
+        sites,
+    }))
+}
+
+/// 检查用户的告警规则, 返回本次新触发的告警
+pub async fn evaluate(user_id: u64) -> Result<Vec<AlertHistory>> {
+    let rules = effective_rules(AlertRule::find_active_for_user(user_id).await?);
+    if rules.is_empty() {
+        return Ok(vec![]);
+    }
+    let snapshot = match snapshot(user_id).await? {
+        Some(snapshot) => snapshot,
+        None => return Ok(vec![]),
+    };
+
+    let mut fired = vec![];
+    for rule in &rules {
+        for check in check(rule, &snapshot) {
+            let history = AlertHistory {
+                id: None,
+                rule_id: rule.id,
+                user_id: Some(user_id),
+                site_id: check.site_id,
+                kind: rule.kind.clone(),
+                threshold: rule.threshold,
+                value: Some(check.value),
+                message: Some(check.message),
+                created_time: None,
+            };
+            if AlertHistory::transition(&check.subject, check.firing, &history).await? {
+                info!("alert fired, user: {}, {:?}", user_id, history.message);
+                fired.push(history);
+            }
+        }
+    }
+    notify_service::notify_alerts(fired.clone());
+    Ok(fired)
+}
+
+#[cfg(test)]
+mod alert_service_tests {
+    use super::*;
+
+    fn rule(user_id: Option<u64>, kind: &str, threshold: u64) -> AlertRule {
+        AlertRule {
+            user_id,
+            kind: Some(kind.to_string()),
+            threshold: Some(threshold),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_balance_below() {
+        let snapshot = UsageSnapshot {
+            user_id: 1,
+            tokens: 99,
+            ..Default::default()
+        };
+        let checks = check(&rule(None, alert::KIND_BALANCE_BELOW, 100), &snapshot);
+        assert_eq!(checks.len(), 1);
+        assert!(checks[0].firing);
+        assert_eq!(checks[0].subject, "user:1");
+    }
+
+    #[test]
+    fn test_monthly_usage_percent() {
+        let mut snapshot = UsageSnapshot {
+            user_id: 1,
+            month_granted: 1000,
+            month_used: 799,
+            ..Default::default()
+        };
+        let rule = rule(None, alert::KIND_MONTHLY_USAGE_PERCENT, 80);
+        assert!(!check(&rule, &snapshot)[0].firing);
+        snapshot.month_used = 800;
+        assert!(check(&rule, &snapshot)[0].firing);
+        snapshot.month_granted = 0;
+        assert!(check(&rule, &snapshot).is_empty());
+    }
+
+    #[test]
+    fn test_site_quota() {
+        let snapshot = UsageSnapshot {
+            user_id: 1,
+            sites: vec![
+                SiteUsage {
+                    site_id: 2,
+                    site_domain: "a.example.com".to_string(),
+                    token_quota: Some(100),
+                    tokens_used: 100,
+                },
+                SiteUsage {
+                    site_id: 3,
+                    site_domain: "b.example.com".to_string(),
+                    token_quota: None,
+                    tokens_used: 100,
+                },
+            ],
+            ..Default::default()
+        };
+        let checks = check(&rule(None, alert::KIND_SITE_QUOTA_EXHAUSTED, 100), &snapshot);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].site_id, Some(2));
+        assert!(checks[0].firing);
+    }
+
+    #[test]
+    fn test_user_rule_overrides_global() {
+        let rules = effective_rules(vec![
+            rule(None, alert::KIND_BALANCE_BELOW, 100),
+            rule(Some(1), alert::KIND_BALANCE_BELOW, 10),
+            rule(None, alert::KIND_MONTHLY_USAGE_PERCENT, 80),
+        ]);
+        assert_eq!(rules.len(), 2);
+        assert!(rules.iter().all(|r| r.threshold != Some(100)));
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::{info, warn};
+use rbatis::{executor::Executor, rbdc::db::ExecResult};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    client::entity::{
+        alert::AlertRule, auth_site::AuthSite, model_price::ModelPrice, openai_pool_key::OpenaiPoolKey,
+        plan::Plan, token_ledger::TokenLedger, user::User, user_api_key::UserApiKey,
+    },
+    db,
+    error::{Error, Result},
+    setting,
+    utils::date_utils,
+};
+
+/// 备份格式版本, 结构变化时递增
+pub const BACKUP_VERSION: u32 = 1;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// 备份的数据
+///
+/// 告警状态与历史、通知投递记录、outbox 事件和 leader 租约是运行时记录, 不在备份中。
+/// 后加入的表在旧版本备份中没有, 恢复时按空处理
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupData {
+    pub users: Vec<User>,
+    pub auth_sites: Vec<AuthSite>,
+    pub token_ledgers: Vec<TokenLedger>,
+    /// 旧版本备份没有, 恢复时由 summary_key 补为 default key
+    #[serde(default)]
+    pub api_keys: Vec<UserApiKey>,
+    #[serde(default)]
+    pub plans: Vec<Plan>,
+    #[serde(default)]
+    pub alert_rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub pool_keys: Vec<OpenaiPoolKey>,
+    #[serde(default)]
+    pub model_prices: Vec<ModelPrice>,
+}
+
+/// 备份文件
+///
+/// checksum 为 data 序列化后的 sha256, 恢复前校验。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub created_time: String,
+    pub counts: HashMap<String, usize>,
+    pub checksum: String,
+    pub data: serde_json::Value,
+}
+
+/// 恢复结果, 各表写入的行数
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct RestoreReport {
+    pub users: usize,
+    pub auth_sites: usize,
+    pub token_ledgers: usize,
+    pub api_keys: usize,
+    pub plans: usize,
+    pub alert_rules: usize,
+    pub pool_keys: usize,
+    pub model_prices: usize,
+}
+
+fn checksum(data: &serde_json::Value) -> Result<String> {
+    let bytes = serde_json::to_vec(data)?;
+    Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+/// 在一个事务中读取全部数据, 保证各表一致
+async fn read_all() -> Result<BackupData> {
+    let rb = db::get_rb();
+    let mut tx = rb.acquire_begin().await?;
+    let data = BackupData {
+        users: User::select_all(&mut tx).await?,
+        auth_sites: AuthSite::select_all(&mut tx).await?,
+        token_ledgers: TokenLedger::select_all(&mut tx).await?,
+        api_keys: UserApiKey::select_all(&mut tx).await?,
+        plans: Plan::select_all(&mut tx).await?,
+        alert_rules: AlertRule::select_all(&mut tx).await?,
+        pool_keys: OpenaiPoolKey::select_all(&mut tx).await?,
+        model_prices: ModelPrice::select_all(&mut tx).await?,
+    };
+    tx.commit().await?;
+    Ok(data)
+}
+
+/// 导出备份, gzip 为 true 时压缩
+///
+/// 只包含数据库中的数据; 服务配置 (configs/config.toml) 不在备份中, 恢复到其他环境时需单独准备
+pub async fn backup(gzip: bool) -> Result<Vec<u8>> {
+    let data = read_all().await?;
+    let mut counts = HashMap::new();
+    counts.insert("users".to_string(), data.users.len());
+    counts.insert("auth_sites".to_string(), data.auth_sites.len());
+    counts.insert("token_ledgers".to_string(), data.token_ledgers.len());
+    counts.insert("api_keys".to_string(), data.api_keys.len());
+    counts.insert("plans".to_string(), data.plans.len());
+    counts.insert("alert_rules".to_string(), data.alert_rules.len());
+    counts.insert("pool_keys".to_string(), data.pool_keys.len());
+    counts.insert("model_prices".to_string(), data.model_prices.len());
+
+    let data = serde_json::to_value(&data)?;
+    let backup = Backup {
+        version: BACKUP_VERSION,
+        created_time: date_utils::get_current_time_str(),
+        counts,
+        checksum: checksum(&data)?,
+        data,
+    };
+    let json = serde_json::to_vec(&backup)?;
+    info!("backup created, counts: {:?}", backup.counts);
+
+    if !gzip {
+        return Ok(json);
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json)?;
+    Ok(encoder.finish()?)
+}
+
+/// 解析备份文件并校验版本、checksum、行数及引用关系
+pub fn parse(bytes: &[u8]) -> Result<BackupData> {
+    parse_limited(bytes, setting::SETTING.admin.max_backup_bytes())
+}
+
+/// 同 `parse`, 备份内容 (解压后) 超过 `limit` 字节时拒绝
+fn parse_limited(bytes: &[u8], limit: usize) -> Result<BackupData> {
+    let json = if bytes.starts_with(&GZIP_MAGIC) {
+        // 多读 1 个字节用来判断是否超限, 避免压缩炸弹解压出任意大小的数据
+        let mut json = vec![];
+        GzDecoder::new(bytes)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut json)
+            .map_err(|e| Error::invalid_param(format!("备份文件解压失败: {}", e)))?;
+        json
+    } else {
+        bytes.to_vec()
+    };
+    if json.len() > limit {
+        return Err(Error::invalid_param(format!("备份文件超过 {} 字节上限", limit)));
+    }
+
+    let backup: Backup = serde_json::from_slice(&json)
+        .map_err(|e| Error::invalid_param(format!("备份文件格式错误: {}", e)))?;
+    if backup.version != BACKUP_VERSION {
+        return Err(Error::invalid_param(format!(
+            "不支持的备份版本: {}",
+            backup.version
+        )));
+    }
+    if checksum(&backup.data)? != backup.checksum {
+        return Err(Error::invalid_param("备份 checksum 校验失败"));
+    }
+    let data: BackupData = serde_json::from_value(backup.data)
+        .map_err(|e| Error::invalid_param(format!("备份数据格式错误: {}", e)))?;
+
+    let expected = |name: &str| backup.counts.get(name).copied().unwrap_or(0);
+    if data.users.len() != expected("users")
+        || data.auth_sites.len() != expected("auth_sites")
+        || data.token_ledgers.len() != expected("token_ledgers")
+        || data.api_keys.len() != expected("api_keys")
+        || data.plans.len() != expected("plans")
+        || data.alert_rules.len() != expected("alert_rules")
+        || data.pool_keys.len() != expected("pool_keys")
+        || data.model_prices.len() != expected("model_prices")
+    {
+        return Err(Error::invalid_param("备份行数与记录不一致"));
+    }
+
+    let user_ids: Vec<Option<u64>> = data.users.iter().map(|u| u.id).collect();
+    let orphan_site = data.auth_sites.iter().any(|s| !user_ids.contains(&s.user_id));
+    let orphan_ledger = data.token_ledgers.iter().any(|l| !user_ids.contains(&l.user_id));
+    let orphan_key = data.api_keys.iter().any(|k| !user_ids.contains(&k.user_id));
+    // user_id 为空的告警规则对所有用户生效
+    let orphan_rule = data
+        .alert_rules
+        .iter()
+        .any(|r| r.user_id.is_some() && !user_ids.contains(&r.user_id));
+    if orphan_site || orphan_ledger || orphan_key || orphan_rule {
+        return Err(Error::invalid_param("备份中存在引用不存在用户的数据"));
+    }
+    Ok(data)
+}
+
+/// 备份涉及的表, 恢复前须为空, 恢复后逐表校验行数
+const TABLES: [&str; 8] = [
+    "user",
+    "auth_site",
+    "token_ledger",
+    "user_api_key",
+    "plan",
+    "alert_rule",
+    "openai_pool_key",
+    "model_price",
+];
+
+fn inserted_id(result: ExecResult, table: &str) -> Result<u64> {
+    result
+        .last_insert_id
+        .as_u64()
+        .ok_or_else(|| Error::BizError(format!("无法获取 {} 的新 id", table)))
+}
+
+/// 各表当前行数, 顺序同 TABLES
+async fn counts(executor: &mut dyn Executor) -> Result<Vec<usize>> {
+    let mut counts = Vec::with_capacity(TABLES.len());
+    for table in TABLES {
+        let sql = format!("select count(*) from {}", table);
+        let value = executor.query(&sql, vec![]).await?;
+        let count: u64 = rbatis::decode::decode(value)?;
+        counts.push(count as usize);
+    }
+    Ok(counts)
+}
+
+/// 旧 id 换成新 id; 旧版本备份没有套餐及共享 key, 找不到时清空引用
+fn remap_id(ids: &HashMap<u64, u64>, id: Option<u64>) -> Option<u64> {
+    id.and_then(|id| ids.get(&id).copied())
+}
+
+async fn restore_in(executor: &mut dyn Executor, data: BackupData) -> Result<RestoreReport> {
+    if counts(executor).await?.iter().any(|count| *count > 0) {
+        return Err(Error::Conflict("只能恢复到空库".to_string()));
+    }
+
+    // 旧 id -> 新 id; 被引用的表先写入
+    let mut plan_ids: HashMap<u64, u64> = HashMap::new();
+    for mut plan in data.plans {
+        let old_id = plan.id.take().unwrap_or_default();
+        plan_ids.insert(old_id, inserted_id(Plan::insert(executor, &plan).await?, "plan")?);
+    }
+    let mut pool_key_ids: HashMap<u64, u64> = HashMap::new();
+    for mut key in data.pool_keys {
+        let old_id = key.id.take().unwrap_or_default();
+        let new_id = inserted_id(OpenaiPoolKey::insert(executor, &key).await?, "openai_pool_key")?;
+        pool_key_ids.insert(old_id, new_id);
+    }
+    let mut user_ids: HashMap<u64, u64> = HashMap::new();
+    for mut user in data.users {
+        let old_id = user.id.take().unwrap_or_default();
+        user.plan_id = remap_id(&plan_ids, user.plan_id);
+        user_ids.insert(old_id, inserted_id(User::insert(executor, &user).await?, "user")?);
+    }
+    let remap = |user_id: Option<u64>| remap_id(&user_ids, user_id);
+
+    let mut report = RestoreReport {
+        users: user_ids.len(),
+        plans: plan_ids.len(),
+        pool_keys: pool_key_ids.len(),
+        ..Default::default()
+    };
+    for mut site in data.auth_sites {
+        site.id = None;
+        site.user_id = remap(site.user_id);
+        // 旧版本备份没有站点用量
+        site.tokens_used = site.tokens_used.or(Some(0));
+        AuthSite::insert(executor, &site).await?;
+        report.auth_sites += 1;
+    }
+    for mut ledger in data.token_ledgers {
+        ledger.id = None;
+        ledger.user_id = remap(ledger.user_id);
+        ledger.pool_key_id = remap_id(&pool_key_ids, ledger.pool_key_id);
+        TokenLedger::insert(executor, &ledger).await?;
+        report.token_ledgers += 1;
+    }
+    if data.api_keys.is_empty() {
+        report.api_keys = UserApiKey::backfill_legacy(executor).await? as usize;
+    }
+    for mut key in data.api_keys {
+        key.id = None;
+        key.user_id = remap(key.user_id);
+        UserApiKey::insert(executor, &key).await?;
+        report.api_keys += 1;
+    }
+    for mut rule in data.alert_rules {
+        rule.id = None;
+        rule.user_id = remap(rule.user_id);
+        AlertRule::insert(executor, &rule).await?;
+        report.alert_rules += 1;
+    }
+    for mut price in data.model_prices {
+        price.id = None;
+        ModelPrice::insert(executor, &price).await?;
+        report.model_prices += 1;
+    }
+
+    let restored = vec![
+        report.users,
+        report.auth_sites,
+        report.token_ledgers,
+        report.api_keys,
+        report.plans,
+        report.alert_rules,
+        report.pool_keys,
+        report.model_prices,
+    ];
+    if counts(executor).await? != restored {
+        return Err(Error::BizError("恢复后行数校验失败".to_string()));
+    }
+    Ok(report)
+}
+
+/// 将备份恢复到空库, 重新分配 id; 任一步失败整体回滚
+pub async fn restore(bytes: &[u8]) -> Result<RestoreReport> {
+    let data = parse(bytes)?;
+    let rb = db::get_rb();
+    let mut tx = rb.acquire_begin().await?;
+    match restore_in(&mut tx, data).await {
+        Ok(report) => {
+            tx.commit().await?;
+            info!("backup restored: {:?}", report);
+            Ok(report)
+        }
+        Err(e) => {
+            if let Err(rollback_err) = tx.rollback().await {
+                warn!("restore rollback error: {}", rollback_err);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod backup_tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut user = User::new();
+        user.id = Some(7);
+        user.account = Some("alice".to_string());
+        let mut site = AuthSite::default();
+        site.id = Some(1);
+        site.user_id = Some(7);
+
+        let data = serde_json::to_value(BackupData {
+            users: vec![user],
+            auth_sites: vec![site],
+            token_ledgers: vec![],
+            ..Default::default()
+        })
+        .unwrap();
+        let mut counts = HashMap::new();
+        counts.insert("users".to_string(), 1);
+        counts.insert("auth_sites".to_string(), 1);
+        let backup = Backup {
+            version: BACKUP_VERSION,
+            created_time: "2023-11-01 00:00:00".to_string(),
+            counts,
+            checksum: checksum(&data).unwrap(),
+            data,
+        };
+        serde_json::to_vec(&backup).unwrap()
+    }
+
+    #[test]
+    fn test_parse_plain_and_gzip() {
+        let json = sample();
+        assert_eq!(parse(&json).unwrap().users.len(), 1);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&json).unwrap();
+        let gz = encoder.finish().unwrap();
+        assert_eq!(parse(&gz).unwrap().auth_sites.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized_gzip() {
+        let json = sample();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&json).unwrap();
+        let gz = encoder.finish().unwrap();
+        assert!(parse_limited(&gz, json.len()).is_ok());
+        assert!(matches!(parse_limited(&gz, json.len() - 1), Err(Error::InvalidParam(..))));
+    }
+
+    #[test]
+    fn test_parse_without_api_keys() {
+        let mut backup: Backup = serde_json::from_slice(&sample()).unwrap();
+        backup.data.as_object_mut().unwrap().remove("api_keys");
+        backup.checksum = checksum(&backup.data).unwrap();
+        let bytes = serde_json::to_vec(&backup).unwrap();
+        assert!(parse(&bytes).unwrap().api_keys.is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects_tampered_data() {
+        let mut backup: Backup = serde_json::from_slice(&sample()).unwrap();
+        backup.data["users"][0]["account"] = serde_json::json!("mallory");
+        let bytes = serde_json::to_vec(&backup).unwrap();
+        assert!(parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_orphan_alert_rule() {
+        let mut backup: Backup = serde_json::from_slice(&sample()).unwrap();
+        backup.data["alert_rules"] = serde_json::json!([{ "id": 1, "user_id": 8 }]);
+        backup.counts.insert("alert_rules".to_string(), 1);
+        backup.checksum = checksum(&backup.data).unwrap();
+        let bytes = serde_json::to_vec(&backup).unwrap();
+        assert!(parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_remap_id_clears_missing_reference() {
+        let ids = HashMap::from([(3, 1)]);
+        assert_eq!(remap_id(&ids, Some(3)), Some(1));
+        assert_eq!(remap_id(&ids, Some(4)), None);
+        assert_eq!(remap_id(&ids, None), None);
+    }
+
+    #[test]
+    fn test_parse_malformed_is_invalid_param() {
+        assert!(matches!(parse(b"not json"), Err(Error::InvalidParam(..))));
+    }
+}
@@ -0,0 +1,62 @@
+//! key 过期的每日报告
+use std::collections::HashMap;
+
+use log::{info, warn};
+
+use crate::{
+    client::{
+        entity::{
+            key_expiry::{self, ExpiringKey},
+            user::User,
+        },
+        service::notify_service,
+    },
+    error::Result,
+    notify::{self, template},
+    setting,
+};
+
+/// 通知模板中 key 的描述
+fn key_label(key: &ExpiringKey) -> String {
+    match (&key.site_domain, &key.key_name) {
+        (Some(domain), _) if key.kind == key_expiry::KIND_SITE_SUMMARY_KEY => {
+            format!("站点 {} 的 site_summary_key", domain)
+        }
+        (_, Some(name)) if key.kind == key_expiry::KIND_API_KEY => format!("api key {}", name),
+        _ => key.kind.clone(),
+    }
+}
+
+/// 列出 report_days 天内过期的 key 并通知对应用户, 返回 key 数量
+pub async fn report() -> Result<usize> {
+    let days = setting::SETTING.key_expiry.report_days;
+    let keys = key_expiry::expiring_within(days).await?;
+    for key in &keys {
+        info!(
+            "key expiring at {}: {} user {} key {:?} site {:?}",
+            key.expires_at, key.kind, key.user_id, key.key_id, key.site_id
+        );
+    }
+    if notify::channels().is_empty() {
+        return Ok(keys.len());
+    }
+
+    let mut users: HashMap<u64, Option<User>> = HashMap::new();
+    for key in &keys {
+        if !users.contains_key(&key.user_id) {
+            users.insert(key.user_id, User::find_by_id(key.user_id).await?);
+        }
+        let user = match users.get(&key.user_id).and_then(|u| u.as_ref()) {
+            Some(user) => user,
+            None => continue,
+        };
+        let mut vars = notify_service::user_vars(user);
+        vars.remove("summary_key_hint");
+        vars.insert("key", key_label(key));
+        vars.insert("expires_at", key.expires_at.clone());
+        if let Err(e) = notify_service::notify(Some(user), template::KEY_EXPIRING, vars).await {
+            warn!("key expiring notify error, user: {}, error: {}", key.user_id, e);
+        }
+    }
+    Ok(keys.len())
+}
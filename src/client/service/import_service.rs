@@ -0,0 +1,418 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    client::{
+        entity::{auth_site::AuthSite, user::User},
+        model::{auth_site_model::AddAuthSite, user_model::AddUser},
+    },
+    db,
+    error::{Error, Result},
+    utils::{
+        uuid,
+        validate::{self, FieldError},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+impl ImportFormat {
+    pub fn parse(s: &str) -> Result<ImportFormat> {
+        match s {
+            "csv" => Ok(ImportFormat::Csv),
+            "json" => Ok(ImportFormat::Json),
+            _ => Err(Error::invalid_param("format 只能为 csv 或 json")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    pub format: ImportFormat,
+    /// 只校验不写入
+    pub dry_run: bool,
+    /// 全部成功才提交, 否则逐个用户写入
+    pub atomic: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSite {
+    pub site_domain: Option<String>,
+    pub site_summary_key: Option<String>,
+}
+
+/// 导入的用户, JSON 格式下站点嵌套在 sites 中
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportUser {
+    pub account: Option<String>,
+    pub password: Option<String>,
+    pub tokens: Option<u64>,
+    pub openai_key: Option<String>,
+    #[serde(default)]
+    pub sites: Vec<ImportSite>,
+}
+
+/// CSV 每行一个用户站点, 同一账号的多行合并为一个用户
+///
+/// 后续行的 password、tokens、openai_key 可以留空, 不为空时须与该账号第一行一致
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    account: Option<String>,
+    password: Option<String>,
+    tokens: Option<u64>,
+    openai_key: Option<String>,
+    site_domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImportRowError {
+    /// CSV 为行号(含表头), JSON 为数组下标(从 1 开始)
+    pub row: usize,
+    pub account: Option<String>,
+    #[schema(value_type = Vec<Object>)]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ImportReport {
+    pub total: usize,
+    pub valid: usize,
+    pub imported: usize,
+    pub dry_run: bool,
+    pub atomic: bool,
+    pub errors: Vec<ImportRowError>,
+}
+
+impl ImportUser {
+    fn to_add_user(&self) -> AddUser {
+        let mut add_user = AddUser::new();
+        add_user.account = self.account.clone();
+        add_user.password = self.password.clone();
+        add_user.tokens = self.tokens.or(Some(0));
+        add_user.openai_key = self.openai_key.clone();
+        add_user
+    }
+
+    fn to_add_sites(&self, user_id: u64) -> Vec<AddAuthSite> {
+        self.sites
+            .iter()
+            .map(|site| {
+                let mut add = AddAuthSite::new();
+                add.user_id = Some(user_id);
+                add.site_domain = site.site_domain.clone();
+                add.site_summary_key = site
+                    .site_summary_key
+                    .clone()
+                    .or_else(|| Some(uuid::new_summary_key()));
+                add
+            })
+            .collect()
+    }
+}
+
+fn field_errors(result: Result<()>, prefix: &str) -> Vec<FieldError> {
+    match result {
+        Ok(_) => vec![],
+        Err(Error::InvalidParam(_, fields)) => fields
+            .into_iter()
+            .map(|mut f| {
+                f.field = format!("{}{}", prefix, f.field);
+                f
+            })
+            .collect(),
+        Err(e) => vec![FieldError {
+            field: prefix.trim_end_matches('.').to_string(),
+            code: "invalid".to_string(),
+            message: e.to_string(),
+        }],
+    }
+}
+
+fn row_error(row: usize, account: Option<String>, code: &str, message: String) -> ImportRowError {
+    ImportRowError {
+        row,
+        account,
+        errors: vec![FieldError {
+            field: "account".to_string(),
+            code: code.to_string(),
+            message,
+        }],
+    }
+}
+
+/// 同一账号的后续 CSV 行中与第一行不一致的字段
+fn conflicting_fields(first: &ImportUser, record: &CsvRow) -> Vec<&'static str> {
+    let mut fields = vec![];
+    if record.password.is_some() && record.password != first.password {
+        fields.push("password");
+    }
+    if record.tokens.is_some() && record.tokens != first.tokens {
+        fields.push("tokens");
+    }
+    if record.openai_key.is_some() && record.openai_key != first.openai_key {
+        fields.push("openai_key");
+    }
+    fields
+}
+
+/// 解析导入数据, 返回 (行号, 用户) 及解析失败的行
+fn parse(data: &[u8], format: ImportFormat) -> Result<(Vec<(usize, ImportUser)>, Vec<ImportRowError>)> {
+    match format {
+        ImportFormat::Json => {
+            let users: Vec<ImportUser> = serde_json::from_slice(data)
+                .map_err(|e| Error::invalid_param(format!("JSON 格式错误: {}", e)))?;
+            Ok((users.into_iter().enumerate().map(|(i, u)| (i + 1, u)).collect(), vec![]))
+        }
+        ImportFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(data);
+            let mut users: Vec<(usize, ImportUser)> = vec![];
+            let mut index: HashMap<String, usize> = HashMap::new();
+            let mut errors = vec![];
+            for (i, record) in reader.deserialize::<CsvRow>().enumerate() {
+                // 表头占第 1 行
+                let row = i + 2;
+                let record = match record {
+                    Ok(record) => record,
+                    Err(e) => {
+                        errors.push(row_error(row, None, "parse", e.to_string()));
+                        continue;
+                    }
+                };
+                let site = record.site_domain.clone().filter(|d| !d.is_empty()).map(|d| ImportSite {
+                    site_domain: Some(d),
+                    site_summary_key: None,
+                });
+                let account = record.account.clone().unwrap_or_default();
+                match index.get(&account) {
+                    Some(&pos) if !account.is_empty() => {
+                        let first_row = users[pos].0;
+                        let conflicts = conflicting_fields(&users[pos].1, &record);
+                        if conflicts.is_empty() {
+                            users[pos].1.sites.extend(site);
+                        } else {
+                            errors.push(row_error(
+                                row,
+                                record.account,
+                                "conflict",
+                                format!("{} 与第 {} 行不一致", conflicts.join("、"), first_row),
+                            ));
+                        }
+                    }
+                    _ => {
+                        index.insert(account, users.len());
+                        users.push((
+                            row,
+                            ImportUser {
+                                account: record.account,
+                                password: record.password,
+                                tokens: record.tokens,
+                                openai_key: record.openai_key,
+                                sites: site.into_iter().collect(),
+                            },
+                        ));
+                    }
+                }
+            }
+            Ok((users, errors))
+        }
+    }
+}
+
+/// 校验全部用户, 返回校验通过的用户
+async fn validate_all(
+    users: Vec<(usize, ImportUser)>,
+    errors: &mut Vec<ImportRowError>,
+) -> Result<Vec<(usize, ImportUser)>> {
+    let mut seen = HashSet::new();
+    let mut seen_site_keys = HashSet::new();
+    let mut valid = vec![];
+    for (row, user) in users {
+        let mut fields = field_errors(validate::validate(&user.to_add_user()), "");
+        // 校验站点时 user_id 尚未生成, 使用占位 id
+        for (i, site) in user.to_add_sites(1).iter().enumerate() {
+            fields.extend(field_errors(validate::validate(site), &format!("sites[{}].", i)));
+        }
+        // 只检查数据中显式给出的站点 key, 未给出的由系统随机生成
+        for (i, site) in user.sites.iter().enumerate() {
+            if let Some(key) = &site.site_summary_key {
+                if !seen_site_keys.insert(key.clone()) {
+                    fields.push(FieldError {
+                        field: format!("sites[{}].site_summary_key", i),
+                        code: "duplicate".to_string(),
+                        message: "导入数据中站点 key 重复".to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some(account) = &user.account {
+            if !seen.insert(account.clone()) {
+                fields.push(FieldError {
+                    field: "account".to_string(),
+                    code: "duplicate".to_string(),
+                    message: "导入数据中账号重复".to_string(),
+                });
+            } else if User::find_by_account(account).await?.is_some() {
+                fields.push(FieldError {
+                    field: "account".to_string(),
+                    code: "exists".to_string(),
+                    message: "账号已存在".to_string(),
+                });
+            }
+        }
+
+        if fields.is_empty() {
+            valid.push((row, user));
+        } else {
+            errors.push(ImportRowError {
+                row,
+                account: user.account.clone(),
+                errors: fields,
+            });
+        }
+    }
+    Ok(valid)
+}
+
+/// 在一个事务中写入用户及其站点
+async fn insert_users(users: &[(usize, ImportUser)]) -> std::result::Result<(), (usize, Error)> {
+    let rb = db::get_rb();
+    let mut tx = rb.acquire_begin().await.map_err(|e| (0, e.into()))?;
+    for (row, user) in users {
+        let result = async {
+            let user_id = User::add_user_in(&mut tx, user.to_add_user()).await?;
+            for site in user.to_add_sites(user_id) {
+                AuthSite::add_auth_site_in(&mut tx, site).await?;
+            }
+            Ok::<(), Error>(())
+        }
+        .await;
+        if let Err(e) = result {
+            if let Err(rollback_err) = tx.rollback().await {
+                warn!("import rollback error: {}", rollback_err);
+            }
+            return Err((*row, e));
+        }
+    }
+    tx.commit().await.map_err(|e| (0, e.into()))?;
+    Ok(())
+}
+
+/// 批量导入用户及站点
+///
+/// 先校验全部数据并逐行报告错误; dry_run 时只校验。
+/// atomic 模式下存在任何错误都不写入, 写入在一个事务中完成;
+/// 否则跳过错误行, 每个用户及其站点单独一个事务。
+pub async fn import(data: &[u8], options: ImportOptions) -> Result<ImportReport> {
+    let (users, mut errors) = parse(data, options.format)?;
+    let total = users.len() + errors.len();
+    let valid = validate_all(users, &mut errors).await?;
+
+    let mut report = ImportReport {
+        total,
+        valid: valid.len(),
+        imported: 0,
+        dry_run: options.dry_run,
+        atomic: options.atomic,
+        errors,
+    };
+    if options.dry_run || (options.atomic && !report.errors.is_empty()) {
+        return Ok(report);
+    }
+
+    if options.atomic {
+        match insert_users(&valid).await {
+            Ok(_) => report.imported = valid.len(),
+            Err((row, e)) => report.errors.push(row_error(row, None, "insert", e.to_string())),
+        }
+    } else {
+        for user in valid {
+            match insert_users(std::slice::from_ref(&user)).await {
+                Ok(_) => report.imported += 1,
+                Err((row, e)) => report
+                    .errors
+                    .push(row_error(row, user.1.account.clone(), "insert", e.to_string())),
+            }
+        }
+    }
+    report.errors.sort_by_key(|e| e.row);
+    info!(
+        "import finished, total: {}, imported: {}, errors: {}",
+        report.total,
+        report.imported,
+        report.errors.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_groups_sites_by_account() {
+        let data = "account,password,tokens,openai_key,site_domain\n\
+                    alice,123456,100,,a.example.com\n\
+                    alice,123456,100,,b.example.com\n\
+                    bob,123456,,,\n\
+                    carol,123456,abc,,\n";
+        let (users, errors) = parse(data.as_bytes(), ImportFormat::Csv).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].0, 2);
+        assert_eq!(users[0].1.sites.len(), 2);
+        assert_eq!(users[1].1.account.as_deref(), Some("bob"));
+        assert!(users[1].1.sites.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].row, 5);
+    }
+
+    #[test]
+    fn test_parse_csv_reports_conflicting_account_rows() {
+        let data = "account,password,tokens,openai_key,site_domain\n\
+                    alice,123456,100,,a.example.com\n\
+                    alice,,,,b.example.com\n\
+                    alice,654321,100,,c.example.com\n\
+                    alice,123456,200,,d.example.com\n";
+        let (users, errors) = parse(data.as_bytes(), ImportFormat::Csv).unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].1.sites.len(), 2);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].row, 4);
+        assert_eq!(errors[0].errors[0].code, "conflict");
+        assert_eq!(errors[1].row, 5);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let data = r#"[{"account": "alice", "password": "123456", "sites": [{"site_domain": "a.example.com"}]}]"#;
+        let (users, errors) = parse(data.as_bytes(), ImportFormat::Json).unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].1.sites.len(), 1);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_field_errors_prefix() {
+        let user = ImportUser {
+            account: Some("alice".to_string()),
+            password: Some("123456".to_string()),
+            sites: vec![ImportSite {
+                site_domain: Some("localhost".to_string()),
+                site_summary_key: None,
+            }],
+            ..Default::default()
+        };
+        let site = &user.to_add_sites(1)[0];
+        let fields = field_errors(validate::validate(site), "sites[0].");
+        assert_eq!(fields[0].field, "sites[0].site_domain");
+    }
+}
@@ -0,0 +1,280 @@
+//! api key 及站点的查询缓存, 以及 key 最近使用时间的批量写入
+//!
+//! 只缓存查到的有效记录, 数据变更提交后调用 `invalidate_*` 立即失效。
+//! 缓存记录带有 key 的过期时刻, 每次命中时检查, 到期的记录立即丢弃并重新查库。
+//!
+//! 失效只作用于当前实例: 多实例部署时, 其他实例上的吊销、停用等变更最多在
+//! `cache.ttl_seconds` 后生效, 因此该值应保持较短。用量扣减只改变余额, 不触发失效,
+//! 解析结果中的余额同样最多滞后 `cache.ttl_seconds`。
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use crate::{
+    client::entity::{auth_site::AuthSite, key_expiry, user::User, user_api_key::UserApiKey},
+    error::Result,
+    metrics, setting, shutdown,
+    utils::{date_utils, ttl_cache::TtlCache},
+};
+
+/// 最近使用时间的写入间隔
+const LAST_USED_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 解析得到的 key 及其用户
+#[derive(Debug, Clone)]
+pub struct ResolvedKey {
+    pub key_id: u64,
+    pub user: User,
+    /// key 的过期时刻, 为空表示不过期
+    pub expires_at: Option<Instant>,
+}
+
+lazy_static! {
+    static ref KEYS: Mutex<TtlCache<String, ResolvedKey>> = Mutex::new(new_cache());
+    /// 站点及 site_summary_key 的过期时刻
+    static ref SITES: Mutex<TtlCache<(u64, String), (AuthSite, Option<Instant>)>> = Mutex::new(new_cache());
+    /// key id -> 最近使用时间, 定期批量写库
+    static ref LAST_USED: Mutex<HashMap<u64, String>> = Mutex::new(HashMap::new());
+}
+
+/// 每次失效加一; 查库前后不一致时不写缓存, 避免并发变更后写入旧数据
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn new_cache<K: Eq + std::hash::Hash + Clone, V: Clone>() -> TtlCache<K, V> {
+    let config = &setting::SETTING.cache;
+    TtlCache::new(config.capacity, Duration::from_secs(config.ttl_seconds))
+}
+
+/// 距过期的秒数换算为本地时刻, 按查库时的数据库时间计算, 不受两边时区影响
+fn expiry_instant(seconds_left: Option<i64>, now: Instant) -> Option<Instant> {
+    seconds_left.map(|seconds| now + Duration::from_secs(seconds.max(0) as u64))
+}
+
+fn is_expired(expires_at: Option<Instant>, now: Instant) -> bool {
+    matches!(expires_at, Some(at) if at <= now)
+}
+
+/// 从缓存中取未过期的 key, 已过期的记录同时移除
+fn cached_key(key: &String, now: Instant) -> Option<ResolvedKey> {
+    let mut keys = KEYS.lock().unwrap();
+    let resolved = keys.get(key, now)?;
+    if is_expired(resolved.expires_at, now) {
+        keys.remove(key);
+        return None;
+    }
+    Some(resolved)
+}
+
+/// 按 api key 查询有效 key 及其用户, 并记录使用时间
+pub async fn find_key(api_key: &str) -> Result<Option<ResolvedKey>> {
+    let key = api_key.to_string();
+    let cached = cached_key(&key, Instant::now());
+    let resolved = match cached {
+        Some(resolved) => {
+            metrics::observe_cache("user", true);
+            Some(resolved)
+        }
+        None => {
+            metrics::observe_cache("user", false);
+            let generation = GENERATION.load(Ordering::SeqCst);
+            let now = Instant::now();
+            let resolved = UserApiKey::find_active(api_key)
+                .await?
+                .map(|(key_id, expires_in, user)| ResolvedKey {
+                    key_id,
+                    user,
+                    expires_at: expiry_instant(expires_in, now),
+                });
+            if let Some(resolved) = &resolved {
+                let mut keys = KEYS.lock().unwrap();
+                if generation == GENERATION.load(Ordering::SeqCst) {
+                    keys.insert(key, resolved.clone(), Instant::now());
+                }
+            }
+            resolved
+        }
+    };
+    if let Some(resolved) = &resolved {
+        touch(resolved.key_id);
+    }
+    Ok(resolved)
+}
+
+/// 按用户和域名查询有效站点
+pub async fn find_site(user_id: u64, domain: &str) -> Result<Option<AuthSite>> {
+    let key = (user_id, domain.to_string());
+    {
+        let now = Instant::now();
+        let mut sites = SITES.lock().unwrap();
+        match sites.get(&key, now) {
+            Some((_, expires_at)) if is_expired(expires_at, now) => sites.remove(&key),
+            Some((site, _)) => {
+                metrics::observe_cache("site", true);
+                return Ok(Some(site));
+            }
+            None => {}
+        }
+    }
+    metrics::observe_cache("site", false);
+
+    let generation = GENERATION.load(Ordering::SeqCst);
+    let now = Instant::now();
+    let site = AuthSite::find_active_by_domain(user_id, domain).await?;
+    if let Some(site) = &site {
+        let seconds_left = match (site.id, &site.site_summary_key_expires_at) {
+            (Some(site_id), Some(_)) => {
+                key_expiry::seconds_left("auth_site", "site_summary_key_expires_at", site_id).await?
+            }
+            _ => None,
+        };
+        let mut sites = SITES.lock().unwrap();
+        if generation == GENERATION.load(Ordering::SeqCst) {
+            sites.insert(key, (site.clone(), expiry_instant(seconds_left, now)), Instant::now());
+        }
+    }
+    Ok(site)
+}
+
+/// 用户变更后失效其所有 key 的缓存
+pub fn invalidate_user(user_id: u64) {
+    let mut keys = KEYS.lock().unwrap();
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    keys.retain(|_, resolved| resolved.user.id != Some(user_id));
+}
+
+/// key 变更后失效其缓存
+pub fn invalidate_key(key_id: u64) {
+    let mut keys = KEYS.lock().unwrap();
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    keys.retain(|_, resolved| resolved.key_id != key_id);
+}
+
+/// 站点变更后失效其缓存
+pub fn invalidate_site(site_id: u64) {
+    let mut sites = SITES.lock().unwrap();
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    sites.retain(|_, (site, _)| site.id != Some(site_id));
+}
+
+/// 记录 key 的使用时间, 由 `run_last_used_flusher` 批量写库
+pub fn touch(key_id: u64) {
+    LAST_USED
+        .lock()
+        .unwrap()
+        .insert(key_id, date_utils::get_current_time_str());
+}
+
+/// 把缓存的使用时间写库, 返回写入条数; 失败的条目放回下次重试
+pub async fn flush_last_used() -> usize {
+    let pending = std::mem::take(&mut *LAST_USED.lock().unwrap());
+    let mut flushed = 0;
+    for (key_id, used_time) in pending {
+        match UserApiKey::update_last_used(key_id, &used_time).await {
+            Ok(()) => flushed += 1,
+            Err(e) => {
+                warn!("api key {} last used update error: {}", key_id, e);
+                LAST_USED.lock().unwrap().entry(key_id).or_insert(used_time);
+            }
+        }
+    }
+    flushed
+}
+
+/// 定期写入 key 的使用时间, 停机前写入剩余部分; 每个实例各自写入
+pub async fn run_last_used_flusher() {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(LAST_USED_FLUSH_INTERVAL) => {}
+            _ = shutdown::wait() => break,
+        }
+        flush_last_used().await;
+    }
+    let flushed = flush_last_used().await;
+    info!("api key last used flushed before shutdown: {}", flushed);
+}
+
+#[cfg(test)]
+mod key_service_tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate_user() {
+        let mut user = User::new();
+        user.id = Some(42);
+        KEYS.lock().unwrap().insert(
+            "key_42".to_string(),
+            ResolvedKey {
+                key_id: 7,
+                user,
+                expires_at: None,
+            },
+            Instant::now(),
+        );
+        let generation = GENERATION.load(Ordering::SeqCst);
+        invalidate_user(42);
+        assert!(GENERATION.load(Ordering::SeqCst) > generation);
+        assert!(KEYS
+            .lock()
+            .unwrap()
+            .get(&"key_42".to_string(), Instant::now())
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalidate_key() {
+        let mut user = User::new();
+        user.id = Some(43);
+        let mut keys = KEYS.lock().unwrap();
+        keys.insert(
+            "key_43a".to_string(),
+            ResolvedKey {
+                key_id: 8,
+                user: user.clone(),
+                expires_at: None,
+            },
+            Instant::now(),
+        );
+        keys.insert(
+            "key_43b".to_string(),
+            ResolvedKey {
+                key_id: 9,
+                user,
+                expires_at: None,
+            },
+            Instant::now(),
+        );
+        drop(keys);
+        invalidate_key(8);
+        let mut keys = KEYS.lock().unwrap();
+        assert!(keys.get(&"key_43a".to_string(), Instant::now()).is_none());
+        assert!(keys.get(&"key_43b".to_string(), Instant::now()).is_some());
+    }
+
+    #[test]
+    fn test_cached_key_expires() {
+        let now = Instant::now();
+        let mut user = User::new();
+        user.id = Some(44);
+        KEYS.lock().unwrap().insert(
+            "key_44".to_string(),
+            ResolvedKey {
+                key_id: 10,
+                user,
+                expires_at: expiry_instant(Some(5), now),
+            },
+            now,
+        );
+        assert!(cached_key(&"key_44".to_string(), now).is_some());
+        assert!(cached_key(&"key_44".to_string(), now + Duration::from_secs(5)).is_none());
+        // 过期的记录已被移除
+        assert!(KEYS.lock().unwrap().get(&"key_44".to_string(), now).is_none());
+    }
+}
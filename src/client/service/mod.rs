@@ -0,0 +1,9 @@
+pub mod import_service;
+pub mod backup_service;
+pub mod key_service;
+pub mod alert_service;
+pub mod notify_service;
+pub mod plan_service;
+pub mod expiry_service;
+pub mod pool_service;
+pub mod pricing_service;
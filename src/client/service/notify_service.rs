@@ -0,0 +1,97 @@
+//! 通过已启用的渠道发送通知, 并记录投递状态
+use std::collections::HashMap;
+
+use log::warn;
+
+use crate::{
+    client::entity::{alert::AlertHistory, notification::Notification, user::User},
+    error::Result,
+    notify::{
+        self,
+        template::{self, Language},
+        Message,
+    },
+    middleware::mask_key,
+    setting,
+};
+
+/// 按模板给用户发送通知, 每个渠道记录一条投递记录
+pub async fn notify(user: Option<&User>, template_name: &str, vars: HashMap<&str, String>) -> Result<()> {
+    let language = Language::parse(&setting::SETTING.notify.language);
+    let (subject, body) = template::render(template_name, language, &vars)?;
+    let message = Message {
+        template: template_name.to_string(),
+        language,
+        subject,
+        body,
+        data: serde_json::to_value(&vars)?,
+    };
+    let user_id = user.and_then(|u| u.id);
+    for channel in notify::channels() {
+        let recipient = match channel.recipient(user) {
+            Some(recipient) => recipient,
+            None => continue,
+        };
+        let id = Notification::create(user_id, channel.name(), &recipient, &message).await?;
+        let delivery = channel.send(&recipient, &message).await;
+        if let Err(e) = &delivery.result {
+            warn!("notification {} via {} failed: {}", id, channel.name(), e);
+        }
+        let error = delivery.result.err().map(|e| e.to_string());
+        Notification::finish(id, delivery.attempts, error).await?;
+    }
+    Ok(())
+}
+
+/// 账户相关的模板变量, summary_key 只给出脱敏后的前缀, 明文不进入通知内容和投递记录
+pub fn user_vars(user: &User) -> HashMap<&'static str, String> {
+    HashMap::from([
+        ("account", user.account.clone().unwrap_or_default()),
+        ("summary_key_hint", mask_key(user.summary_key.as_deref().unwrap_or_default())),
+    ])
+}
+
+/// 发送账户通知 (欢迎、key 更换等), 失败只记录日志
+pub async fn notify_account(user: &User, template_name: &str) {
+    if let Err(e) = notify(Some(user), template_name, user_vars(user)).await {
+        warn!("send {} notification error, user: {:?}, error: {}", template_name, user.id, e);
+    }
+}
+
+/// 后台发送账户通知, 不阻塞调用方
+pub fn spawn_notify_account(user: User, template_name: &'static str) {
+    if notify::channels().is_empty() {
+        return;
+    }
+    tokio::spawn(async move { notify_account(&user, template_name).await });
+}
+
+async fn notify_alert(alert: &AlertHistory) -> Result<()> {
+    let user = match alert.user_id {
+        Some(user_id) => User::find_by_id(user_id).await?,
+        None => None,
+    };
+    let mut vars = user.as_ref().map(user_vars).unwrap_or_default();
+    vars.remove("summary_key_hint");
+    vars.insert("value", alert.value.unwrap_or_default().to_string());
+    vars.insert("threshold", alert.threshold.unwrap_or_default().to_string());
+    if let Some(site_id) = alert.site_id {
+        vars.insert("site_id", site_id.to_string());
+    }
+    let template_name = format!("{}{}", template::ALERT_PREFIX, alert.kind.as_deref().unwrap_or_default());
+    notify(user.as_ref(), &template_name, vars).await
+}
+
+/// 后台发送告警通知, 不阻塞调用方
+pub fn notify_alerts(alerts: Vec<AlertHistory>) {
+    if alerts.is_empty() || notify::channels().is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        for alert in &alerts {
+            if let Err(e) = notify_alert(alert).await {
+                warn!("alert notify error: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc};
+use log::warn;
+
+use crate::{
+    client::entity::{plan::Plan, user::User},
+    error::{Error, Result},
+    middleware::rate_limit,
+    utils::date_utils,
+};
+
+/// 本月剩余时间占整月的比例
+pub fn remaining_fraction(month_begin: NaiveDateTime, now: NaiveDateTime) -> f64 {
+    let date = month_begin.date();
+    let next = if date.month() == 12 {
+        NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
+    }
+    .unwrap()
+    .and_hms_opt(0, 0, 0)
+    .unwrap();
+    let total = (next - month_begin).num_seconds() as f64;
+    let remaining = (next - now).num_seconds() as f64;
+    (remaining / total).clamp(0.0, 1.0)
+}
+
+/// 给本月尚未发放额度的套餐用户发放额度, 返回发放的用户数
+pub async fn grant_monthly_allowances() -> Result<usize> {
+    let month_begin = date_utils::get_current_month_begin();
+    let mut granted = 0;
+    for user_id in Plan::users_due(&month_begin).await? {
+        match User::grant_plan_allowance(user_id, &month_begin).await {
+            Ok(Some(_)) => granted += 1,
+            Ok(None) => {}
+            Err(e) => warn!("plan allowance error, user: {}, error: {}", user_id, e),
+        }
+    }
+    Ok(granted)
+}
+
+/// 按套餐刷新各用户的限流额度, 返回有套餐限额的用户数
+pub async fn refresh_rate_limits() -> Result<usize> {
+    let limits: HashMap<_, _> = Plan::user_limits().await?.into_iter().collect();
+    let count = limits.len();
+    rate_limit::set_plan_limits(limits);
+    Ok(count)
+}
+
+/// 更换用户套餐并按本月剩余时间折算额度, 返回 (折算的 tokens, 调整后余额)
+pub async fn change_plan(user_id: u64, plan_id: Option<u64>) -> Result<(i64, u64)> {
+    let plan = match plan_id {
+        Some(plan_id) => {
+            let plan = Plan::find_by_id(plan_id)
+                .await?
+                .filter(|p| p.active == Some(1))
+                .ok_or_else(|| Error::NotFound(format!("plan {}", plan_id)))?;
+            Some(plan)
+        }
+        None => None,
+    };
+    let month_begin = date_utils::get_current_month_begin();
+    let begin = date_utils::str_to_date(&month_begin, date_utils::DateFormat::YYYYMMDDHHMMSS)?;
+    let remaining = remaining_fraction(begin, Utc::now().naive_utc());
+    let result = User::change_plan(user_id, plan.as_ref(), &month_begin, remaining).await?;
+    if let Err(e) = refresh_rate_limits().await {
+        warn!("plan rate limit refresh error: {}", e);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_remaining_fraction() {
+        let begin = at("2023-04-01 00:00:00");
+        assert_eq!(remaining_fraction(begin, begin), 1.0);
+        assert_eq!(remaining_fraction(begin, at("2023-04-16 00:00:00")), 0.5);
+        assert_eq!(remaining_fraction(begin, at("2023-05-01 00:00:00")), 0.0);
+        let december = at("2023-12-01 00:00:00");
+        assert_eq!(remaining_fraction(december, at("2024-01-01 00:00:00")), 0.0);
+    }
+}
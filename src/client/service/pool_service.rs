@@ -0,0 +1,116 @@
+//! 共享 OpenAI key 池的选择
+//!
+//! 每个实例在内存中按平滑加权轮询选择 key, 可选的 key 定期从数据库刷新;
+//! 健康状态和月度上限的变化最多在 `openai_pool.refresh_seconds` 后生效。
+//!
+//! 本服务只分配 key id, key 本身不经任何 HTTP 接口返回。调用 OpenAI 的服务端用只读数据库账号
+//! 按 `/client/resolve` 返回的 pool_key_id 查询 `openai_pool_key.api_key` 并自行缓存;
+//! 用量上报时原样带回该 id, 扣减时校验 id 存在且用户没有自己的 openai_key。
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use crate::{
+    client::entity::openai_pool_key::OpenaiPoolKey, error::Result, setting, shutdown,
+    utils::date_utils,
+};
+
+/// 参与轮询的 key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub id: u64,
+    pub weight: i64,
+    /// 当前权重, 每轮加上 weight, 被选中后减去总权重
+    pub current: i64,
+}
+
+lazy_static! {
+    static ref CANDIDATES: Mutex<Vec<Candidate>> = Mutex::new(vec![]);
+}
+
+/// 平滑加权轮询, 权重高的 key 被均匀地穿插选中
+pub fn pick(candidates: &mut [Candidate]) -> Option<u64> {
+    let total: i64 = candidates.iter().map(|c| c.weight).sum();
+    if total <= 0 {
+        return None;
+    }
+    for candidate in candidates.iter_mut() {
+        candidate.current += candidate.weight;
+    }
+    // 当前权重最大的被选中, 相同时取靠前的
+    let mut best = 0;
+    for (i, candidate) in candidates.iter().enumerate() {
+        if candidate.current > candidates[best].current {
+            best = i;
+        }
+    }
+    candidates[best].current -= total;
+    Some(candidates[best].id)
+}
+
+/// 选择一个共享 key, 没有可用的 key 时返回 None
+pub fn select() -> Option<u64> {
+    pick(&mut CANDIDATES.lock().unwrap())
+}
+
+/// 从数据库刷新可选的 key, 保留仍可选 key 的当前权重, 返回可选 key 数量
+pub async fn refresh() -> Result<usize> {
+    let available = OpenaiPoolKey::available(&date_utils::get_current_month_begin()).await?;
+    let mut candidates = CANDIDATES.lock().unwrap();
+    let current: HashMap<u64, i64> = candidates.iter().map(|c| (c.id, c.current)).collect();
+    *candidates = available
+        .into_iter()
+        .map(|(id, weight)| Candidate {
+            id,
+            weight: weight as i64,
+            current: current.get(&id).copied().unwrap_or(0),
+        })
+        .collect();
+    Ok(candidates.len())
+}
+
+/// 定期刷新可选的 key, 每个实例各自刷新
+pub async fn run_refresher() {
+    let interval = Duration::from_secs(setting::SETTING.openai_pool.refresh_seconds.max(1));
+    loop {
+        match refresh().await {
+            Ok(count) => info!("openai pool keys refreshed: {}", count),
+            Err(e) => warn!("openai pool keys refresh error: {}", e),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown::wait() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use super::*;
+
+    fn candidates(weights: &[(u64, i64)]) -> Vec<Candidate> {
+        weights
+            .iter()
+            .map(|&(id, weight)| Candidate {
+                id,
+                weight,
+                current: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pick_smooth_weighted() {
+        let mut keys = candidates(&[(1, 5), (2, 1), (3, 1)]);
+        let picked: Vec<u64> = (0..7).map(|_| pick(&mut keys).unwrap()).collect();
+        assert_eq!(picked, vec![1, 1, 2, 1, 3, 1, 1]);
+        assert!(keys.iter().all(|c| c.current == 0));
+    }
+
+    #[test]
+    fn test_pick_empty() {
+        assert_eq!(pick(&mut []), None);
+        assert_eq!(pick(&mut candidates(&[(1, 0)])), None);
+    }
+}
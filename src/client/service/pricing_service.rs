@@ -0,0 +1,71 @@
+//! 按模型价格把原始 tokens 折算为计费 tokens
+use chrono::Utc;
+
+use crate::{
+    client::{entity::model_price::ModelPrice, model::usage_model::UsageEvent},
+    error::Result,
+    utils::date_utils,
+};
+
+/// 按价格折算计费 tokens, 向上取整; 没有价格时按原始 tokens 计费
+///
+/// 事件没有 prompt / completion 拆分时, total_tokens 按两者中较高的倍率折算
+pub fn billed_tokens(price: Option<&ModelPrice>, prompt: u64, completion: u64, total: u64) -> u64 {
+    let price = match price {
+        Some(price) => price,
+        None => return total.max(prompt.saturating_add(completion)),
+    };
+    let prompt_multiplier = price.prompt_multiplier.unwrap_or(1.0);
+    let completion_multiplier = price.completion_multiplier.unwrap_or(1.0);
+    let billed = if prompt.saturating_add(completion) == 0 {
+        total as f64 * prompt_multiplier.max(completion_multiplier)
+    } else {
+        prompt as f64 * prompt_multiplier + completion as f64 * completion_multiplier
+    };
+    billed.ceil().max(0.0) as u64
+}
+
+/// 按事件发生时生效的模型价格折算计费 tokens, 没有事件时间时按当前价格
+///
+/// 价格的 effective_from 按 UTC 存储, 事件时间同样按 UTC 比较
+pub async fn bill(event: &UsageEvent) -> Result<u64> {
+    let price = match event.model.as_deref().filter(|m| !m.is_empty()) {
+        Some(model) => {
+            let at = date_utils::mills_convert_datetime(event.event_time).unwrap_or_else(Utc::now);
+            let at = date_utils::naive_date_time_to_string(at.naive_utc());
+            ModelPrice::find_effective(model, &at).await?
+        }
+        None => None,
+    };
+    Ok(billed_tokens(
+        price.as_ref(),
+        event.prompt_tokens,
+        event.completion_tokens,
+        event.total_tokens,
+    ))
+}
+
+#[cfg(test)]
+mod pricing_tests {
+    use super::*;
+
+    fn price(prompt: f64, completion: f64) -> ModelPrice {
+        ModelPrice {
+            prompt_multiplier: Some(prompt),
+            completion_multiplier: Some(completion),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_billed_tokens() {
+        assert_eq!(billed_tokens(None, 100, 50, 150), 150);
+        assert_eq!(billed_tokens(Some(&price(1.5, 2.0)), 100, 50, 150), 250);
+        // 向上取整
+        assert_eq!(billed_tokens(Some(&price(0.1, 0.1)), 3, 3, 6), 1);
+        // 没有拆分时按较高倍率
+        assert_eq!(billed_tokens(Some(&price(1.0, 3.0)), 0, 0, 10), 30);
+        // 上报的 tokens 异常大时不溢出
+        assert_eq!(billed_tokens(None, u64::MAX, 1, 0), u64::MAX);
+    }
+}
@@ -54,6 +54,22 @@ pub async fn init_connections(conn_string: &str) -> Result<()> {
     Ok(())
 }
 
+/// 执行 migrations 目录下未执行的数据库迁移
+pub async fn run_migrations() -> Result<()> {
+    sqlx::migrate!("./migrations").run(&get_pool()).await?;
+    Ok(())
+}
+
+/// 关闭连接池, 停机时调用
+pub async fn close() {
+    let pools = POOLS.lock().unwrap().clone();
+    for pool in pools {
+        pool.close().await;
+    }
+    // rbatis 连接池在最后一个引用释放时关闭
+    *RB.lock().unwrap() = RBatis::new();
+}
+
 /// get pool
 pub fn get_pool() -> Pool<MySql> {
     let pools = POOLS.lock().unwrap();
@@ -1,11 +1,18 @@
 use std::fmt::{Debug, Display, Formatter};
 use std::num::ParseIntError;
 
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{
+    http::{
+        header::{HeaderValue, RETRY_AFTER},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use log::error;
 use rbatis::rbdc;
 use thiserror::Error;
 
-use crate::api::error;
+use crate::{api, utils::validate::FieldError};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -17,6 +24,9 @@ pub enum Error {
     #[error("database error: {0}")]
     DatabaseError2(#[from] rbdc::Error),
 
+    #[error("migrate error: {0}")]
+    MigrateError(#[from] sqlx::migrate::MigrateError),
+
     #[error("io error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -35,12 +45,33 @@ pub enum Error {
 
     #[error("KafkaError: {0}")]
     KafkaError(#[from] rdkafka::error::KafkaError),
+
+    #[error("PrometheusError: {0}")]
+    PrometheusError(#[from] prometheus::Error),
+
+    #[error("NotifyError: {0}")]
+    NotifyError(String),
+
+    #[error("配置错误: {0}")]
+    ConfigError(String),
  
     #[error("{0}")]
     ApiError(String),
 
     #[error("无效参数: {0}")]
-    InvalidParam(String),
+    InvalidParam(String, Vec<FieldError>),
+
+    #[error("未授权: {0}")]
+    Unauthorized(String),
+
+    #[error("无权限: {0}")]
+    Forbidden(String),
+
+    #[error("不存在: {0}")]
+    NotFound(String),
+
+    #[error("冲突: {0}")]
+    Conflict(String),
 
    #[error("商户名称已存在")]
     DuplicateMerchantName,
@@ -48,13 +79,97 @@ pub enum Error {
     #[error("{0}")]
     BizError(String),
 
+    #[error("请求过于频繁, 请 {0} 秒后重试")]
+    TooManyRequests(u64),
+
     #[error("ParseIntError: {0}")]
     ParseIntError(#[from] ParseIntError),
 }
 
+/// 错误码
+///
+/// 对外稳定, 客户端可据此处理错误:
+/// - 1000-1099 参数错误 (400)
+/// - 1100 未授权 (401)
+/// - 1200 无权限 (403)
+/// - 1300 不存在 (404)
+/// - 1400-1499 冲突 (409)
+/// - 1500 业务错误 (400)
+/// - 1600 请求过于频繁 (429)
+/// - 5000 内部错误 (500)
+/// - 5030 服务未就绪 (503)
+pub mod code {
+    pub const INVALID_PARAM: u32 = 1000;
+    pub const PARSE_ERROR: u32 = 1001;
+    pub const UNAUTHORIZED: u32 = 1100;
+    pub const FORBIDDEN: u32 = 1200;
+    pub const NOT_FOUND: u32 = 1300;
+    pub const CONFLICT: u32 = 1400;
+    pub const DUPLICATE_MERCHANT_NAME: u32 = 1401;
+    pub const BIZ_ERROR: u32 = 1500;
+    pub const TOO_MANY_REQUESTS: u32 = 1600;
+    pub const INTERNAL: u32 = 5000;
+    pub const UNAVAILABLE: u32 = 5030;
+}
+
+impl Error {
+    /// 不带字段明细的参数错误
+    pub fn invalid_param(message: impl Into<String>) -> Error {
+        Error::InvalidParam(message.into(), vec![])
+    }
+
+    /// 对外的错误码
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::InvalidParam(..) => code::INVALID_PARAM,
+            Error::ParseError(_) | Error::ParseIntError(_) => code::PARSE_ERROR,
+            Error::Unauthorized(_) => code::UNAUTHORIZED,
+            Error::Forbidden(_) => code::FORBIDDEN,
+            Error::NotFound(_) => code::NOT_FOUND,
+            Error::Conflict(_) => code::CONFLICT,
+            Error::DuplicateMerchantName => code::DUPLICATE_MERCHANT_NAME,
+            Error::BizError(_) => code::BIZ_ERROR,
+            Error::TooManyRequests(_) => code::TOO_MANY_REQUESTS,
+            _ => code::INTERNAL,
+        }
+    }
+
+    /// 是否为内部错误, 内部错误的详情不返回给客户端
+    pub fn is_internal(&self) -> bool {
+        self.code() == code::INTERNAL
+    }
+}
+
 impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self.code() {
+            code::UNAUTHORIZED => StatusCode::UNAUTHORIZED,
+            code::FORBIDDEN => StatusCode::FORBIDDEN,
+            code::NOT_FOUND => StatusCode::NOT_FOUND,
+            code::CONFLICT | code::DUPLICATE_MERCHANT_NAME => StatusCode::CONFLICT,
+            code::TOO_MANY_REQUESTS => StatusCode::TOO_MANY_REQUESTS,
+            code::INTERNAL => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
-        error(Some(self.to_string()))
+        let message = if self.is_internal() {
+            error!("internal error: {:?}", self);
+            "服务内部错误".to_string()
+        } else {
+            self.to_string()
+        };
+        let data = match self {
+            Error::InvalidParam(_, fields) if !fields.is_empty() => serde_json::to_value(fields).ok(),
+            _ => None,
+        };
+        let mut res = api::error(self.status_code(), self.code(), data, Some(message));
+        if let Error::TooManyRequests(retry_after) = self {
+            res.headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+        }
+        res
     }
 }
 
@@ -88,4 +203,26 @@ impl std::error::Error for BizError{
     }
 }
 unsafe impl Send for BizError {}
-unsafe impl Sync for BizError {}
\ No newline at end of file
+unsafe impl Sync for BizError {}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code_and_status() {
+        let e = Error::invalid_param("account");
+        assert_eq!(e.code(), code::INVALID_PARAM);
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+
+        let e = Error::NotFound("user".to_string());
+        assert_eq!(e.status_code(), StatusCode::NOT_FOUND);
+
+        let e = Error::DuplicateMerchantName;
+        assert_eq!(e.status_code(), StatusCode::CONFLICT);
+
+        let e = Error::IoError(std::io::Error::new(std::io::ErrorKind::Other, "disk"));
+        assert!(e.is_internal());
+        assert_eq!(e.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use rdkafka::{
+    consumer::{BaseConsumer, Consumer},
+    error::KafkaResult,
+    ClientConfig,
+};
+
+use crate::{error::Result, setting};
+
+pub mod outbox_publisher;
+pub mod usage_consumer;
+
+/// kafka 是否已配置
+pub fn enabled() -> bool {
+    !setting::SETTING.kafka.brokers.is_empty()
+}
+
+/// 是否发布领域事件
+pub fn events_enabled() -> bool {
+    enabled() && !setting::SETTING.kafka.events_topic.is_empty()
+}
+
+/// 公共的 kafka 客户端配置
+pub fn client_config() -> ClientConfig {
+    let kafka = &setting::SETTING.kafka;
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", &kafka.brokers);
+    config
+}
+
+lazy_static! {
+    /// 仅用于探测集群连通性的客户端
+    static ref PROBE: KafkaResult<BaseConsumer> = client_config().create();
+}
+
+/// 探测 kafka 连通性, 会阻塞直到拉取到集群元数据或超时
+pub fn ping(timeout: Duration) -> Result<()> {
+    match &*PROBE {
+        Ok(consumer) => {
+            consumer.fetch_metadata(None, timeout)?;
+            Ok(())
+        }
+        Err(e) => Err(e.clone().into()),
+    }
+}
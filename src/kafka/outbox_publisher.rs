@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use serde::Serialize;
+use sqlx::Row;
+
+use crate::{db, error::Result, kafka, leader, metrics, setting, shutdown};
+
+/// 每批发布的事件数
+const BATCH_SIZE: u32 = 100;
+/// 没有待发布事件时的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// 单条消息的投递超时
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// 认领的有效期, 须大于发送一批事件的最长耗时
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 发布到 kafka 的事件格式
+#[derive(Serialize, Debug)]
+struct Envelope {
+    id: u64,
+    #[serde(rename = "type")]
+    event_type: String,
+    aggregate_id: u64,
+    payload: serde_json::Value,
+    created_time: Option<String>,
+}
+
+impl Envelope {
+    fn from_row(row: &sqlx::mysql::MySqlRow) -> Result<Envelope> {
+        let payload: String = row.try_get("payload")?;
+        Ok(Envelope {
+            id: row.try_get("id")?,
+            event_type: row.try_get("event_type")?,
+            aggregate_id: row.try_get("aggregate_id")?,
+            payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::String(payload)),
+            created_time: row.try_get("created_time")?,
+        })
+    }
+}
+
+/// 认领一批未发布的事件
+///
+/// 在短事务中用 `for update skip locked` 选出事件并写入认领到期时间后立即提交,
+/// 多个实例同时运行时不会重复认领 (需 MySQL 8); 认领后异常退出的事件到期后可被重新认领。
+async fn claim_batch() -> Result<Vec<Envelope>> {
+    let mut tx = db::get_pool().begin().await?;
+    let sql = "select id, event_type, aggregate_id, payload, date_format(created_time, '%Y-%m-%d %H:%i:%s') as created_time \
+               from outbox_event where published_time is null and (claimed_until is null or claimed_until < now()) \
+               order by id limit ? for update skip locked";
+    let rows = sqlx::query(sql).bind(BATCH_SIZE).fetch_all(&mut *tx).await?;
+    let envelopes = rows.iter().map(Envelope::from_row).collect::<Result<Vec<_>>>()?;
+
+    if !envelopes.is_empty() {
+        let sql = format!(
+            "update outbox_event set claimed_until = date_add(now(), interval ? second) where id in ({})",
+            placeholders(envelopes.len())
+        );
+        let mut query = sqlx::query(&sql).bind(CLAIM_TIMEOUT.as_secs());
+        for envelope in &envelopes {
+            query = query.bind(envelope.id);
+        }
+        query.execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(envelopes)
+}
+
+fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
+}
+
+/// 发布一批未发布的事件, 返回发布成功的条数
+///
+/// 先认领再在事务外逐条发送, 最后标记已发布的事件并释放未发送事件的认领。
+/// 某条事件发布失败时停止本批, 保证同一批内按 id 顺序发布。
+async fn publish_batch(producer: &FutureProducer, topic: &str) -> Result<usize> {
+    let envelopes = claim_batch().await?;
+
+    let mut published = vec![];
+    let mut failed = None;
+    for envelope in &envelopes {
+        let key = envelope.aggregate_id.to_string();
+        let body = serde_json::to_string(envelope)?;
+        let record = FutureRecord::to(topic).key(&key).payload(&body);
+        match producer.send(record, DELIVERY_TIMEOUT).await {
+            Ok(_) => {
+                published.push(envelope.id);
+                metrics::observe_kafka_event(topic, true);
+            }
+            Err((e, _)) => {
+                warn!("outbox event {} publish error: {}", envelope.id, e);
+                metrics::observe_kafka_event(topic, false);
+                failed = Some(envelope.id);
+                break;
+            }
+        }
+    }
+
+    if !published.is_empty() {
+        let sql = format!(
+            "update outbox_event set published_time = now(), claimed_until = null where id in ({})",
+            placeholders(published.len())
+        );
+        let mut query = sqlx::query(&sql);
+        for id in &published {
+            query = query.bind(id);
+        }
+        query.execute(&db::get_pool()).await?;
+    }
+    if let Some(failed) = failed {
+        sqlx::query("update outbox_event set attempts = attempts + 1 where id = ?")
+            .bind(failed)
+            .execute(&db::get_pool())
+            .await?;
+        // 释放本批未发送的事件, 下一轮按 id 顺序重新认领
+        let unsent: Vec<u64> = envelopes.iter().map(|e| e.id).filter(|id| *id >= failed).collect();
+        let sql = format!(
+            "update outbox_event set claimed_until = null where id in ({})",
+            placeholders(unsent.len())
+        );
+        let mut query = sqlx::query(&sql);
+        for id in &unsent {
+            query = query.bind(id);
+        }
+        query.execute(&db::get_pool()).await?;
+    }
+    Ok(published.len())
+}
+
+/// 启动事件发布, 轮询 outbox_event 表发布到 kafka, 收到停机通知或失去 leader 身份后退出
+pub async fn run() -> Result<()> {
+    let topic = setting::SETTING.kafka.events_topic.as_str();
+    let producer: FutureProducer = kafka::client_config()
+        .set("message.timeout.ms", &DELIVERY_TIMEOUT.as_millis().to_string())
+        .create()?;
+    info!("outbox publisher started, topic: {}", topic);
+
+    while !shutdown::is_triggered() && leader::is_leader(leader::OUTBOX_PUBLISHER) {
+        let published = match publish_batch(&producer, topic).await {
+            Ok(published) => published,
+            Err(e) => {
+                warn!("outbox publish error: {}", e);
+                0
+            }
+        };
+        if published < BATCH_SIZE as usize {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = shutdown::wait() => break,
+                _ = leader::wait_lost(leader::OUTBOX_PUBLISHER) => break,
+            }
+        }
+    }
+
+    if let Err(e) = producer.flush(DELIVERY_TIMEOUT) {
+        warn!("outbox producer flush error: {}", e);
+    }
+    info!("outbox publisher stopped");
+    Ok(())
+}
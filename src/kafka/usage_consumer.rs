@@ -0,0 +1,244 @@
+//! 用量消费者: 从 `kafka.usage_topic` 读取用量事件, 按 api key 扣减 tokens 并在处理成功后提交 offset
+//!
+//! /metrics 中的 kafka 消费延迟和已处理事件数由这里上报; 之前服务没有任何 kafka 消费者,
+//! 这些指标无从采集。未配置 `kafka.brokers` 时不启动, 也就没有 kafka 相关指标。
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use rdkafka::{
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    message::BorrowedMessage,
+    producer::{FutureProducer, FutureRecord},
+    Message, Offset, TopicPartitionList,
+};
+use serde::Serialize;
+
+use crate::{
+    client::{
+        entity::{token_ledger::TokenLedger, user::User},
+        model::usage_model::UsageEvent,
+        service::{alert_service, pricing_service},
+    },
+    error::{Error, Result},
+    kafka, leader, metrics, setting, shutdown,
+};
+
+/// 消费延迟的刷新间隔
+const LAG_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+/// 处理失败后的首次重试间隔, 之后逐次翻倍
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+/// 重试间隔上限
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+/// 处理失败达到该次数后转发到死信 topic
+const MAX_ATTEMPTS: u32 = 5;
+/// 死信消息的投递超时
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 转发到死信 topic 的消息格式
+#[derive(Serialize, Debug)]
+struct DeadLetter<'a> {
+    event_key: &'a str,
+    error: String,
+    payload: String,
+}
+
+/// 消息在 kafka 中的唯一标识, 记录到扣减流水用于去重
+fn event_key(topic: &str, partition: i32, offset: i64) -> String {
+    format!("{}:{}:{}", topic, partition, offset)
+}
+
+/// 处理一条用量消息: 按事件时间的模型价格折算计费 tokens 并按 api key 扣减, 然后检查告警规则
+///
+/// 已扣减过的消息 (重复投递或提交 offset 前重启) 直接跳过
+async fn handle_payload(payload: &[u8], event_key: &str) -> Result<()> {
+    let event: UsageEvent = serde_json::from_slice(payload)?;
+    if TokenLedger::has_event(event_key).await? {
+        info!("usage event {} already debited, skipped", event_key);
+        return Ok(());
+    }
+    let billed = pricing_service::bill(&event).await?;
+    let user_id = User::debit_tokens(&event, billed, event_key).await?;
+    match user_id {
+        Some(user_id) => {
+            // 告警失败不影响扣减结果
+            if let Err(e) = alert_service::evaluate(user_id).await {
+                warn!("alert evaluate error, user: {}, error: {}", user_id, e);
+            }
+        }
+        None => warn!("usage event for unknown summary key: {:?}", event),
+    }
+    Ok(())
+}
+
+/// 把处理失败的消息转发到死信 topic
+async fn send_dead_letter(
+    producer: &FutureProducer,
+    topic: &str,
+    event_key: &str,
+    payload: &[u8],
+    error: &Error,
+) -> Result<()> {
+    let body = serde_json::to_string(&DeadLetter {
+        event_key,
+        error: error.to_string(),
+        payload: String::from_utf8_lossy(payload).into_owned(),
+    })?;
+    let record = FutureRecord::to(topic).key(event_key).payload(&body);
+    producer
+        .send(record, DELIVERY_TIMEOUT)
+        .await
+        .map_err(|(e, _)| Error::from(e))?;
+    Ok(())
+}
+
+/// 处理一条消息直到成功或转发到死信 topic, 返回是否可以提交 offset
+///
+/// 格式错误或内容无效的消息不重试; 其余错误按退避间隔重试, 达到 MAX_ATTEMPTS 后转发到死信 topic,
+/// 没有配置死信 topic 或转发失败时继续重试。等待重试时收到停机通知或失去 leader 身份返回 false,
+/// 该消息不提交, 下次消费时重新处理。
+async fn process(message: &BorrowedMessage<'_>, dead_letter: Option<&FutureProducer>) -> bool {
+    let payload = match message.payload() {
+        Some(payload) => payload,
+        None => return true,
+    };
+    let topic = message.topic();
+    let key = event_key(topic, message.partition(), message.offset());
+    let dead_letter_topic = setting::SETTING.kafka.dead_letter_topic.as_str();
+
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = handle_payload(payload, &key).await;
+        metrics::observe_kafka_event(topic, result.is_ok());
+        let err = match result {
+            Ok(()) => return true,
+            Err(err) => err,
+        };
+        error!(
+            "usage event handle error, event: {}, attempt: {}, error: {}",
+            key, attempts, err
+        );
+
+        // 格式错误或内容无效 (如未知的 pool_key_id) 的消息重试也不会成功
+        let malformed = matches!(err, Error::SerdeJsonError(_) | Error::InvalidParam(..));
+        if malformed || attempts >= MAX_ATTEMPTS {
+            match dead_letter {
+                Some(producer) => {
+                    match send_dead_letter(producer, dead_letter_topic, &key, payload, &err).await {
+                        Ok(()) => {
+                            warn!("usage event {} sent to {}", key, dead_letter_topic);
+                            return true;
+                        }
+                        Err(e) => warn!("usage event {} dead letter error: {}", key, e),
+                    }
+                }
+                None if malformed => {
+                    error!("malformed usage event {} dropped", key);
+                    return true;
+                }
+                None => {}
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown::wait() => return false,
+            _ = leader::wait_lost(leader::USAGE_CONSUMER) => return false,
+        }
+        backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+    }
+}
+
+/// 启动用量消费者, 逐条处理并在处理成功后提交 offset, 收到停机通知或失去 leader 身份后退出
+pub async fn run() -> Result<()> {
+    let config = &setting::SETTING.kafka;
+    let topic = config.usage_topic.as_str();
+
+    let mut client_config = kafka::client_config();
+    client_config
+        .set("group.id", &config.group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest");
+    // fetch_watermarks 是阻塞调用, 需要把 consumer 交给 spawn_blocking
+    let consumer: Arc<StreamConsumer> = Arc::new(client_config.create()?);
+    consumer.subscribe(&[topic])?;
+    info!("usage consumer subscribed to {}", topic);
+
+    let dead_letter: Option<FutureProducer> = if config.dead_letter_topic.is_empty() {
+        None
+    } else {
+        Some(
+            kafka::client_config()
+                .set("message.timeout.ms", &DELIVERY_TIMEOUT.as_millis().to_string())
+                .create()?,
+        )
+    };
+
+    // 每个分区最后处理成功的 offset
+    let mut processed: HashMap<i32, i64> = HashMap::new();
+    let mut lag_checked_at: HashMap<i32, Instant> = HashMap::new();
+    loop {
+        let received = tokio::select! {
+            _ = shutdown::wait() => break,
+            _ = leader::wait_lost(leader::USAGE_CONSUMER) => {
+                warn!("usage consumer lost leadership");
+                break;
+            }
+            received = consumer.recv() => received,
+        };
+        let message = match received {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("usage consumer receive error: {}", e);
+                continue;
+            }
+        };
+
+        let partition = message.partition();
+        let due = lag_checked_at
+            .get(&partition)
+            .map_or(true, |at| at.elapsed() >= LAG_REFRESH_INTERVAL);
+        if due {
+            let watermarks = {
+                let consumer = consumer.clone();
+                let topic = topic.to_string();
+                tokio::task::spawn_blocking(move || {
+                    consumer.fetch_watermarks(&topic, partition, Duration::from_millis(500))
+                })
+                .await
+            };
+            if let Ok(Ok((_, high))) = watermarks {
+                metrics::set_kafka_lag(topic, partition, high - message.offset() - 1);
+            }
+            lag_checked_at.insert(partition, Instant::now());
+        }
+
+        // 未处理成功的消息不提交, 也不再消费后面的消息, 以免其 offset 被一并提交
+        if !process(&message, dead_letter.as_ref()).await {
+            break;
+        }
+        processed.insert(partition, message.offset());
+        if let Err(e) = consumer.commit_message(&message, CommitMode::Async) {
+            warn!("usage consumer commit error: {}", e);
+        }
+    }
+
+    // 退出前同步提交已处理的 offset; 不能提交消费位置, 它可能越过未处理成功的消息
+    if !processed.is_empty() {
+        let mut offsets = TopicPartitionList::new();
+        for (partition, offset) in &processed {
+            offsets.add_partition_offset(topic, *partition, Offset::Offset(offset + 1))?;
+        }
+        if let Err(e) = consumer.commit(&offsets, CommitMode::Sync) {
+            warn!("usage consumer final commit error: {}", e);
+        }
+    }
+    consumer.unsubscribe();
+    info!("usage consumer stopped");
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! 基于 MySQL 租约表的 leader 选举, 保证定时任务和消费者在多实例部署时只有一个实例执行
+use std::{collections::HashMap, future::Future, sync::Mutex, time::Duration};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::Row;
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::{db, error::Result, setting, shutdown, utils::uuid};
+
+/// 定时任务
+pub const SCHEDULER: &str = "scheduler";
+/// kafka 用量消费者
+pub const USAGE_CONSUMER: &str = "usage_consumer";
+/// outbox 事件发布
+pub const OUTBOX_PUBLISHER: &str = "outbox_publisher";
+
+const ACQUIRE_SQL: &str = "insert into leader_lease (name, holder, expires_at, updated_time) \
+    values (?, ?, now(3) + interval ? second, now(3)) \
+    on duplicate key update \
+    holder = if(holder = values(holder) or expires_at < now(3), values(holder), holder), \
+    expires_at = if(holder = values(holder), values(expires_at), expires_at), \
+    updated_time = if(holder = values(holder), values(updated_time), updated_time)";
+
+lazy_static! {
+    /// 当前实例 id: 主机名:进程号:随机串
+    pub static ref INSTANCE_ID: String = format!(
+        "{}:{}:{}",
+        std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string()),
+        std::process::id(),
+        uuid::new_summary_key()
+    );
+    static ref STATES: Mutex<HashMap<&'static str, watch::Sender<bool>>> = Mutex::new(HashMap::new());
+}
+
+/// 租约持有情况
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Lease {
+    pub name: String,
+    pub holder: String,
+    pub expires_at: String,
+    /// 是否由当前实例持有
+    pub is_self: bool,
+}
+
+/// 是否启用 leader 选举
+pub fn enabled() -> bool {
+    setting::SETTING.leader.enabled
+}
+
+fn state(name: &'static str) -> watch::Sender<bool> {
+    STATES
+        .lock()
+        .unwrap()
+        .entry(name)
+        .or_insert_with(|| watch::channel(!enabled()).0)
+        .clone()
+}
+
+/// 当前实例是否持有 name 的租约
+pub fn is_leader(name: &'static str) -> bool {
+    *state(name).borrow()
+}
+
+/// 尝试获取或续约租约, 返回是否持有
+async fn try_acquire(name: &str, lease_seconds: u64) -> Result<bool> {
+    let pool = db::get_pool();
+    sqlx::query(ACQUIRE_SQL)
+        .bind(name)
+        .bind(INSTANCE_ID.as_str())
+        .bind(lease_seconds)
+        .execute(&pool)
+        .await?;
+    let row = sqlx::query("select holder from leader_lease where name = ?")
+        .bind(name)
+        .fetch_one(&pool)
+        .await?;
+    let holder: String = row.try_get("holder")?;
+    Ok(holder == *INSTANCE_ID)
+}
+
+/// 主动释放租约, 其他实例可以立即接管
+async fn release(name: &str) -> Result<()> {
+    sqlx::query("delete from leader_lease where name = ? and holder = ?")
+        .bind(name)
+        .bind(INSTANCE_ID.as_str())
+        .execute(&db::get_pool())
+        .await?;
+    Ok(())
+}
+
+/// 所有租约
+pub async fn leases() -> Result<Vec<Lease>> {
+    let sql = "select name, holder, cast(expires_at as char) as expires_at from leader_lease order by name";
+    let rows = sqlx::query(sql).fetch_all(&db::get_pool()).await?;
+    let mut leases = Vec::with_capacity(rows.len());
+    for row in rows {
+        let holder: String = row.try_get("holder")?;
+        leases.push(Lease {
+            name: row.try_get("name")?,
+            is_self: holder == *INSTANCE_ID,
+            holder,
+            expires_at: row.try_get("expires_at")?,
+        });
+    }
+    Ok(leases)
+}
+
+/// 定期获取或续约租约, 续约失败时立即放弃 leader 身份, 停机时释放租约
+pub async fn run(name: &'static str) {
+    let config = &setting::SETTING.leader;
+    let state = state(name);
+    if !config.enabled {
+        return;
+    }
+    let renew = Duration::from_secs(config.renew_seconds.clamp(1, config.lease_seconds.max(2) - 1));
+    info!("leader election for {} started, instance: {}", name, *INSTANCE_ID);
+    loop {
+        let leader = match try_acquire(name, config.lease_seconds).await {
+            Ok(leader) => leader,
+            Err(e) => {
+                warn!("leader lease {} renew error: {}", name, e);
+                false
+            }
+        };
+        let was_leader = state.send_replace(leader);
+        if leader != was_leader {
+            info!("leader {}: {}", name, if leader { "acquired" } else { "lost" });
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(renew) => {}
+            _ = shutdown::wait() => break,
+        }
+    }
+    // 先让依赖租约的任务退出, 再释放租约
+    state.send_replace(false);
+    if let Err(e) = release(name).await {
+        warn!("leader lease {} release error: {}", name, e);
+    }
+}
+
+/// 等待成为 leader, 停机时返回 false
+pub async fn wait_leader(name: &'static str) -> bool {
+    let mut rx = state(name).subscribe();
+    loop {
+        if *rx.borrow() {
+            return true;
+        }
+        tokio::select! {
+            changed = rx.changed() => if changed.is_err() { return false },
+            _ = shutdown::wait() => return false,
+        }
+    }
+}
+
+/// 等待失去 leader 身份, 任务在 select 中使用
+pub async fn wait_lost(name: &'static str) {
+    let mut rx = state(name).subscribe();
+    while *rx.borrow() {
+        if rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// 成为 leader 后执行 task, task 退出后若未停机则重新等待 leader 身份
+///
+/// task 需要在失去 leader 身份时自行退出 (select `wait_lost`)
+pub async fn run_as_leader<F, Fut>(name: &'static str, task: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    while wait_leader(name).await {
+        info!("{} starting as leader", name);
+        task().await;
+        if shutdown::is_triggered() {
+            break;
+        }
+        if is_leader(name) {
+            // 任务异常退出但仍是 leader, 稍后重启
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(5)) => {}
+                _ = shutdown::wait() => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod leader_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_lost() {
+        let name = "test_wait_lost";
+        let state = state(name);
+        state.send_replace(true);
+        assert!(is_leader(name));
+        assert!(wait_leader(name).await);
+
+        let lost = tokio::spawn(wait_lost(name));
+        tokio::task::yield_now().await;
+        assert!(!lost.is_finished());
+        state.send_replace(false);
+        tokio::time::timeout(Duration::from_secs(1), lost).await.unwrap().unwrap();
+        assert!(!is_leader(name));
+    }
+}
@@ -3,6 +3,13 @@ pub mod setting;
 pub mod db;
 
 pub mod api;
+pub mod middleware;
+pub mod metrics;
+pub mod kafka;
+pub mod shutdown;
+pub mod leader;
+pub mod notify;
+pub mod scheduler;
 
 pub mod client;
 pub mod utils;
\ No newline at end of file
@@ -1,29 +1,106 @@
+use std::time::Duration;
+
 use actix_web::App;
 use actix_web::HttpServer;
-use log::info;
+use log::{error, info};
 use summary_gpt_server_admin::api;
+use summary_gpt_server_admin::client::service::{key_service, pool_service};
 use summary_gpt_server_admin::db;
 use summary_gpt_server_admin::error::Result;
+use summary_gpt_server_admin::kafka;
+use summary_gpt_server_admin::leader;
+use summary_gpt_server_admin::middleware::cors;
+use summary_gpt_server_admin::middleware::request_id::RequestId;
+use summary_gpt_server_admin::scheduler;
 use summary_gpt_server_admin::setting;
+use summary_gpt_server_admin::shutdown;
+use tokio::time::Instant;
 
 #[actix_web::main]
 async fn main() -> Result<()> {
     setting::log_init();
+    if let Err(e) = setting::validate() {
+        error!("{}", e);
+        return Err(e);
+    }
     let conn_string = setting::get_conn_string();
     info!("conn_string:{}", conn_string);
     db::init_connections(conn_string.as_str()).await?;
 
+    // 多实例部署时由持有租约的实例执行
+    if kafka::enabled() {
+        shutdown::spawn("usage_consumer_lease", leader::run(leader::USAGE_CONSUMER));
+        shutdown::spawn(
+            "usage_consumer",
+            leader::run_as_leader(leader::USAGE_CONSUMER, || async {
+                if let Err(e) = kafka::usage_consumer::run().await {
+                    error!("usage consumer stopped: {}", e);
+                }
+            }),
+        );
+    }
+
+    if kafka::events_enabled() {
+        shutdown::spawn("outbox_publisher_lease", leader::run(leader::OUTBOX_PUBLISHER));
+        shutdown::spawn(
+            "outbox_publisher",
+            leader::run_as_leader(leader::OUTBOX_PUBLISHER, || async {
+                if let Err(e) = kafka::outbox_publisher::run().await {
+                    error!("outbox publisher stopped: {}", e);
+                }
+            }),
+        );
+    }
+
+    if scheduler::enabled() {
+        shutdown::spawn("scheduler_lease", leader::run(leader::SCHEDULER));
+        shutdown::spawn("scheduler", scheduler::run());
+    }
+
+    // 每个实例各自写入 api key 最近使用时间
+    shutdown::spawn("api_key_last_used", key_service::run_last_used_flusher());
+    shutdown::spawn("openai_pool_refresh", pool_service::run_refresher());
+
+    if cors::site_domains_enabled() {
+        shutdown::spawn("cors_site_domains", cors::run_site_domain_refresher());
+    }
+
     let config = &*setting::SETTING;
     let app = &config.app;
+    let timeout = Duration::from_secs(config.shutdown.timeout_seconds);
     info!("server listening at http://{}:{}", app.host, app.port);
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .wrap(RequestId)
+            .app_data(api::json_config())
+            .app_data(api::query_config())
             .service(api::routes())
+            .service(api::openapi::swagger_ui())
     })
     .bind((app.host.as_str(), app.port))?
-    .run()
-    .await?;
+    .disable_signals()
+    .shutdown_timeout(timeout.as_secs())
+    .run();
+
+    // 收到信号后停止接收新连接, 等待处理中的请求完成;
+    // 请求排空和后台任务退出共用收到信号时确定的截止时间
+    let handle = server.handle();
+    let (deadline_tx, mut deadline_rx) = tokio::sync::oneshot::channel();
+    actix_web::rt::spawn(async move {
+        shutdown::signal().await;
+        info!("shutting down, waiting up to {:?}", timeout);
+        let _ = deadline_tx.send(Instant::now() + timeout);
+        handle.stop(true).await;
+    });
+    server.await?;
 
+    let deadline = deadline_rx
+        .try_recv()
+        .unwrap_or_else(|_| Instant::now() + timeout);
+    shutdown::trigger();
+    shutdown::join_tasks(deadline).await;
+    db::close().await;
+    info!("server stopped");
     Ok(())
 }
@@ -0,0 +1,159 @@
+use lazy_static::lazy_static;
+use log::warn;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use sqlx::Row;
+
+use crate::{db, error::Result};
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "HTTP 请求数",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DURATION_SECONDS: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP 请求耗时",
+        &["method", "route"]
+    )
+    .unwrap();
+    pub static ref DB_POOL_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!(
+        "db_pool_connections",
+        "数据库连接池连接数",
+        &["pool", "state"]
+    )
+    .unwrap();
+    pub static ref KAFKA_EVENTS_PROCESSED_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "kafka_events_processed_total",
+        "kafka 已处理消息数",
+        &["topic", "result"]
+    )
+    .unwrap();
+    pub static ref KAFKA_CONSUMER_LAG: IntGaugeVec = register_int_gauge_vec!(
+        "kafka_consumer_lag",
+        "kafka 消费延迟(条)",
+        &["topic", "partition"]
+    )
+    .unwrap();
+    pub static ref CACHE_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "cache_requests_total",
+        "缓存查询次数",
+        &["cache", "result"]
+    )
+    .unwrap();
+    pub static ref JOB_RUNS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "job_runs_total",
+        "定时任务执行次数",
+        &["job", "result"]
+    )
+    .unwrap();
+    pub static ref ACTIVE_USERS: IntGauge =
+        register_int_gauge!("active_users", "有效用户数").unwrap();
+    pub static ref OUTSTANDING_TOKENS: IntGauge =
+        register_int_gauge!("outstanding_tokens", "有效用户剩余 tokens 总数").unwrap();
+}
+
+/// 记录一次 HTTP 请求
+pub fn observe_http(method: &str, route: &str, status: u16, seconds: f64) {
+    HTTP_REQUESTS_TOTAL
+        .with_label_values(&[method, route, &status.to_string()])
+        .inc();
+    HTTP_REQUEST_DURATION_SECONDS
+        .with_label_values(&[method, route])
+        .observe(seconds);
+}
+
+/// 记录一条 kafka 消息的处理结果
+pub fn observe_kafka_event(topic: &str, ok: bool) {
+    let result = if ok { "ok" } else { "error" };
+    KAFKA_EVENTS_PROCESSED_TOTAL
+        .with_label_values(&[topic, result])
+        .inc();
+}
+
+/// 记录一次缓存查询是否命中
+pub fn observe_cache(cache: &str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    CACHE_REQUESTS_TOTAL.with_label_values(&[cache, result]).inc();
+}
+
+/// 记录一次定时任务执行结果
+pub fn observe_job(job: &str, ok: bool) {
+    let result = if ok { "ok" } else { "error" };
+    JOB_RUNS_TOTAL.with_label_values(&[job, result]).inc();
+}
+
+pub fn set_kafka_lag(topic: &str, partition: i32, lag: i64) {
+    KAFKA_CONSUMER_LAG
+        .with_label_values(&[topic, &partition.to_string()])
+        .set(lag);
+}
+
+/// 采集 sqlx 及 rbatis 连接池状态
+async fn collect_pool() {
+    let pool = db::get_pool();
+    let size = pool.size() as i64;
+    let idle = pool.num_idle() as i64;
+    set_pool("sqlx", size, idle);
+
+    match db::get_rb().get_pool() {
+        Ok(pool) => {
+            let state = pool.state().await;
+            set_pool("rbatis", state.connections as i64, state.idle as i64);
+        }
+        Err(e) => warn!("rbatis pool state error: {}", e),
+    }
+}
+
+fn set_pool(name: &str, size: i64, idle: i64) {
+    DB_POOL_CONNECTIONS
+        .with_label_values(&[name, "size"])
+        .set(size);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&[name, "idle"])
+        .set(idle);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&[name, "active"])
+        .set(size - idle);
+}
+
+/// 采集业务指标: 有效用户数、剩余 tokens 总数
+async fn collect_business() -> Result<()> {
+    let sql = "select count(*), cast(coalesce(sum(tokens), 0) as signed) from user where active = 1";
+    let row = sqlx::query(sql).fetch_one(&db::get_pool()).await?;
+    ACTIVE_USERS.set(row.try_get::<i64, _>(0)?);
+    OUTSTANDING_TOKENS.set(row.try_get::<i64, _>(1)?);
+    Ok(())
+}
+
+/// 生成 Prometheus 文本格式的指标
+pub async fn render() -> Result<(String, Vec<u8>)> {
+    collect_pool().await;
+    if let Err(e) = collect_business().await {
+        warn!("collect business metrics error: {}", e);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+    Ok((encoder.format_type().to_string(), buffer))
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[test]
+    fn test_observe_http() {
+        observe_http("GET", "/test", 200, 0.01);
+        let count = HTTP_REQUESTS_TOTAL
+            .with_label_values(&["GET", "/test", "200"])
+            .get();
+        assert!(count >= 1);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{collections::HashSet, sync::RwLock, time::Duration};
+
+use actix_cors::Cors;
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use crate::{
+    client::entity::auth_site::AuthSite,
+    error::Result,
+    middleware::request_id::REQUEST_ID_HEADER,
+    setting::{self, CorsPolicy},
+    shutdown,
+};
+
+lazy_static! {
+    /// 有效站点的域名, 供 /client 的跨域校验使用
+    static ref SITE_DOMAINS: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+}
+
+/// 从 Origin 中取出域名, 去掉协议和端口
+fn origin_host(origin: &str) -> Option<&str> {
+    let host = origin.split("://").nth(1)?;
+    host.split(':').next().filter(|h| !h.is_empty())
+}
+
+/// 站点只登记了域名, 没有协议; 只接受 https 来源, 避免同名的 http 页面被中间人冒用
+fn is_site_origin(origin: &str) -> bool {
+    if !origin.starts_with("https://") {
+        return false;
+    }
+    match origin_host(origin) {
+        Some(host) => SITE_DOMAINS.read().unwrap().contains(host),
+        None => false,
+    }
+}
+
+/// 按配置构建 CORS 策略
+fn build(policy: &CorsPolicy) -> Cors {
+    let mut cors = Cors::default();
+    for origin in &policy.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    if policy.allow_site_domains {
+        cors = cors.allowed_origin_fn(|origin, _| origin.to_str().map_or(false, is_site_origin));
+    }
+    cors = cors
+        .allowed_methods(policy.allowed_methods.iter().map(|m| m.as_str()))
+        .allowed_headers(policy.allowed_headers.iter().map(|h| h.as_str()))
+        .expose_headers(vec![REQUEST_ID_HEADER, "retry-after"])
+        .max_age(policy.max_age);
+    if policy.supports_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+/// /admin 的 CORS 策略
+pub fn admin() -> Cors {
+    build(&setting::SETTING.cors.admin)
+}
+
+/// /client 的 CORS 策略
+pub fn client() -> Cors {
+    build(&setting::SETTING.cors.client)
+}
+
+/// 是否需要加载站点域名
+pub fn site_domains_enabled() -> bool {
+    let config = &setting::SETTING.cors;
+    config.admin.allow_site_domains || config.client.allow_site_domains
+}
+
+async fn refresh_site_domains() -> Result<usize> {
+    let domains: HashSet<String> = AuthSite::find_all_active()
+        .await?
+        .into_iter()
+        .filter_map(|s| s.site_domain)
+        .collect();
+    let count = domains.len();
+    *SITE_DOMAINS.write().unwrap() = domains;
+    Ok(count)
+}
+
+/// 定时刷新有效站点域名
+pub async fn run_site_domain_refresher() {
+    let interval = Duration::from_secs(setting::SETTING.cors.site_domain_refresh_seconds.max(1));
+    loop {
+        match refresh_site_domains().await {
+            Ok(count) => info!("cors site domains refreshed: {}", count),
+            Err(e) => warn!("cors site domains refresh error: {}", e),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown::wait() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod cors_tests {
+    use super::*;
+
+    #[test]
+    fn test_origin_host() {
+        assert_eq!(origin_host("https://www.baidu.com"), Some("www.baidu.com"));
+        assert_eq!(origin_host("http://localhost:3000"), Some("localhost"));
+        assert_eq!(origin_host("null"), None);
+    }
+
+    #[test]
+    fn test_is_site_origin() {
+        SITE_DOMAINS
+            .write()
+            .unwrap()
+            .insert("a.example.com".to_string());
+        assert!(is_site_origin("https://a.example.com"));
+        assert!(!is_site_origin("http://a.example.com"));
+        assert!(!is_site_origin("https://b.example.com"));
+    }
+}
@@ -0,0 +1,40 @@
+use actix_web::dev::ServiceRequest;
+
+pub mod cors;
+pub mod rate_limit;
+pub mod request_id;
+
+/// 客户端调用方通过该请求头携带 summary_key
+pub const SUMMARY_KEY_HEADER: &str = "x-summary-key";
+
+/// 站点嵌入调用时通过该请求头携带 site_summary_key
+pub const SITE_SUMMARY_KEY_HEADER: &str = "x-site-summary-key";
+
+/// 获取调用方身份: 有 summary_key 时使用脱敏后的 key, 否则为 anonymous
+pub fn caller_identity(req: &ServiceRequest) -> String {
+    match req
+        .headers()
+        .get(SUMMARY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(key) if !key.is_empty() => mask_key(key),
+        _ => "anonymous".to_string(),
+    }
+}
+
+/// key 脱敏, 只保留前 3 位
+pub fn mask_key(key: &str) -> String {
+    let prefix: String = key.chars().take(3).collect();
+    format!("{}***", prefix)
+}
+
+#[cfg(test)]
+mod middleware_tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_key() {
+        assert_eq!(mask_key("abcdefgh"), "abc***");
+        assert_eq!(mask_key("ab"), "ab***");
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    future::{ready, Ready},
+    net::IpAddr,
+    rc::Rc,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, ResponseError,
+};
+use futures::future::LocalBoxFuture;
+use lazy_static::lazy_static;
+use log::warn;
+
+use crate::{
+    client::service::key_service,
+    error::Error as MyError,
+    middleware::{SITE_SUMMARY_KEY_HEADER, SUMMARY_KEY_HEADER},
+    setting::{self, Limit},
+};
+
+/// 桶数量上限, 超过时先清理空闲的桶, 仍超过时淘汰最久未使用的桶
+const MAX_BUCKETS: usize = 100_000;
+/// 空闲超过该时间的桶会被清理
+const BUCKET_IDLE: Duration = Duration::from_secs(600);
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<String, Bucket>> = Mutex::new(HashMap::new());
+    /// 用户 id 对应的套餐限额, 定时从数据库刷新
+    static ref PLAN_LIMITS: RwLock<HashMap<u64, Limit>> = RwLock::new(HashMap::new());
+}
+
+/// 替换套餐限额, 配置文件中的 overrides 优先
+pub fn set_plan_limits(limits: HashMap<u64, Limit>) {
+    *PLAN_LIMITS.write().unwrap() = limits;
+}
+
+/// 令牌桶
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: &Limit, now: Instant) -> Bucket {
+        Bucket {
+            tokens: limit.capacity as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.refill_per_second).min(limit.capacity as f64);
+        self.updated = now;
+    }
+
+    /// 距离下一个令牌可用的秒数
+    fn retry_after(&self, limit: &Limit) -> u64 {
+        if limit.refill_per_second <= 0.0 {
+            return BUCKET_IDLE.as_secs();
+        }
+        ((1.0 - self.tokens) / limit.refill_per_second).ceil().max(1.0) as u64
+    }
+}
+
+/// 检查所有桶是否都有令牌, 不扣减; 没有时返回需要等待的秒数
+fn check(buckets: &mut HashMap<String, Bucket>, keys: &[(String, Limit)], now: Instant) -> Result<(), u64> {
+    let mut retry_after = 0;
+    for (key, limit) in keys {
+        let bucket = buckets
+            .entry(key.clone())
+            .or_insert_with(|| Bucket::new(limit, now));
+        bucket.refill(limit, now);
+        if bucket.tokens < 1.0 {
+            retry_after = retry_after.max(bucket.retry_after(limit));
+        }
+    }
+    if retry_after > 0 {
+        return Err(retry_after);
+    }
+    Ok(())
+}
+
+/// 所有桶都有令牌时各扣一个, 否则不扣并返回需要等待的秒数
+fn acquire(buckets: &mut HashMap<String, Bucket>, keys: &[(String, Limit)], now: Instant) -> Result<(), u64> {
+    check(buckets, keys, now)?;
+    for (key, _) in keys {
+        if let Some(bucket) = buckets.get_mut(key) {
+            bucket.tokens -= 1.0;
+        }
+    }
+    if buckets.len() > MAX_BUCKETS {
+        evict(buckets, now, MAX_BUCKETS);
+    }
+    Ok(())
+}
+
+/// 清理空闲的桶; 仍超过 max 时淘汰最久未使用的桶, 留到 max 的 90%, 避免每次请求都清理
+fn evict(buckets: &mut HashMap<String, Bucket>, now: Instant, max: usize) {
+    buckets.retain(|_, b| now.saturating_duration_since(b.updated) < BUCKET_IDLE);
+    if buckets.len() <= max {
+        return;
+    }
+    let excess = buckets.len() - max * 9 / 10;
+    let mut updated: Vec<Instant> = buckets.values().map(|b| b.updated).collect();
+    let (_, cutoff, _) = updated.select_nth_unstable(excess - 1);
+    let cutoff = *cutoff;
+    buckets.retain(|_, b| b.updated > cutoff);
+}
+
+fn header(req: &ServiceRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+}
+
+/// 请求头中 summary_key 所属的用户 id
+///
+/// 只为有效 key 建桶, 随机 key 不会占用桶; 无效 key 由接口返回 401
+async fn resolved_user_id(req: &ServiceRequest) -> Option<u64> {
+    let key = header(req, SUMMARY_KEY_HEADER)?;
+    match key_service::find_key(&key).await {
+        Ok(resolved) => resolved.and_then(|r| r.user.id),
+        Err(e) => {
+            warn!("rate limit resolve key error: {}", e);
+            None
+        }
+    }
+}
+
+/// 客户端 IP: 取连接地址; 连接来自可信代理时才按转发头取
+fn client_ip(req: &ServiceRequest, trusted_proxies: &[IpAddr]) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    if trusted_proxies.contains(&peer) {
+        if let Some(ip) = req.connection_info().realip_remote_addr() {
+            return Some(ip.to_string());
+        }
+    }
+    Some(peer.to_string())
+}
+
+/// IP 桶, 在解析 key 之前检查, 超限的请求不查库
+fn ip_bucket(req: &ServiceRequest) -> Option<(String, Limit)> {
+    let config = &setting::SETTING.rate_limit;
+    client_ip(req, &config.trusted_proxies).map(|ip| (format!("ip:{}", ip), config.ip.clone()))
+}
+
+/// 当前请求对应的 key 及站点限流桶和限额, 站点桶只在 summary_key 有效时使用
+///
+/// key 的桶按用户建立, 同一用户的多个 key 共用套餐额度
+async fn bucket_keys(req: &ServiceRequest) -> Vec<(String, Limit)> {
+    let config = &setting::SETTING.rate_limit;
+    let mut keys = vec![];
+    if let Some(user_id) = resolved_user_id(req).await {
+        let limit = match config.user_override(user_id) {
+            Some(limit) => limit.clone(),
+            None => PLAN_LIMITS
+                .read()
+                .unwrap()
+                .get(&user_id)
+                .unwrap_or(&config.key)
+                .clone(),
+        };
+        keys.push((format!("user:{}", user_id), limit));
+        if let Some(site) = header(req, SITE_SUMMARY_KEY_HEADER) {
+            keys.push((format!("site:{}", site), config.site.clone()));
+        }
+    }
+    keys
+}
+
+/// 客户端接口限流中间件
+///
+/// 按 summary_key 所属用户、site_summary_key 和客户端 IP 分别使用令牌桶限流,
+/// 超限时返回 429 并带 `Retry-After` 响应头。桶总数有上限, 见 [`MAX_BUCKETS`]。
+pub struct RateLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            if setting::SETTING.rate_limit.enabled {
+                // 先只检查 IP 桶, 超限时不解析 key, 随机 key 刷接口不会打到数据库
+                let mut keys: Vec<(String, Limit)> = ip_bucket(&req).into_iter().collect();
+                let mut result = check(&mut BUCKETS.lock().unwrap(), &keys, Instant::now());
+                if result.is_ok() {
+                    keys.extend(bucket_keys(&req).await);
+                    result = acquire(&mut BUCKETS.lock().unwrap(), &keys, Instant::now());
+                }
+                if let Err(retry_after) = result {
+                    warn!("rate limited: {:?}", keys.iter().map(|(k, _)| k).collect::<Vec<_>>());
+                    let res = MyError::TooManyRequests(retry_after).error_response();
+                    return Ok(req.into_response(res).map_into_right_body());
+                }
+            }
+
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    fn limit(capacity: u64, refill_per_second: f64) -> Limit {
+        Limit {
+            capacity,
+            refill_per_second,
+        }
+    }
+
+    #[test]
+    fn test_acquire_until_empty() {
+        let mut buckets = HashMap::new();
+        let keys = vec![("user:1".to_string(), limit(2, 1.0))];
+        let now = Instant::now();
+        assert!(acquire(&mut buckets, &keys, now).is_ok());
+        assert!(acquire(&mut buckets, &keys, now).is_ok());
+        assert_eq!(acquire(&mut buckets, &keys, now), Err(1));
+        assert!(acquire(&mut buckets, &keys, now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_acquire_does_not_consume_when_any_bucket_is_empty() {
+        let mut buckets = HashMap::new();
+        let now = Instant::now();
+        let ip = ("ip:1.1.1.1".to_string(), limit(1, 0.5));
+        assert!(acquire(&mut buckets, &[ip.clone()], now).is_ok());
+
+        let key = ("user:1".to_string(), limit(5, 1.0));
+        assert_eq!(acquire(&mut buckets, &[key.clone(), ip], now), Err(2));
+        assert_eq!(buckets.get("user:1").unwrap().tokens, 5.0);
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut buckets = HashMap::new();
+        let now = Instant::now();
+        for i in 0..20u64 {
+            let updated = now + Duration::from_secs(i);
+            buckets.insert(format!("ip:{}", i), Bucket { tokens: 1.0, updated });
+        }
+        evict(&mut buckets, now + Duration::from_secs(20), 10);
+        assert_eq!(buckets.len(), 9);
+        assert!(buckets.contains_key("ip:19"));
+        assert!(!buckets.contains_key("ip:10"));
+    }
+
+    #[test]
+    fn test_client_ip_ignores_untrusted_forwarded_header() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let req = actix_web::test::TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "1.2.3.4"))
+            .to_srv_request();
+        assert_eq!(client_ip(&req, &[]), Some("10.0.0.1".to_string()));
+        assert_eq!(client_ip(&req, &[proxy]), Some("1.2.3.4".to_string()));
+    }
+}
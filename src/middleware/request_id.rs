@@ -0,0 +1,154 @@
+use std::{future::ready, future::Ready, rc::Rc, time::Instant};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error,
+};
+use futures::future::LocalBoxFuture;
+use log::info;
+
+use crate::{metrics, middleware::caller_identity, utils::uuid};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// 获取当前请求的 request id, 不在请求上下文中时返回 None
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// 调用方传入的 request id 只接受字母、数字、`-`、`_`, 长度不超过 64
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// request id 及访问日志中间件
+///
+/// 接收或生成 `X-Request-Id`, 请求处理期间的日志都会带上该 id,
+/// 并在响应头中返回 (处理出错时也返回); 请求结束后输出一行访问日志并记录 HTTP 指标。
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| is_valid_request_id(v))
+            .map(|v| v.to_string())
+            .unwrap_or_else(uuid::new_request_id);
+
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let caller = caller_identity(&req);
+        let ip = req
+            .connection_info()
+            .realip_remote_addr()
+            .unwrap_or("-")
+            .to_string();
+        let http_req = req.request().clone();
+        let service = self.service.clone();
+
+        Box::pin(REQUEST_ID.scope(request_id.clone(), async move {
+            let start = Instant::now();
+            let result = service.call(req).await;
+            let elapsed = start.elapsed();
+            let latency = elapsed.as_millis();
+
+            let (status, route) = match &result {
+                Ok(res) => (res.status(), res.request().match_pattern()),
+                Err(err) => (err.as_response_error().status_code(), None),
+            };
+            metrics::observe_http(
+                &method,
+                route.as_deref().unwrap_or("unmatched"),
+                status.as_u16(),
+                elapsed.as_secs_f64(),
+            );
+            info!(
+                target: "access",
+                "{} {} {} {}ms caller={} ip={}",
+                method,
+                path,
+                status.as_u16(),
+                latency,
+                caller,
+                ip
+            );
+
+            // 错误在这里转成响应, 以便同样带上 request id 响应头
+            let mut res = match result {
+                Ok(res) => res.map_into_left_body(),
+                Err(err) => ServiceResponse::from_err(err, http_req).map_into_right_body(),
+            };
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod request_id_tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_request_id() {
+        assert!(is_valid_request_id("abc-123_XYZ"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("abc 123"));
+        assert!(!is_valid_request_id(&"a".repeat(65)));
+    }
+
+    #[tokio::test]
+    async fn test_current_request_id() {
+        assert_eq!(current_request_id(), None);
+        let id = REQUEST_ID
+            .scope("req-1".to_string(), async { current_request_id() })
+            .await;
+        assert_eq!(id, Some("req-1".to_string()));
+    }
+}
@@ -0,0 +1,98 @@
+//! 通知渠道: 签名 webhook 及 SMTP 邮件
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{client::entity::user::User, error::Result, setting};
+
+pub mod smtp;
+pub mod template;
+pub mod webhook;
+
+use template::Language;
+
+/// 重试间隔上限
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// 待投递的消息
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    pub template: String,
+    pub language: Language,
+    pub subject: String,
+    pub body: String,
+    /// 模板变量, webhook 原样发送
+    pub data: serde_json::Value,
+}
+
+/// 投递结果: 尝试次数及最后一次的结果
+#[derive(Debug)]
+pub struct Delivery {
+    pub attempts: u32,
+    pub result: Result<()>,
+}
+
+/// 通知渠道
+#[async_trait]
+pub trait Channel: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// 消息的收件人, 返回 None 时跳过该渠道
+    fn recipient(&self, user: Option<&User>) -> Option<String>;
+
+    /// 投递消息, 失败时按渠道配置重试
+    async fn send(&self, recipient: &str, message: &Message) -> Delivery;
+}
+
+lazy_static! {
+    static ref CHANNELS: Vec<Box<dyn Channel>> = build_channels();
+}
+
+fn build_channels() -> Vec<Box<dyn Channel>> {
+    let config = &setting::SETTING.notify;
+    let mut channels: Vec<Box<dyn Channel>> = vec![];
+    if !config.webhook.url.is_empty() {
+        match webhook::WebhookChannel::new(&config.webhook) {
+            Ok(channel) => channels.push(Box::new(channel)),
+            Err(e) => warn!("webhook channel disabled: {}", e),
+        }
+    }
+    if !config.smtp.host.is_empty() {
+        match smtp::SmtpChannel::new(&config.smtp) {
+            Ok(channel) => channels.push(Box::new(channel)),
+            Err(e) => warn!("smtp channel disabled: {}", e),
+        }
+    }
+    info!(
+        "notify channels: {:?}",
+        channels.iter().map(|c| c.name()).collect::<Vec<_>>()
+    );
+    channels
+}
+
+/// 按配置启用的渠道
+pub fn channels() -> &'static [Box<dyn Channel>] {
+    &CHANNELS
+}
+
+/// 第 attempt 次失败后的等待时间, 指数增长
+pub(crate) fn backoff(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod notify_tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let base = Duration::from_millis(500);
+        assert_eq!(backoff(base, 1), Duration::from_millis(500));
+        assert_eq!(backoff(base, 3), Duration::from_secs(2));
+        assert_eq!(backoff(base, 20), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message as Email, Tokio1Executor,
+};
+use log::warn;
+
+use crate::{
+    client::entity::user::User,
+    error::{Error, Result},
+    notify::{backoff, Channel, Delivery, Message},
+    setting::SmtpSetting,
+};
+
+/// SMTP 邮件, 发送给用户的 email
+pub struct SmtpChannel {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+fn notify_error(e: impl std::fmt::Display) -> Error {
+    Error::NotifyError(e.to_string())
+}
+
+impl SmtpChannel {
+    pub fn new(config: &SmtpSetting) -> Result<SmtpChannel> {
+        let builder = if config.tls {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).map_err(notify_error)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+        };
+        let mut builder = builder
+            .port(config.port)
+            .timeout(Some(Duration::from_secs(config.timeout_seconds)));
+        if !config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.username.clone(),
+                config.password.clone(),
+            ));
+        }
+        Ok(SmtpChannel {
+            transport: builder.build(),
+            from: config.from.parse().map_err(notify_error)?,
+            max_retries: config.max_retries,
+            backoff: Duration::from_millis(config.backoff_millis),
+        })
+    }
+
+    fn email(&self, recipient: &str, message: &Message) -> Result<Email> {
+        Email::builder()
+            .from(self.from.clone())
+            .to(recipient.parse().map_err(notify_error)?)
+            .subject(message.subject.as_str())
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.clone())
+            .map_err(notify_error)
+    }
+}
+
+#[async_trait]
+impl Channel for SmtpChannel {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn recipient(&self, user: Option<&User>) -> Option<String> {
+        user.and_then(|u| u.email.clone()).filter(|e| !e.is_empty())
+    }
+
+    async fn send(&self, recipient: &str, message: &Message) -> Delivery {
+        let email = match self.email(recipient, message) {
+            Ok(email) => email,
+            Err(e) => {
+                return Delivery {
+                    attempts: 0,
+                    result: Err(e),
+                }
+            }
+        };
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.transport.send(email.clone()).await {
+                Ok(_) => {
+                    return Delivery {
+                        attempts,
+                        result: Ok(()),
+                    }
+                }
+                // 永久性错误 (5xx) 不重试
+                Err(e) if e.is_permanent() || attempts > self.max_retries => {
+                    return Delivery {
+                        attempts,
+                        result: Err(notify_error(e)),
+                    }
+                }
+                Err(e) => {
+                    warn!("smtp attempt {} failed: {}", attempts, e);
+                    tokio::time::sleep(backoff(self.backoff, attempts)).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod smtp_tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::notify::template::Language;
+
+    /// 本地替身 SMTP 服务, 记录收到的邮件内容
+    async fn stand_in() -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mails = Arc::new(Mutex::new(vec![]));
+        let received = mails.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            let mut data = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        received.lock().unwrap().push(std::mem::take(&mut data));
+                        writer.write_all(b"250 queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250 localhost\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+        (port, mails)
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let (port, mails) = stand_in().await;
+        let channel = SmtpChannel::new(&SmtpSetting {
+            host: "127.0.0.1".to_string(),
+            port,
+            from: "noreply@example.com".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let message = Message {
+            template: "welcome".to_string(),
+            language: Language::En,
+            subject: "Welcome".to_string(),
+            body: "hello".to_string(),
+            data: serde_json::json!({}),
+        };
+        let delivery = channel.send("alice@example.com", &message).await;
+        assert!(delivery.result.is_ok(), "{:?}", delivery.result);
+        assert_eq!(delivery.attempts, 1);
+        let mails = mails.lock().unwrap();
+        assert_eq!(mails.len(), 1);
+        assert!(mails[0].contains("Subject: Welcome"));
+        assert!(mails[0].contains("hello"));
+    }
+
+    #[test]
+    fn test_recipient() {
+        let channel = SmtpChannel::new(&SmtpSetting {
+            host: "127.0.0.1".to_string(),
+            from: "noreply@example.com".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut user = User::new();
+        assert_eq!(channel.recipient(Some(&user)), None);
+        user.email = Some("alice@example.com".to_string());
+        assert_eq!(channel.recipient(Some(&user)).as_deref(), Some("alice@example.com"));
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+pub const WELCOME: &str = "welcome";
+pub const KEY_ROTATED: &str = "key_rotated";
+pub const KEY_EXPIRING: &str = "key_expiring";
+/// 告警模板为 `alert.{规则类型}`
+pub const ALERT_PREFIX: &str = "alert.";
+
+/// 通知语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Language {
+    /// 未知语言按中文处理
+    pub fn parse(s: &str) -> Language {
+        if s.eq_ignore_ascii_case("en") {
+            Language::En
+        } else {
+            Language::Zh
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::Zh => "zh",
+            Language::En => "en",
+        }
+    }
+}
+
+/// 模板的 (标题, 正文)
+fn source(template: &str, language: Language) -> Option<(&'static str, &'static str)> {
+    let text = match (template, language) {
+        (WELCOME, Language::Zh) => (
+            "欢迎使用 summary-gpt",
+            "您的账户 {account} 已创建, summary_key 以 {summary_key_hint} 开头, 完整 key 请向管理员获取。",
+        ),
+        (WELCOME, Language::En) => (
+            "Welcome to summary-gpt",
+            "Your account {account} has been created. Your summary_key starts with {summary_key_hint}; ask your administrator for the full key.",
+        ),
+        (KEY_ROTATED, Language::Zh) => (
+            "summary_key 已更换",
+            "您的账户 {account} 的 summary_key 已更换, 新 key 以 {summary_key_hint} 开头, 旧 key 已失效。",
+        ),
+        (KEY_ROTATED, Language::En) => (
+            "Your summary_key was rotated",
+            "The summary_key of account {account} was rotated; the new key starts with {summary_key_hint}. The old key no longer works.",
+        ),
+        (KEY_EXPIRING, Language::Zh) => (
+            "key 即将过期",
+            "您的账户 {account} 的 {key} 将于 {expires_at} 过期, 请联系管理员续期。",
+        ),
+        (KEY_EXPIRING, Language::En) => (
+            "Key expiring soon",
+            "The {key} of account {account} expires at {expires_at}. Please contact us to renew it.",
+        ),
+        ("alert.balance_below", Language::Zh) => (
+            "余额不足提醒",
+            "您的账户 {account} 剩余 {value} tokens, 已低于 {threshold}。",
+        ),
+        ("alert.balance_below", Language::En) => (
+            "Low balance",
+            "Your account {account} has {value} tokens left, below {threshold}.",
+        ),
+        ("alert.monthly_usage_percent", Language::Zh) => (
+            "本月额度使用提醒",
+            "您的账户 {account} 本月已使用 {value}% 的额度, 提醒阈值 {threshold}%。",
+        ),
+        ("alert.monthly_usage_percent", Language::En) => (
+            "Monthly usage",
+            "Your account {account} has used {value}% of this month's tokens (alert at {threshold}%).",
+        ),
+        ("alert.site_quota_exhausted", Language::Zh) => (
+            "站点配额提醒",
+            "您的账户 {account} 的站点 #{site_id} 已使用 {value}% 的配额, 提醒阈值 {threshold}%。",
+        ),
+        ("alert.site_quota_exhausted", Language::En) => (
+            "Site quota",
+            "Site #{site_id} of account {account} has used {value}% of its quota (alert at {threshold}%).",
+        ),
+        _ => return None,
+    };
+    Some(text)
+}
+
+fn fill(text: &str, vars: &HashMap<&str, String>) -> String {
+    vars.iter().fold(text.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// 渲染模板, 返回 (标题, 正文)
+pub fn render(template: &str, language: Language, vars: &HashMap<&str, String>) -> Result<(String, String)> {
+    let (subject, body) = source(template, language)
+        .ok_or_else(|| Error::NotifyError(format!("unknown template: {}", template)))?;
+    Ok((fill(subject, vars), fill(body, vars)))
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = HashMap::from([
+            ("account", "alice".to_string()),
+            ("value", "10".to_string()),
+            ("threshold", "100".to_string()),
+        ]);
+        let (subject, body) = render("alert.balance_below", Language::Zh, &vars).unwrap();
+        assert_eq!(subject, "余额不足提醒");
+        assert_eq!(body, "您的账户 alice 剩余 10 tokens, 已低于 100。");
+        let (_, body) = render("alert.balance_below", Language::En, &vars).unwrap();
+        assert_eq!(body, "Your account alice has 10 tokens left, below 100.");
+    }
+
+    #[test]
+    fn test_unknown_template() {
+        assert!(render("unknown", Language::Zh, &HashMap::new()).is_err());
+        assert_eq!(Language::parse("EN"), Language::En);
+        assert_eq!(Language::parse("fr"), Language::Zh);
+    }
+
+    #[test]
+    fn test_account_templates_only_show_hint() {
+        let vars = HashMap::from([
+            ("account", "alice".to_string()),
+            ("summary_key", "sk-secret".to_string()),
+            ("summary_key_hint", "sk-***".to_string()),
+        ]);
+        for template in [WELCOME, KEY_ROTATED] {
+            for language in [Language::Zh, Language::En] {
+                let (_, body) = render(template, language, &vars).unwrap();
+                assert!(body.contains("sk-***"));
+                assert!(!body.contains("sk-secret"));
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use log::warn;
+use reqwest::StatusCode;
+use sha2::Sha256;
+
+use crate::{
+    client::entity::user::User,
+    error::{Error, Result},
+    notify::{backoff, Channel, Delivery, Message},
+    setting::WebhookSetting,
+};
+
+/// 请求时间戳, 秒
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+/// `sha256=` 加 hex(hmac_sha256(secret, "{timestamp}.{body}"))
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// 签名 webhook, 5xx、429 及网络错误会重试
+pub struct WebhookChannel {
+    url: String,
+    secret: String,
+    max_retries: u32,
+    backoff: Duration,
+    client: reqwest::Client,
+}
+
+/// 计算签名
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+impl WebhookChannel {
+    pub fn new(config: &WebhookSetting) -> Result<WebhookChannel> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()?;
+        Ok(WebhookChannel {
+            url: config.url.clone(),
+            secret: config.secret.clone(),
+            max_retries: config.max_retries,
+            backoff: Duration::from_millis(config.backoff_millis),
+            client,
+        })
+    }
+
+    async fn post(&self, url: &str, body: &[u8]) -> Result<(), (bool, Error)> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let response = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, sign(&self.secret, timestamp, body))
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| (true, e.into()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error = Error::NotifyError(format!("webhook status {}", status));
+        Err((retryable(status), error))
+    }
+}
+
+#[async_trait]
+impl Channel for WebhookChannel {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn recipient(&self, _user: Option<&User>) -> Option<String> {
+        Some(self.url.clone())
+    }
+
+    async fn send(&self, recipient: &str, message: &Message) -> Delivery {
+        let body = match serde_json::to_vec(message) {
+            Ok(body) => body,
+            Err(e) => {
+                return Delivery {
+                    attempts: 0,
+                    result: Err(e.into()),
+                }
+            }
+        };
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.post(recipient, &body).await {
+                Ok(()) => {
+                    return Delivery {
+                        attempts,
+                        result: Ok(()),
+                    }
+                }
+                Err((retry, e)) => {
+                    if !retry || attempts > self.max_retries {
+                        return Delivery {
+                            attempts,
+                            result: Err(e),
+                        };
+                    }
+                    warn!("webhook attempt {} failed: {}", attempts, e);
+                    tokio::time::sleep(backoff(self.backoff, attempts)).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod webhook_tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::notify::template::Language;
+
+    /// 本地替身服务: 按顺序返回给定的状态码, 记录收到的请求
+    async fn stand_in(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 64 * 1024];
+                let mut len = 0;
+                loop {
+                    let n = socket.read(&mut buffer[len..]).await.unwrap();
+                    len += n;
+                    let text = String::from_utf8_lossy(&buffer[..len]).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let content_length = text[..end]
+                            .lines()
+                            .find_map(|l| l.to_lowercase().strip_prefix("content-length: ").map(|v| v.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if len >= end + 4 + content_length || n == 0 {
+                            received.lock().unwrap().push(text);
+                            break;
+                        }
+                    }
+                }
+                let response = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn channel(url: &str) -> WebhookChannel {
+        WebhookChannel::new(&WebhookSetting {
+            url: url.to_string(),
+            secret: "secret".to_string(),
+            max_retries: 2,
+            backoff_millis: 10,
+            timeout_seconds: 5,
+        })
+        .unwrap()
+    }
+
+    fn message() -> Message {
+        Message {
+            template: "welcome".to_string(),
+            language: Language::Zh,
+            subject: "subject".to_string(),
+            body: "body".to_string(),
+            data: serde_json::json!({}),
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let signature = sign("secret", 1700000000, b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(signature, sign("other", 1700000000, b"{}"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let (url, requests) = stand_in(vec![500, 503, 200]).await;
+        let delivery = channel(&url).send(&url, &message()).await;
+        assert!(delivery.result.is_ok());
+        assert_eq!(delivery.attempts, 3);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].to_lowercase().contains(SIGNATURE_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_client_error() {
+        let (url, _) = stand_in(vec![400]).await;
+        let delivery = channel(&url).send(&url, &message()).await;
+        assert!(delivery.result.is_err());
+        assert_eq!(delivery.attempts, 1);
+    }
+}
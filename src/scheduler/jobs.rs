@@ -0,0 +1,94 @@
+//! 定时任务列表
+use futures::future::BoxFuture;
+
+use crate::{
+    client::{
+        entity::{auth_site::AuthSite, outbox_event::OutboxEvent},
+        service::{expiry_service, plan_service},
+    },
+    error::Result,
+    scheduler::JobSpec,
+    utils::date_utils::{self, DateFormat},
+};
+
+/// 已发布事件的保留天数
+const OUTBOX_RETENTION_DAYS: i64 = 7;
+
+pub fn all() -> Vec<JobSpec> {
+    vec![
+        JobSpec {
+            name: "plan_allowance",
+            description: "发放套餐月度额度, 已发放的用户跳过",
+            schedule: "0 */10 * * * *",
+            run: plan_allowance,
+            per_instance: false,
+        },
+        JobSpec {
+            name: "site_usage_reset",
+            description: "跨月后清零站点已用 tokens, 已清零的站点跳过",
+            schedule: "0 */10 * * * *",
+            run: site_usage_reset,
+            per_instance: false,
+        },
+        JobSpec {
+            name: "plan_rate_limits",
+            description: "按套餐刷新本实例的限流额度",
+            schedule: "0 * * * * *",
+            run: plan_rate_limits,
+            per_instance: true,
+        },
+        JobSpec {
+            name: "key_expiry_report",
+            description: "列出即将过期的 key 并通知用户",
+            schedule: "0 0 9 * * *",
+            run: key_expiry_report,
+            per_instance: false,
+        },
+        JobSpec {
+            name: "outbox_purge",
+            description: "清理已发布的 outbox 事件",
+            schedule: "0 30 3 * * *",
+            run: outbox_purge,
+            per_instance: false,
+        },
+    ]
+}
+
+fn plan_allowance() -> BoxFuture<'static, Result<String>> {
+    Box::pin(async {
+        let count = plan_service::grant_monthly_allowances().await?;
+        Ok(format!("granted {} users", count))
+    })
+}
+
+fn site_usage_reset() -> BoxFuture<'static, Result<String>> {
+    Box::pin(async {
+        let count = AuthSite::reset_usage(&date_utils::get_current_month_begin()).await?;
+        Ok(format!("reset {} sites", count))
+    })
+}
+
+fn plan_rate_limits() -> BoxFuture<'static, Result<String>> {
+    Box::pin(async {
+        let count = plan_service::refresh_rate_limits().await?;
+        Ok(format!("{} users", count))
+    })
+}
+
+fn key_expiry_report() -> BoxFuture<'static, Result<String>> {
+    Box::pin(async {
+        let count = expiry_service::report().await?;
+        Ok(format!("{} keys expiring", count))
+    })
+}
+
+fn outbox_purge() -> BoxFuture<'static, Result<String>> {
+    Box::pin(async {
+        let before = date_utils::date_to_str(
+            date_utils::plus_days(-OUTBOX_RETENTION_DAYS),
+            DateFormat::YYYYMMDDHHMMSS,
+        );
+        let count = OutboxEvent::purge_published(&before).await?;
+        Ok(format!("deleted {} events", count))
+    })
+}
@@ -0,0 +1,458 @@
+//! 进程内定时任务: 按 cron 表达式在 tokio 上执行, 支持手动触发和暂停
+use std::{
+    any::Any,
+    panic::AssertUnwindSafe,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use futures::{future::BoxFuture, FutureExt};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    client::entity::job_control::{JobControl, JobRun},
+    error::{Error, Result},
+    leader, metrics, setting, shutdown,
+    utils::date_utils::{self, DateFormat},
+};
+
+pub mod jobs;
+
+/// 检查到期任务的间隔
+const TICK: Duration = Duration::from_secs(1);
+/// 多实例部署时同步暂停状态和手动触发请求的间隔
+const CONTROL_REFRESH: Duration = Duration::from_secs(5);
+
+/// 任务函数, 成功时返回执行摘要
+pub type JobFn = fn() -> BoxFuture<'static, Result<String>>;
+
+/// 任务定义
+pub struct JobSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// 默认 cron 表达式 (秒 分 时 日 月 周)
+    pub schedule: &'static str,
+    pub run: JobFn,
+    /// 每个实例都执行, 不受 leader 选举限制; 用于刷新进程内状态的任务
+    pub per_instance: bool,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    paused: bool,
+    running: bool,
+    next_run: Option<DateTime<Utc>>,
+    last_run: Option<JobRun>,
+}
+
+/// 已注册的任务
+pub struct Job {
+    spec: JobSpec,
+    expression: String,
+    schedule: Schedule,
+    state: Mutex<JobState>,
+}
+
+/// 任务状态
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobView {
+    pub name: String,
+    pub description: String,
+    pub schedule: String,
+    pub paused: bool,
+    pub running: bool,
+    pub next_run_time: Option<String>,
+    pub last_run_time: Option<String>,
+    pub last_duration_millis: Option<u64>,
+    pub last_success: Option<bool>,
+    /// 成功时为执行摘要, 失败时为错误信息
+    pub last_message: Option<String>,
+}
+
+lazy_static! {
+    static ref JOBS: Vec<Arc<Job>> = jobs::all()
+        .into_iter()
+        .filter_map(|spec| {
+            let config = &setting::SETTING.scheduler;
+            let name = spec.name;
+            let paused = config.paused.iter().any(|x| x == name);
+            let expression = config.schedules.get(name).map(String::as_str).unwrap_or(spec.schedule);
+            Job::new(spec, expression, paused)
+                .map_err(|e| error!("invalid schedule for job {}: {}", name, e))
+                .ok()
+        })
+        .map(Arc::new)
+        .collect();
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> Option<String> {
+    time.map(|t| date_utils::date_to_str(t, DateFormat::YYYYMMDDHHMMSS))
+}
+
+impl Job {
+    pub fn new(spec: JobSpec, expression: &str, paused: bool) -> Result<Job> {
+        let schedule = Schedule::from_str(expression)
+            .map_err(|e| Error::BizError(format!("cron 表达式错误 {}: {}", expression, e)))?;
+        let next_run = schedule.after(&Utc::now()).next();
+        Ok(Job {
+            spec,
+            expression: expression.to_string(),
+            schedule,
+            state: Mutex::new(JobState {
+                paused,
+                next_run,
+                ..Default::default()
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.spec.name
+    }
+
+    /// 到期且未暂停、未在执行时返回 true, 并计算下次执行时间; 非 leader 只推进计划时间, per_instance 任务除外
+    fn take_due(&self, now: DateTime<Utc>, leader: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let due = matches!(state.next_run, Some(next) if next <= now);
+        if !due {
+            return false;
+        }
+        state.next_run = self.schedule.after(&now).next();
+        if state.paused || state.running || !(leader || self.spec.per_instance) {
+            return false;
+        }
+        state.running = true;
+        true
+    }
+
+    /// 标记为执行中, 已在执行时返回 false
+    fn begin_manual(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.running {
+            return false;
+        }
+        state.running = true;
+        true
+    }
+
+    fn finish(&self, run: JobRun) {
+        let mut state = self.state.lock().unwrap();
+        state.running = false;
+        state.last_run = Some(run);
+    }
+
+    /// 采用其他实例记录的执行结果, 只接受比本地更新的记录
+    fn merge_last_run(&self, run: &JobRun) {
+        let mut state = self.state.lock().unwrap();
+        if state.last_run.as_ref().map_or(true, |last| run.started_at > last.started_at) {
+            state.last_run = Some(run.clone());
+        }
+    }
+
+    fn set_paused(&self, paused: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.paused == paused {
+            return;
+        }
+        state.paused = paused;
+        if !paused {
+            state.next_run = self.schedule.after(&Utc::now()).next();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.state.lock().unwrap().running
+    }
+
+    pub fn view(&self) -> JobView {
+        let state = self.state.lock().unwrap();
+        JobView {
+            name: self.spec.name.to_string(),
+            description: self.spec.description.to_string(),
+            schedule: self.expression.clone(),
+            paused: state.paused,
+            running: state.running,
+            next_run_time: if state.paused { None } else { format_time(state.next_run) },
+            last_run_time: format_time(state.last_run.as_ref().map(|run| run.started_at)),
+            last_duration_millis: state.last_run.as_ref().map(|run| run.duration_millis),
+            last_success: state.last_run.as_ref().map(|run| run.success),
+            last_message: state.last_run.as_ref().map(|run| run.message.clone()),
+        }
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// 执行任务并记录结果, 调用前需已标记为执行中
+///
+/// 多实例部署时同时写入 job_control, 其他实例的任务列表从那里读取执行结果
+async fn execute(job: Arc<Job>) {
+    let run = run_job(&job).await;
+    if leader::enabled() {
+        if let Err(e) = JobControl::record_run(job.name(), &run).await {
+            warn!("job {} record run error: {}", job.name(), e);
+        }
+    }
+}
+
+/// 执行任务并更新本地状态
+///
+/// 任务 panic 时按失败记录, 保证执行中标记被清除, 否则任务再也不会被调度
+async fn run_job(job: &Job) -> JobRun {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let result = AssertUnwindSafe(async { (job.spec.run)().await })
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| Err(Error::BizError(format!("job panicked: {}", panic_message(panic)))));
+    let duration = started.elapsed();
+    match &result {
+        Ok(message) => info!("job {} finished in {:?}: {}", job.name(), duration, message),
+        Err(e) => warn!("job {} failed in {:?}: {}", job.name(), duration, e),
+    }
+    metrics::observe_job(job.name(), result.is_ok());
+    let run = JobRun {
+        started_at,
+        duration_millis: duration.as_millis() as u64,
+        success: result.is_ok(),
+        message: match result {
+            Ok(message) => message,
+            Err(e) => e.to_string(),
+        },
+    };
+    job.finish(run.clone());
+    run
+}
+
+fn find(name: &str) -> Result<&'static Arc<Job>> {
+    JOBS.iter()
+        .find(|job| job.name() == name)
+        .ok_or_else(|| Error::NotFound(format!("job {}", name)))
+}
+
+/// 所有任务的状态
+///
+/// 多实例部署时任务通常在其他实例上执行, 先从 job_control 读取最近一次执行结果
+pub async fn list() -> Result<Vec<JobView>> {
+    if leader::enabled() {
+        for control in JobControl::all().await? {
+            if let (Ok(job), Some(run)) = (find(&control.name), &control.last_run) {
+                job.merge_last_run(run);
+            }
+        }
+    }
+    Ok(JOBS.iter().map(|job| job.view()).collect())
+}
+
+fn start_manual(job: &Arc<Job>) -> Result<JobView> {
+    if !job.begin_manual() {
+        return Err(Error::Conflict(format!("任务正在执行: {}", job.name())));
+    }
+    info!("job {} triggered manually", job.name());
+    tokio::spawn(execute(job.clone()));
+    Ok(job.view())
+}
+
+/// 立即执行一次任务, 不影响下次计划时间; 暂停的任务也可以手动执行
+///
+/// 当前实例不是 leader 时记录触发请求, 由 leader 稍后执行
+pub async fn trigger(name: &str) -> Result<JobView> {
+    let job = find(name)?;
+    if leader::is_leader(leader::SCHEDULER) {
+        return start_manual(job);
+    }
+    JobControl::request_trigger(name).await?;
+    info!("job {} trigger requested from leader", name);
+    Ok(job.view())
+}
+
+/// 暂停或恢复任务的计划执行
+pub async fn set_paused(name: &str, paused: bool) -> Result<JobView> {
+    let job = find(name)?;
+    if leader::enabled() {
+        JobControl::set_paused(name, paused).await?;
+    }
+    job.set_paused(paused);
+    info!("job {} {}", name, if paused { "paused" } else { "resumed" });
+    Ok(job.view())
+}
+
+/// 同步其他实例写入的暂停状态, leader 领取并执行触发请求
+async fn sync_controls() -> Result<()> {
+    for control in JobControl::all().await? {
+        let job = match find(&control.name) {
+            Ok(job) => job,
+            Err(_) => continue,
+        };
+        job.set_paused(control.paused);
+        if let Some(run) = &control.last_run {
+            job.merge_last_run(run);
+        }
+        if control.trigger_requested
+            && leader::is_leader(leader::SCHEDULER)
+            && JobControl::take_trigger(&control.name).await?
+        {
+            if let Err(e) = start_manual(job) {
+                warn!("job {} requested trigger skipped: {}", control.name, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 是否启用定时任务
+pub fn enabled() -> bool {
+    setting::SETTING.scheduler.enabled
+}
+
+/// 调度循环, 收到停机通知后等待执行中的任务结束
+pub async fn run() {
+    info!("scheduler started with {} jobs", JOBS.len());
+    let mut synced_at: Option<Instant> = None;
+    loop {
+        if leader::enabled() && synced_at.map_or(true, |at| at.elapsed() >= CONTROL_REFRESH) {
+            if let Err(e) = sync_controls().await {
+                warn!("scheduler control sync error: {}", e);
+            }
+            synced_at = Some(Instant::now());
+        }
+        let now = Utc::now();
+        let leader = leader::is_leader(leader::SCHEDULER);
+        for job in JOBS.iter() {
+            if job.take_due(now, leader) {
+                tokio::spawn(execute(job.clone()));
+            }
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(TICK) => {}
+            _ = shutdown::wait() => break,
+        }
+    }
+    while JOBS.iter().any(|job| job.is_running()) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    info!("scheduler stopped");
+}
+
+#[cfg(test)]
+mod scheduler_tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn noop() -> BoxFuture<'static, Result<String>> {
+        Box::pin(async { Ok("done".to_string()) })
+    }
+
+    fn spec(schedule: &'static str) -> JobSpec {
+        JobSpec {
+            name: "test",
+            description: "test job",
+            schedule,
+            run: noop,
+            per_instance: false,
+        }
+    }
+
+    fn panics() -> BoxFuture<'static, Result<String>> {
+        Box::pin(async { panic!("boom") })
+    }
+
+    #[test]
+    fn test_default_schedules() {
+        for spec in jobs::all() {
+            assert!(Schedule::from_str(spec.schedule).is_ok(), "{}", spec.name);
+        }
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        assert!(Job::new(spec("not a cron"), "not a cron", false).is_err());
+    }
+
+    #[test]
+    fn test_take_due() {
+        let job = Job::new(spec("0 * * * * *"), "0 * * * * *", false).unwrap();
+        let at = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        job.state.lock().unwrap().next_run = Some(at);
+
+        assert!(!job.take_due(at - chrono::Duration::seconds(1), true));
+        assert!(job.take_due(at, true));
+        assert_eq!(job.state.lock().unwrap().next_run, Some(at + chrono::Duration::minutes(1)));
+
+        // 执行中的任务跳过本次
+        assert!(!job.take_due(at + chrono::Duration::minutes(1), true));
+        assert!(!job.begin_manual());
+        job.finish(run(at, true));
+        assert_eq!(job.view().last_success, Some(true));
+
+        // 暂停的任务不执行, 但仍推进计划时间
+        job.state.lock().unwrap().paused = true;
+        assert!(!job.take_due(at + chrono::Duration::minutes(2), true));
+        assert_eq!(job.state.lock().unwrap().next_run, Some(at + chrono::Duration::minutes(3)));
+        assert!(job.view().next_run_time.is_none());
+
+        // 非 leader 不执行
+        job.set_paused(false);
+        let next = job.state.lock().unwrap().next_run.unwrap();
+        assert!(!job.take_due(next, false));
+        assert!(!job.view().running);
+
+        // per_instance 任务在非 leader 上也执行
+        let mut spec = spec("0 * * * * *");
+        spec.per_instance = true;
+        let job = Job::new(spec, "0 * * * * *", false).unwrap();
+        job.state.lock().unwrap().next_run = Some(at);
+        assert!(job.take_due(at, false));
+    }
+
+    fn run(started_at: DateTime<Utc>, success: bool) -> JobRun {
+        JobRun {
+            started_at,
+            duration_millis: 5,
+            success,
+            message: "done".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_last_run_keeps_newest() {
+        let job = Job::new(spec("0 * * * * *"), "0 * * * * *", false).unwrap();
+        let at = Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap();
+        job.merge_last_run(&run(at, true));
+        assert_eq!(job.view().last_success, Some(true));
+
+        // 其他实例的旧记录不覆盖本地较新的结果
+        job.finish(run(at + chrono::Duration::minutes(1), false));
+        job.merge_last_run(&run(at, true));
+        assert_eq!(job.view().last_success, Some(false));
+
+        job.merge_last_run(&run(at + chrono::Duration::minutes(2), true));
+        assert_eq!(job.view().last_success, Some(true));
+    }
+
+    #[actix_web::test]
+    async fn test_panicking_job_is_finished() {
+        let mut spec = spec("0 * * * * *");
+        spec.run = panics;
+        let job = Arc::new(Job::new(spec, "0 * * * * *", false).unwrap());
+        assert!(job.begin_manual());
+        run_job(&job).await;
+        let view = job.view();
+        assert!(!view.running);
+        assert_eq!(view.last_success, Some(false));
+        assert!(view.last_message.unwrap().contains("boom"));
+    }
+}
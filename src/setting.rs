@@ -2,9 +2,14 @@ use chrono::Local;
 use fern::Dispatch;
 use lazy_static::lazy_static;
 use serde_derive::Deserialize;
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::net::IpAddr;
+
+use crate::error::{Error, Result};
+use crate::middleware::request_id::current_request_id;
 
 
 
@@ -32,12 +37,414 @@ pub struct Log {
     pub level: String,
     pub path: String,
 }
+/// kafka 配置, brokers 为空时不启动消费者, events_topic 为空时不发布领域事件
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Kafka {
+    pub brokers: String,
+    pub group_id: String,
+    pub usage_topic: String,
+    pub events_topic: String,
+    /// 多次重试仍处理失败的用量消息转发到该 topic, 为空时一直重试
+    pub dead_letter_topic: String,
+}
+
+/// 令牌桶限额: 容量及每秒补充的令牌数
+#[derive(Deserialize, Clone, Debug)]
+pub struct Limit {
+    pub capacity: u64,
+    pub refill_per_second: f64,
+}
+
+/// 客户端接口限流配置
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct RateLimit {
+    pub enabled: bool,
+    /// 每个用户的限额, 同一用户的所有 summary_key 共用; 套餐设置了限流时以套餐为准
+    pub key: Limit,
+    /// 每个 site_summary_key 的限额
+    pub site: Limit,
+    /// 每个客户端 IP 的限额
+    pub ip: Limit,
+    /// 可信的反向代理地址, 只有来自这些地址的请求才按 X-Forwarded-For / Forwarded 取客户端 IP
+    pub trusted_proxies: Vec<IpAddr>,
+    /// 按用户 id 单独配置的限额, 优先于套餐限额; toml 的键只能是字符串, 启动时校验为数字
+    pub overrides: HashMap<String, Limit>,
+}
+
+impl RateLimit {
+    /// 用户单独配置的限额
+    pub fn user_override(&self, user_id: u64) -> Option<&Limit> {
+        self.overrides.get(&user_id.to_string())
+    }
+
+    fn validate(&self) -> Result<()> {
+        for key in self.overrides.keys() {
+            if key.parse::<u64>().is_err() {
+                return Err(Error::ConfigError(format!(
+                    "rate_limit.overrides: 键须为用户 id, 实际为 \"{}\"",
+                    key
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            enabled: false,
+            key: Limit {
+                capacity: 60,
+                refill_per_second: 1.0,
+            },
+            site: Limit {
+                capacity: 60,
+                refill_per_second: 1.0,
+            },
+            ip: Limit {
+                capacity: 120,
+                refill_per_second: 2.0,
+            },
+            trusted_proxies: vec![],
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+/// 单个作用域的跨域策略
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CorsPolicy {
+    /// 允许的来源, `*` 表示任意来源
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub supports_credentials: bool,
+    pub max_age: usize,
+    /// 是否允许有效站点的域名作为来源, 只接受 https
+    pub allow_site_domains: bool,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        CorsPolicy {
+            allowed_origins: vec![],
+            allowed_methods: ["GET", "POST", "PUT", "DELETE"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            allowed_headers: ["content-type", "x-request-id", "x-summary-key", "x-site-summary-key"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            supports_credentials: false,
+            max_age: 3600,
+            allow_site_domains: false,
+        }
+    }
+}
+
+impl CorsPolicy {
+    /// 校验来源、方法和请求头, 避免构建 CORS 中间件时 panic
+    ///
+    /// 来源须为 `*` 或 `scheme://host[:port]`, 不带路径和结尾的 `/`
+    fn validate(&self, scope: &str) -> Result<()> {
+        for origin in &self.allowed_origins {
+            if origin == "*" {
+                if self.supports_credentials {
+                    return Err(Error::ConfigError(format!(
+                        "cors.{}: allowed_origins 为 * 时不能开启 supports_credentials",
+                        scope
+                    )));
+                }
+                continue;
+            }
+            if !is_valid_origin(origin) {
+                return Err(Error::ConfigError(format!(
+                    "cors.{}: 无效的 allowed_origins \"{}\", 须为 scheme://host[:port]",
+                    scope, origin
+                )));
+            }
+        }
+        for method in &self.allowed_methods {
+            if actix_web::http::Method::from_bytes(method.as_bytes()).is_err() {
+                return Err(Error::ConfigError(format!(
+                    "cors.{}: 无效的 allowed_methods \"{}\"",
+                    scope, method
+                )));
+            }
+        }
+        for header in &self.allowed_headers {
+            if actix_web::http::header::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return Err(Error::ConfigError(format!(
+                    "cors.{}: 无效的 allowed_headers \"{}\"",
+                    scope, header
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_valid_origin(origin: &str) -> bool {
+    let (scheme, host) = match origin.split_once("://") {
+        Some(x) => x,
+        None => return false,
+    };
+    let host_valid = !host.is_empty()
+        && !host.starts_with(':')
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'));
+    (scheme == "http" || scheme == "https") && host_valid
+}
+
+/// 跨域配置, /admin 与 /client 分别配置
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct CorsSetting {
+    pub admin: CorsPolicy,
+    pub client: CorsPolicy,
+    /// 站点域名的刷新间隔
+    pub site_domain_refresh_seconds: u64,
+}
+
+impl Default for CorsSetting {
+    fn default() -> Self {
+        CorsSetting {
+            admin: CorsPolicy::default(),
+            client: CorsPolicy::default(),
+            site_domain_refresh_seconds: 60,
+        }
+    }
+}
+
+/// key 及站点查询缓存
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Cache {
+    /// 每类缓存的最大条目数
+    pub capacity: usize,
+    /// 失效只通知当前实例, 其他实例的缓存最多滞后这么久
+    pub ttl_seconds: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            capacity: 10000,
+            ttl_seconds: 10,
+        }
+    }
+}
+
+/// webhook 通知, url 为空时不启用
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WebhookSetting {
+    pub url: String,
+    /// 签名密钥
+    pub secret: String,
+    pub max_retries: u32,
+    /// 首次重试间隔, 之后指数增长
+    pub backoff_millis: u64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for WebhookSetting {
+    fn default() -> Self {
+        WebhookSetting {
+            url: String::new(),
+            secret: String::new(),
+            max_retries: 3,
+            backoff_millis: 500,
+            timeout_seconds: 5,
+        }
+    }
+}
+
+/// SMTP 邮件通知, host 为空时不启用
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SmtpSetting {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    /// 发件人, 如 `summary-gpt <noreply@example.com>`
+    pub from: String,
+    /// 是否使用 TLS 连接
+    pub tls: bool,
+    pub max_retries: u32,
+    pub backoff_millis: u64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for SmtpSetting {
+    fn default() -> Self {
+        SmtpSetting {
+            host: String::new(),
+            port: 25,
+            username: String::new(),
+            password: String::new(),
+            from: String::new(),
+            tls: false,
+            max_retries: 2,
+            backoff_millis: 1000,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+/// 通知配置
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Notify {
+    /// 通知语言: zh 或 en
+    pub language: String,
+    pub webhook: WebhookSetting,
+    pub smtp: SmtpSetting,
+}
+
+/// 停机配置
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Shutdown {
+    /// 等待请求处理完及后台任务退出的最长时间
+    pub timeout_seconds: u64,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown { timeout_seconds: 30 }
+    }
+}
+
+/// 定时任务配置
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Scheduler {
+    pub enabled: bool,
+    /// 覆盖任务默认的 cron 表达式 (秒 分 时 日 月 周), 如 plan_allowance = "0 0 * * * *"
+    pub schedules: HashMap<String, String>,
+    /// 启动时暂停的任务
+    pub paused: Vec<String>,
+}
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            enabled: true,
+            schedules: HashMap::new(),
+            paused: vec![],
+        }
+    }
+}
+
+/// leader 选举配置, 关闭时每个实例都视为 leader
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Leader {
+    pub enabled: bool,
+    /// 租约有效期, leader 宕机后最多经过这么久由其他实例接管
+    pub lease_seconds: u64,
+    /// 续约间隔, 需小于 lease_seconds
+    pub renew_seconds: u64,
+}
+impl Default for Leader {
+    fn default() -> Self {
+        Leader {
+            enabled: true,
+            lease_seconds: 15,
+            renew_seconds: 5,
+        }
+    }
+}
+
+/// key 过期提醒配置
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct KeyExpiry {
+    /// 每日报告列出多少天内过期的 key
+    pub report_days: u32,
+}
+impl Default for KeyExpiry {
+    fn default() -> Self {
+        KeyExpiry { report_days: 7 }
+    }
+}
+
+/// 共享 OpenAI key 池配置
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OpenaiPool {
+    /// 可选 key 的刷新间隔, 健康状态和月度上限的变化最多延迟这么久生效
+    pub refresh_seconds: u64,
+}
+impl Default for OpenaiPool {
+    fn default() -> Self {
+        OpenaiPool { refresh_seconds: 30 }
+    }
+}
+
+/// 管理接口配置
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Admin {
+    /// 用户导入、备份恢复等上传接口的请求体上限, 单位 MB
+    pub max_upload_mb: usize,
+    /// 备份文件解压后的大小上限, 单位 MB, 防止压缩炸弹耗尽内存
+    pub max_backup_mb: usize,
+    /// 备份/恢复接口的访问令牌, 通过 x-admin-token 请求头传入, 为空时禁用这两个接口
+    ///
+    /// 备份中含明文密码、openai_key 及 key 池的 api_key, 不能像其他管理接口一样只靠 CORS 限制
+    pub backup_token: String,
+}
+impl Default for Admin {
+    fn default() -> Self {
+        Admin { max_upload_mb: 64, max_backup_mb: 512, backup_token: String::new() }
+    }
+}
+impl Admin {
+    pub fn max_upload_bytes(&self) -> usize {
+        self.max_upload_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn max_backup_bytes(&self) -> usize {
+        self.max_backup_mb.saturating_mul(1024 * 1024)
+    }
+}
+
 /// 系统配置信息
 #[derive(Deserialize, Default, Debug)]
 pub struct Setting {
     pub app: App,
     pub database: Database,
     pub log: Log,
+    #[serde(default)]
+    pub kafka: Kafka,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub cors: CorsSetting,
+    #[serde(default)]
+    pub shutdown: Shutdown,
+    #[serde(default)]
+    pub cache: Cache,
+    #[serde(default)]
+    pub notify: Notify,
+    #[serde(default)]
+    pub scheduler: Scheduler,
+    #[serde(default)]
+    pub leader: Leader,
+    #[serde(default)]
+    pub key_expiry: KeyExpiry,
+    #[serde(default)]
+    pub openai_pool: OpenaiPool,
+    #[serde(default)]
+    pub admin: Admin,
 }
 
 
@@ -77,6 +484,15 @@ lazy_static! {
     pub static ref SETTING: Setting = get_setting_from_toml!(Setting);
 }
 
+/// 校验配置, 启动时调用, 配置有误时返回说明具体字段的错误
+pub fn validate() -> Result<()> {
+    let cors = &SETTING.cors;
+    cors.admin.validate("admin")?;
+    cors.client.validate("client")?;
+    SETTING.rate_limit.validate()?;
+    Ok(())
+}
+
 /// 得到数据库连接字符串
 pub fn get_conn_string() -> String {
     let setting = &*SETTING;
@@ -117,11 +533,12 @@ pub fn log_init() {
     Dispatch::new()
         .format(move |out, message, record| {
             out.finish(format_args!(
-                "{} {} [{}:{}] {}",
+                "{} {} [{}:{}] [{}] {}",
                 Local::now().format("%Y-%m-%d %H:%M:%S"),
                 record.level(),
                 record.module_path().unwrap_or("<unnamed>"),
                 record.line().unwrap_or(0),
+                current_request_id().unwrap_or_else(|| "-".to_string()),
                 message
             ))
         })
@@ -144,6 +561,45 @@ mod config_tests {
         println!("{:?}", setting);
     }
 
+    #[test]
+    fn test_rate_limit_overrides_by_user_id() {
+        let mut config = RateLimit::default();
+        let limit = Limit {
+            capacity: 600,
+            refill_per_second: 10.0,
+        };
+        config.overrides.insert("42".to_string(), limit.clone());
+        assert!(config.validate().is_ok());
+        assert_eq!(config.user_override(42).map(|l| l.capacity), Some(600));
+        assert!(config.user_override(43).is_none());
+
+        config.overrides.insert("summary_key".to_string(), limit);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_cors_policy_validate() {
+        let mut policy = CorsPolicy {
+            allowed_origins: vec!["https://a.example.com".to_string(), "http://localhost:3000".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.validate("admin").is_ok());
+
+        policy.allowed_origins = vec!["https://a.example.com/".to_string()];
+        assert!(policy.validate("admin").is_err());
+        policy.allowed_origins = vec!["a.example.com".to_string()];
+        assert!(policy.validate("admin").is_err());
+
+        policy.allowed_origins = vec!["*".to_string()];
+        assert!(policy.validate("admin").is_ok());
+        policy.supports_credentials = true;
+        assert!(policy.validate("admin").is_err());
+
+        policy.supports_credentials = false;
+        policy.allowed_methods = vec!["GE T".to_string()];
+        assert!(policy.validate("admin").is_err());
+    }
+
     #[tokio::test]
     async fn test_get_conn_string() {
         println!("{:?}", get_conn_string());
@@ -0,0 +1,106 @@
+//! 优雅停机: 监听 SIGTERM / Ctrl-C, 通知后台任务退出并在超时后取消
+use std::{future::Future, sync::Mutex};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+
+lazy_static! {
+    static ref SHUTDOWN: watch::Sender<bool> = watch::channel(false).0;
+    /// 需要在停机时等待的后台任务
+    static ref TASKS: Mutex<Vec<(&'static str, JoinHandle<()>)>> = Mutex::new(vec![]);
+}
+
+/// 等待停机信号
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => info!("received SIGTERM"),
+                    _ = tokio::signal::ctrl_c() => info!("received SIGINT"),
+                }
+                return;
+            }
+            Err(e) => warn!("install SIGTERM handler error: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    info!("received SIGINT");
+}
+
+/// 通知所有后台任务开始停机
+pub fn trigger() {
+    SHUTDOWN.send_replace(true);
+}
+
+/// 是否已开始停机
+pub fn is_triggered() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// 等待停机通知, 后台任务在 select 中使用
+pub async fn wait() {
+    let mut rx = SHUTDOWN.subscribe();
+    while !*rx.borrow() {
+        if rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// 启动后台任务, 停机时等待其退出
+pub fn spawn<F>(name: &'static str, fut: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    let handle = actix_web::rt::spawn(fut);
+    TASKS.lock().unwrap().push((name, handle));
+}
+
+/// 等待后台任务退出, 到 deadline 仍未退出的任务被取消
+///
+/// deadline 与 HTTP 排空共用, 在收到停机信号时确定, 整个停机过程不超过配置的超时时间
+pub async fn join_tasks(deadline: Instant) {
+    let tasks = std::mem::take(&mut *TASKS.lock().unwrap());
+    join(tasks, deadline).await;
+}
+
+/// 等待任务退出, 返回超时被取消的任务数
+async fn join(tasks: Vec<(&'static str, JoinHandle<()>)>, deadline: Instant) -> usize {
+    let mut cancelled = 0;
+    for (name, mut handle) in tasks {
+        match tokio::time::timeout_at(deadline, &mut handle).await {
+            Ok(_) => info!("background task {} stopped", name),
+            Err(_) => {
+                handle.abort();
+                cancelled += 1;
+                warn!("background task {} cancelled after timeout", name);
+            }
+        }
+    }
+    cancelled
+}
+
+#[cfg(test)]
+mod shutdown_tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // 不触发全局停机通知, 以免影响同进程中其他测试的后台任务
+    #[actix_web::test]
+    async fn test_join_cancels_after_deadline() {
+        let (tx, mut rx) = watch::channel(false);
+        let waiting = actix_web::rt::spawn(async move {
+            let _ = rx.changed().await;
+        });
+        let stuck = actix_web::rt::spawn(std::future::pending());
+        tx.send_replace(true);
+        let tasks = vec![("waiting", waiting), ("stuck", stuck)];
+        let cancelled = join(tasks, Instant::now() + Duration::from_millis(100)).await;
+        assert_eq!(cancelled, 1);
+    }
+}
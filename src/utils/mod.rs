@@ -1,3 +1,6 @@
 // pub mod entity;
 pub mod uuid;
-// pub mod date_utils;
\ No newline at end of file
+pub mod validate;
+pub mod page;
+pub mod date_utils;
+pub mod ttl_cache;
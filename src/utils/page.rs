@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use rbs::Value;
+use serde::de::DeserializeOwned;
+use serde_derive::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db,
+    error::{Error, Result},
+};
+
+const DEFAULT_SIZE: u64 = 20;
+const MAX_SIZE: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// 列表接口公共查询参数
+///
+/// 从 query string 解析: `page`、`size`、`cursor`、`sort`、`order`,
+/// 其余参数均视为等值过滤条件, 由各实体的 [`PageSpec`] 决定是否允许。
+/// 传入 `cursor` 时按 id 游标翻页, 忽略 `page`。
+#[derive(Debug, Clone)]
+pub struct PageQuery {
+    pub page: u64,
+    pub size: u64,
+    pub cursor: Option<u64>,
+    pub sort: Option<String>,
+    pub order: SortOrder,
+    pub filters: HashMap<String, String>,
+}
+
+impl Default for PageQuery {
+    fn default() -> Self {
+        PageQuery {
+            page: 1,
+            size: DEFAULT_SIZE,
+            cursor: None,
+            sort: None,
+            order: SortOrder::Desc,
+            filters: HashMap::new(),
+        }
+    }
+}
+
+/// 实体允许的排序字段和过滤字段
+pub struct PageSpec {
+    pub table: &'static str,
+    pub sorts: &'static [&'static str],
+    pub filters: &'static [&'static str],
+}
+
+/// 分页元数据, 放在响应信封中
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PageMeta {
+    pub total: u64,
+    pub page: u64,
+    pub size: u64,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub records: Vec<T>,
+    pub meta: PageMeta,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            records: self.records.into_iter().map(f).collect(),
+            meta: self.meta,
+        }
+    }
+}
+
+impl PageQuery {
+    pub fn from_query(params: &HashMap<String, String>) -> Result<PageQuery> {
+        let mut query = PageQuery::default();
+        for (key, value) in params {
+            match key.as_str() {
+                "page" => query.page = value.parse::<u64>()?.max(1),
+                "size" => query.size = value.parse::<u64>()?.clamp(1, MAX_SIZE),
+                "cursor" => query.cursor = Some(value.parse::<u64>()?),
+                "sort" => query.sort = Some(value.clone()),
+                "order" => {
+                    query.order = match value.as_str() {
+                        "asc" => SortOrder::Asc,
+                        "desc" => SortOrder::Desc,
+                        _ => return Err(Error::invalid_param("order 只能为 asc 或 desc")),
+                    }
+                }
+                _ => {
+                    query.filters.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(query)
+    }
+
+    fn sort_field(&self, spec: &PageSpec) -> Result<&str> {
+        let sort = self.sort.as_deref().unwrap_or("id");
+        if !spec.sorts.contains(&sort) {
+            return Err(Error::invalid_param(format!("不支持的排序字段: {}", sort)));
+        }
+        if self.cursor.is_some() && sort != "id" {
+            return Err(Error::invalid_param("游标翻页只支持按 id 排序"));
+        }
+        Ok(sort)
+    }
+
+    /// 生成 (count sql, count 参数, 列表 sql, 列表参数)
+    ///
+    /// `fixed` 为调用方附加的固定等值条件, 如 `user_id`。
+    fn to_sql(
+        &self,
+        spec: &PageSpec,
+        fixed: Vec<(&str, Value)>,
+    ) -> Result<(String, Vec<Value>, String, Vec<Value>)> {
+        let mut conditions = vec![];
+        let mut args = vec![];
+        for (field, value) in fixed {
+            conditions.push(format!("{} = ?", field));
+            args.push(value);
+        }
+        let mut filters: Vec<(&String, &String)> = self.filters.iter().collect();
+        filters.sort();
+        for (field, value) in filters {
+            if !spec.filters.contains(&field.as_str()) {
+                return Err(Error::invalid_param(format!("不支持的过滤字段: {}", field)));
+            }
+            conditions.push(format!("{} = ?", field));
+            args.push(Value::String(value.clone()));
+        }
+
+        let count_sql = format!("select count(1) from {}{}", spec.table, where_sql(&conditions));
+        let count_args = args.clone();
+
+        let sort = self.sort_field(spec)?;
+        let mut list_sql = String::new();
+        if let Some(cursor) = self.cursor {
+            let op = if self.order == SortOrder::Asc { ">" } else { "<" };
+            conditions.push(format!("id {} ?", op));
+            args.push(Value::U64(cursor));
+            list_sql.push_str(&format!(
+                "select * from {}{} order by id {} limit ?",
+                spec.table,
+                where_sql(&conditions),
+                self.order.as_sql()
+            ));
+            args.push(Value::U64(self.size));
+        } else {
+            let order = self.order.as_sql();
+            let order_by = if sort == "id" {
+                format!("id {}", order)
+            } else {
+                format!("{} {}, id {}", sort, order, order)
+            };
+            list_sql.push_str(&format!(
+                "select * from {}{} order by {} limit ? offset ?",
+                spec.table,
+                where_sql(&conditions),
+                order_by
+            ));
+            args.push(Value::U64(self.size));
+            // page 来自查询参数, 过大时不能溢出
+            args.push(Value::U64((self.page - 1).saturating_mul(self.size)));
+        }
+        Ok((count_sql, count_args, list_sql, args))
+    }
+
+    /// 按查询参数分页查询
+    ///
+    /// `id_of` 用于从最后一条记录取得下一页游标。
+    pub async fn fetch<T: DeserializeOwned>(
+        &self,
+        spec: &PageSpec,
+        fixed: Vec<(&str, Value)>,
+        id_of: fn(&T) -> Option<u64>,
+    ) -> Result<Page<T>> {
+        let (count_sql, count_args, list_sql, list_args) = self.to_sql(spec, fixed)?;
+        let rb = db::get_rb();
+        let total: u64 = rb.query_decode(&count_sql, count_args).await?;
+        let records: Vec<T> = rb.query_decode(&list_sql, list_args).await?;
+
+        let next_cursor = if records.len() as u64 == self.size && self.sort_field(spec)? == "id" {
+            records.last().and_then(id_of).map(|id| id.to_string())
+        } else {
+            None
+        };
+        Ok(Page {
+            records,
+            meta: PageMeta {
+                total,
+                page: if self.cursor.is_some() { 0 } else { self.page },
+                size: self.size,
+                next_cursor,
+            },
+        })
+    }
+}
+
+fn where_sql(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" where {}", conditions.join(" and "))
+    }
+}
+
+#[cfg(test)]
+mod page_tests {
+    use super::*;
+
+    const SPEC: PageSpec = PageSpec {
+        table: "user",
+        sorts: &["id", "created_time"],
+        filters: &["active"],
+    };
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_query() {
+        let query = PageQuery::from_query(&params(&[
+            ("page", "2"),
+            ("size", "500"),
+            ("sort", "created_time"),
+            ("order", "asc"),
+            ("active", "1"),
+        ]))
+        .unwrap();
+        assert_eq!(query.page, 2);
+        assert_eq!(query.size, MAX_SIZE);
+        assert_eq!(query.order, SortOrder::Asc);
+        assert_eq!(query.filters.get("active"), Some(&"1".to_string()));
+
+        assert!(PageQuery::from_query(&params(&[("order", "up")])).is_err());
+        assert!(PageQuery::from_query(&params(&[("page", "x")])).is_err());
+    }
+
+    #[test]
+    fn test_to_sql() {
+        let query = PageQuery::from_query(&params(&[("page", "3"), ("size", "10"), ("active", "1")])).unwrap();
+        let (count_sql, count_args, list_sql, list_args) = query.to_sql(&SPEC, vec![]).unwrap();
+        assert_eq!(count_sql, "select count(1) from user where active = ?");
+        assert_eq!(count_args.len(), 1);
+        assert_eq!(
+            list_sql,
+            "select * from user where active = ? order by id desc limit ? offset ?"
+        );
+        assert_eq!(list_args.last(), Some(&Value::U64(20)));
+
+        let query = PageQuery::from_query(&params(&[("page", "18446744073709551615"), ("size", "100")])).unwrap();
+        let (_, _, _, list_args) = query.to_sql(&SPEC, vec![]).unwrap();
+        assert_eq!(list_args.last(), Some(&Value::U64(u64::MAX)));
+
+        let query = PageQuery::from_query(&params(&[("cursor", "42")])).unwrap();
+        let (_, _, list_sql, _) = query.to_sql(&SPEC, vec![("user_id", Value::U64(1))]).unwrap();
+        assert_eq!(
+            list_sql,
+            "select * from user where user_id = ? and id < ? order by id desc limit ?"
+        );
+
+        let query = PageQuery::from_query(&params(&[("password", "x")])).unwrap();
+        assert!(query.to_sql(&SPEC, vec![]).is_err());
+        let query = PageQuery::from_query(&params(&[("sort", "password")])).unwrap();
+        assert!(query.to_sql(&SPEC, vec![]).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// 有容量上限的 TTL 缓存
+///
+/// 满时先清理过期条目, 仍然满则淘汰最早过期的条目。
+/// 条目另按过期时间排序索引, 插入和淘汰都是 O(log n), 不必在锁内扫描全部条目。
+/// 不加锁, 由调用方包在 `Mutex` 中使用。
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<K, Entry<V>>,
+    /// (过期时间, 插入序号) -> key, 序号区分过期时间相同的条目
+    expiries: BTreeMap<(Instant, u64), K>,
+    seq: u64,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    seq: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(capacity: usize, ttl: Duration) -> TtlCache<K, V> {
+        TtlCache {
+            capacity: capacity.max(1),
+            ttl,
+            entries: HashMap::new(),
+            expiries: BTreeMap::new(),
+            seq: 0,
+        }
+    }
+
+    /// 取出未过期的值, 过期的条目会被删除
+    pub fn get(&mut self, key: &K, now: Instant) -> Option<V> {
+        match self.entries.get(key) {
+            Some(entry) if entry.expires_at > now => Some(entry.value.clone()),
+            Some(_) => {
+                self.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V, now: Instant) {
+        if let Some(old) = self.entries.remove(&key) {
+            self.expiries.remove(&(old.expires_at, old.seq));
+        } else {
+            // 先清理已过期的条目, 仍然满时淘汰最早过期的一条
+            loop {
+                match self.expiries.keys().next().copied() {
+                    Some((expires_at, _)) if expires_at <= now || self.entries.len() >= self.capacity => {
+                        self.pop_first()
+                    }
+                    _ => break,
+                }
+            }
+        }
+        self.seq += 1;
+        let expires_at = now + self.ttl;
+        self.expiries.insert((expires_at, self.seq), key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at,
+                seq: self.seq,
+            },
+        );
+    }
+
+    fn pop_first(&mut self) {
+        let first = self.expiries.keys().next().copied();
+        if let Some(first) = first {
+            if let Some(key) = self.expiries.remove(&first) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.expiries.remove(&(entry.expires_at, entry.seq));
+        }
+    }
+
+    /// 只保留满足条件的条目
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        let entries = &mut self.entries;
+        self.expiries.retain(|_, key| {
+            let keep = entries.get(key).map_or(false, |entry| f(key, &entry.value));
+            if !keep {
+                entries.remove(key);
+            }
+            keep
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod ttl_cache_tests {
+    use super::*;
+
+    #[test]
+    fn test_expire() {
+        let now = Instant::now();
+        let mut cache = TtlCache::new(10, Duration::from_secs(60));
+        cache.insert("a", 1, now);
+        assert_eq!(cache.get(&"a", now + Duration::from_secs(59)), Some(1));
+        assert_eq!(cache.get(&"a", now + Duration::from_secs(60)), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let now = Instant::now();
+        let mut cache = TtlCache::new(2, Duration::from_secs(60));
+        cache.insert("a", 1, now);
+        cache.insert("b", 2, now + Duration::from_secs(1));
+        cache.insert("c", 3, now + Duration::from_secs(2));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"a", now), None);
+        assert_eq!(cache.get(&"c", now), Some(3));
+    }
+
+    #[test]
+    fn test_capacity_prefers_expired() {
+        let now = Instant::now();
+        let mut cache = TtlCache::new(2, Duration::from_secs(60));
+        cache.insert("a", 1, now);
+        cache.insert("b", 2, now + Duration::from_secs(30));
+        // 重新插入已有的 key 不淘汰其他条目, 并刷新过期时间
+        cache.insert("a", 3, now + Duration::from_secs(40));
+        assert_eq!(cache.len(), 2);
+
+        // b 已过期, 淘汰 b 而不是 a
+        let later = now + Duration::from_secs(95);
+        cache.insert("c", 4, later);
+        assert_eq!(cache.get(&"b", now), None);
+        assert_eq!(cache.get(&"a", later), Some(3));
+        assert_eq!(cache.get(&"c", later), Some(4));
+    }
+
+    #[test]
+    fn test_retain() {
+        let now = Instant::now();
+        let mut cache = TtlCache::new(10, Duration::from_secs(60));
+        cache.insert("a", 1, now);
+        cache.insert("b", 2, now);
+        cache.retain(|_, v| *v != 1);
+        assert_eq!(cache.get(&"a", now), None);
+        assert_eq!(cache.get(&"b", now), Some(2));
+    }
+}
@@ -2,6 +2,11 @@ pub fn new_summary_key() -> String {
     nanoid::nanoid!(8)
 }
 
+/// 生成请求 id
+pub fn new_request_id() -> String {
+    nanoid::nanoid!(16)
+}
+
 // #test
 #[cfg(test)]
 mod uuid_tests {
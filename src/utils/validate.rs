@@ -0,0 +1,181 @@
+use serde_derive::Serialize;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::error::{Error, Result};
+
+/// 字段校验错误, 随 InvalidParam 返回给客户端
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+/// 校验请求模型, 失败时返回带字段错误列表的 `Error::InvalidParam`
+pub fn validate<T: Validate>(model: &T) -> Result<()> {
+    model.validate().map_err(into_error)
+}
+
+fn into_error(errors: ValidationErrors) -> Error {
+    let mut fields: Vec<FieldError> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errs)| {
+            errs.iter().map(move |e| FieldError {
+                field: field.to_string(),
+                code: e.code.to_string(),
+                message: e
+                    .message
+                    .as_ref()
+                    .map(|m| m.to_string())
+                    .unwrap_or_else(|| e.code.to_string()),
+            })
+        })
+        .collect();
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+    let message = fields
+        .iter()
+        .map(|f| format!("{}: {}", f.field, f.message))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Error::InvalidParam(message, fields)
+}
+
+/// 单个字段的参数错误, 用于需要查库才能判断的校验
+pub fn field_error(field: &str, code: &str, message: &str) -> Error {
+    Error::InvalidParam(
+        format!("{}: {}", field, message),
+        vec![FieldError {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: message.to_string(),
+        }],
+    )
+}
+
+/// 账号只允许字母、数字及 `_` `-` `.` `@`
+pub fn validate_account(account: &str) -> std::result::Result<(), ValidationError> {
+    if account
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-.@".contains(c))
+    {
+        Ok(())
+    } else {
+        Err(error("charset", "账号只能包含字母、数字及 _ - . @"))
+    }
+}
+
+/// key 只允许字母、数字及 `_` `-`
+pub fn validate_key(key: &str) -> std::result::Result<(), ValidationError> {
+    if key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(error("charset", "key 只能包含字母、数字及 _ -"))
+    }
+}
+
+/// 域名格式: 至少两级, 每级 1-63 位字母数字或 `-`, 不以 `-` 开头结尾, 顶级域为字母
+pub fn validate_domain(domain: &str) -> std::result::Result<(), ValidationError> {
+    let labels: Vec<&str> = domain.split('.').collect();
+    let valid_label = |label: &&str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let valid_tld = labels
+        .last()
+        .map_or(false, |tld| tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()));
+
+    if domain.len() <= 253 && labels.len() >= 2 && labels.iter().all(valid_label) && valid_tld {
+        Ok(())
+    } else {
+        Err(error("domain", "域名格式不正确"))
+    }
+}
+
+/// 时间格式: yyyy-MM-dd HH:mm:ss
+pub fn validate_datetime(s: &str) -> std::result::Result<(), ValidationError> {
+    match chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        Ok(_) => Ok(()),
+        Err(_) => Err(error("datetime", "时间格式须为 yyyy-MM-dd HH:mm:ss")),
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut err = ValidationError::new(code);
+    err.message = Some(message.into());
+    err
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::client::model::user_model::AddUser;
+
+    #[test]
+    fn test_validate_domain() {
+        assert!(validate_domain("www.baidu.com").is_ok());
+        assert!(validate_domain("a-b.example.cn").is_ok());
+        assert!(validate_domain("localhost").is_err());
+        assert!(validate_domain("-a.com").is_err());
+        assert!(validate_domain("a..com").is_err());
+        assert!(validate_domain("a.c0m").is_err());
+        assert!(validate_domain("http://a.com").is_err());
+    }
+
+    #[test]
+    fn test_field_error() {
+        match field_error("effective_from", "past", "不能早于当前时间") {
+            Error::InvalidParam(message, fields) => {
+                assert_eq!(message, "effective_from: 不能早于当前时间");
+                assert_eq!(fields[0].field, "effective_from");
+                assert_eq!(fields[0].code, "past");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_validate_account() {
+        assert!(validate_account("test_add@x.com").is_ok());
+        assert!(validate_account("测试").is_err());
+        assert!(validate_account("a b").is_err());
+    }
+
+    #[test]
+    fn test_validate_add_user() {
+        let mut add_user = AddUser::new();
+        add_user.account = Some("ab".to_string());
+        add_user.password = Some("12".to_string());
+        match validate(&add_user) {
+            Err(Error::InvalidParam(_, fields)) => {
+                let names: Vec<&str> = fields.iter().map(|f| f.field.as_str()).collect();
+                assert_eq!(names, vec!["account", "password"]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        add_user.account = Some("test_add".to_string());
+        add_user.password = Some("1234567".to_string());
+        assert!(validate(&add_user).is_ok());
+    }
+
+    #[test]
+    fn test_validate_datetime() {
+        assert!(validate_datetime("2024-02-29 23:59:59").is_ok());
+        assert!(validate_datetime("2023-02-29 00:00:00").is_err());
+        assert!(validate_datetime("2024-01-01").is_err());
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("aB3_-x").is_ok());
+        assert!(validate_key("a/b").is_err());
+    }
+}